@@ -1,14 +1,14 @@
 use clap::{Args, FromArgMatches, Parser, Subcommand};
 use clap_builder::builder::via_prelude::_ValueParserViaParse;
-use std::{env::current_dir, fmt::Debug, io::stdout};
-use ys_core::{
-    initialize::{DotYuanShenClient, YuanShenClient},
-    IgnoreRules, ObjectID, ObjectStore, SnapShot, SnapShotDirectory, YsError,
-};
+use std::{fmt::Debug, path::PathBuf, process::ExitCode};
+use ys_core::YsError;
 use yuan_shen::*;
 
 #[derive(Parser, Debug)]
 struct YuanShen {
+    /// 仓库的工作目录或 `.ys` 文件夹, 优先于环境变量 `YS_DIR`, 都不给出时从当前目录向上查找
+    #[arg(long, global = true)]
+    repo: Option<PathBuf>,
     #[clap(subcommand)]
     cmd: YsCommand,
 }
@@ -19,7 +19,12 @@ enum YsCommand {
     Initialize(YuanShenInitialize),
     #[command(alias = "diff")]
     Difference(YuanShenDifference),
-    Changes,
+    #[command(alias = "changes")]
+    Status(YuanShenStatus),
+    Add(YuanShenAdd),
+    #[command(alias = "rm")]
+    Remove(YuanShenRemove),
+    Reset(YuanShenReset),
     Commit(YuanShenCommit),
     Squash(YuanShenSquash),
     Merge(YuanShenMerge),
@@ -28,6 +33,14 @@ enum YsCommand {
     Orphan(YuanShenOrphan),
     Checkout(YuanShenCheckout),
     Branch(YuanShenBranch),
+    Tag(YuanShenTag),
+    Reflog(YuanShenReflog),
+    Undo(YuanShenUndo),
+    Redo(YuanShenRedo),
+    #[command(name = "op", alias = "operation")]
+    Operation(YuanShenOperation),
+    CheckIgnore(YuanShenCheckIgnore),
+    Config(YuanShenConfig),
     Stash(YuanShenCommit),
     #[command(alias = "gc")]
     GarbageCollect,
@@ -36,29 +49,29 @@ enum YsCommand {
 }
 
 #[tokio::main]
-pub async fn main() -> Result<(), YsError> {
+pub async fn main() -> Result<ExitCode, YsError> {
     let args = YuanShen::parse();
+    let repo = args.repo.as_deref();
     use YsCommand::*;
     match args.cmd {
-        Initialize(init) => init.initialize().await?,
-        Difference(diff) => diff.difference().await?,
-        Branch(b) => b.branch().await?,
-        Checkout(c) => c.checkout().await?,
-        Changes => {
-            let dir = current_dir()?;
-            let dot_rev = DotYuanShenClient::open(&dir).unwrap();
-            let mut store = dot_rev.store().unwrap();
-            let branch: String = dot_rev.get_branch_name().unwrap();
-            let old_tip: ObjectID = dot_rev.get_branch_id(&branch).unwrap();
-            let ignores: IgnoreRules = dot_rev.ignores().unwrap();
-            let directory = SnapShotDirectory::new(dir.as_path(), &ignores, &mut store).unwrap();
-            let snapshot: SnapShot = store.get_typed(old_tip).await.unwrap();
-            let old_directory: SnapShotDirectory = store.get_typed(snapshot.directory).await.unwrap();
-            serde_json::to_writer_pretty(stdout(), &old_directory.difference(&directory)).unwrap();
-        }
-        Commit(sub) => sub.commit().await.unwrap(),
+        Initialize(init) => init.initialize(repo).await?,
+        Difference(diff) => diff.difference(repo).await?,
+        Branch(b) => b.branch(repo).await?,
+        Tag(tag) => tag.tag(repo).await?,
+        Reflog(reflog) => reflog.reflog(repo).await?,
+        Undo(undo) => undo.undo(repo).await?,
+        Redo(redo) => redo.redo(repo).await?,
+        Operation(operation) => operation.operation(repo).await?,
+        Checkout(c) => c.checkout(repo).await?,
+        Status(status) => return Ok(status.status(repo).await),
+        CheckIgnore(check) => return Ok(check.check_ignore(repo)),
+        Config(config) => config.config(repo).await?,
+        Add(add) => add.add(repo).await?,
+        Remove(remove) => remove.remove(repo).await?,
+        Reset(reset) => reset.reset(repo).await?,
+        Commit(sub) => sub.commit(repo).await?,
         Squash(_) => {}
-        Merge(_) => {}
+        Merge(merge) => merge.merge(repo).await?,
         Rebase(_) => {}
         Reverse(_) => {}
         Orphan(_) => {}
@@ -66,5 +79,5 @@ pub async fn main() -> Result<(), YsError> {
         External(_) => {}
         GarbageCollect => {}
     }
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
@@ -3,15 +3,15 @@ use clap_builder::{
     builder::{_AutoValueParser, via_prelude::_ValueParserViaParse},
     Command,
 };
-use std::{env::current_dir, fmt::Debug, io::stdout};
-use ys_core::{
-    initialize::{DotYuanShenClient, YuanShenClient},
-    IgnoreRules, ObjectID, ObjectStore, SnapShot, SnapShotDirectory, YsError,
-};
+use std::{fmt::Debug, path::PathBuf, process::ExitCode};
+use ys_core::YsError;
 use yuan_shen::*;
 
 #[derive(Parser, Debug)]
 struct YuanShen {
+    /// 仓库的工作目录或 `.ys` 文件夹, 优先于环境变量 `YS_DIR`, 都不给出时从当前目录向上查找
+    #[arg(long, global = true)]
+    repo: Option<PathBuf>,
     #[clap(subcommand)]
     cmd: YsCommand,
 }
@@ -22,8 +22,14 @@ enum YsCommand {
     Initialize(YuanShenInitialize),
     /// 观测目标世界线与当前世界的差异
     Difference(YuanShenDifference),
-
-    Changes,
+    /// 观测当前世界的异变
+    Status(YuanShenStatus),
+    /// 将文件加入暂存区
+    Add(YuanShenAdd),
+    /// 将文件从暂存区和工作目录中移除
+    Remove(YuanShenRemove),
+    /// 取消暂存
+    Reset(YuanShenReset),
 
     Commit(YuanShenCommit),
     /// 将观测结果合并到当前世界线
@@ -36,9 +42,24 @@ enum YsCommand {
     Reverse(YuanShenCommit),
     /// 从某一条世界线开始开启一个新世界
     Orphan(YuanShenOrphan),
-    /// 切换到指定名称的世界线
+    /// 切换到指定的世界线, 或者分离到指定的快照
     Checkout(YuanShenCheckout),
+    /// 列出, 创建, 重命名或删除世界线
     Branch(YuanShenBranch),
+    /// 为快照设置不会移动的标签
+    Tag(YuanShenTag),
+    /// 查看世界线和标签的移动历史
+    Reflog(YuanShenReflog),
+    /// 撤销最近一次操作
+    Undo(YuanShenUndo),
+    /// 重做最近一次被撤销的操作
+    Redo(YuanShenRedo),
+    /// 查看操作日志, 或者恢复到任意操作之后的状态
+    Operation(YuanShenOperation),
+    /// 检查路径是否被忽略
+    CheckIgnore(YuanShenCheckIgnore),
+    /// 读取或修改配置
+    Config(YuanShenConfig),
     Stash(YuanShenCommit),
     /// 对象有点太城市化了
     GarbageCollect,
@@ -62,10 +83,20 @@ impl FromArgMatches for YsCommand {
                     "rebase" | "干涉" => Self::Rebase(FromArgMatches::from_arg_matches_mut(sub_args)?),
                     "reverse" | "回溯" => Self::Reverse(FromArgMatches::from_arg_matches_mut(sub_args)?),
                     "checkout" | "跃迁" => Self::Checkout(FromArgMatches::from_arg_matches_mut(sub_args)?),
+                    "check-ignore" => Self::CheckIgnore(FromArgMatches::from_arg_matches_mut(sub_args)?),
+                    "config" | "设定" => Self::Config(FromArgMatches::from_arg_matches_mut(sub_args)?),
                     "branch" => Self::Branch(FromArgMatches::from_arg_matches_mut(sub_args)?),
+                    "tag" | "锚定" => Self::Tag(FromArgMatches::from_arg_matches_mut(sub_args)?),
+                    "reflog" => Self::Reflog(FromArgMatches::from_arg_matches_mut(sub_args)?),
+                    "undo" => Self::Undo(FromArgMatches::from_arg_matches_mut(sub_args)?),
+                    "redo" => Self::Redo(FromArgMatches::from_arg_matches_mut(sub_args)?),
+                    "op" | "operation" => Self::Operation(FromArgMatches::from_arg_matches_mut(sub_args)?),
                     "stash" => Self::Stash(FromArgMatches::from_arg_matches_mut(sub_args)?),
+                    "add" | "暂存" => Self::Add(FromArgMatches::from_arg_matches_mut(sub_args)?),
+                    "remove" | "rm" | "移除" => Self::Remove(FromArgMatches::from_arg_matches_mut(sub_args)?),
+                    "reset" | "重置" => Self::Reset(FromArgMatches::from_arg_matches_mut(sub_args)?),
                     "commit" => Self::Commit(FromArgMatches::from_arg_matches_mut(sub_args)?),
-                    "changes" => Self::Changes,
+                    "status" | "changes" | "异变" => Self::Status(FromArgMatches::from_arg_matches_mut(sub_args)?),
                     "garbage-collect" => Self::GarbageCollect,
                     _ => Self::External(
                         std::iter::once(String::from(cmd))
@@ -97,10 +128,25 @@ impl FromArgMatches for YsCommand {
                     let __clap_arg_matches = &mut __clap_arg_sub_matches;
                     FromArgMatches::update_from_arg_matches_mut(__clap_arg, __clap_arg_matches)?
                 }
-                Self::Changes if "changes" == clap => {
+                Self::Status(ref mut __clap_arg) if "status" == clap => {
                     let (_, mut __clap_arg_sub_matches) = args.remove_subcommand().unwrap();
                     let __clap_arg_matches = &mut __clap_arg_sub_matches;
-                    {}
+                    FromArgMatches::update_from_arg_matches_mut(__clap_arg, __clap_arg_matches)?
+                }
+                Self::Add(ref mut __clap_arg) if "add" == clap => {
+                    let (_, mut __clap_arg_sub_matches) = args.remove_subcommand().unwrap();
+                    let __clap_arg_matches = &mut __clap_arg_sub_matches;
+                    FromArgMatches::update_from_arg_matches_mut(__clap_arg, __clap_arg_matches)?
+                }
+                Self::Remove(ref mut __clap_arg) if "remove" == clap => {
+                    let (_, mut __clap_arg_sub_matches) = args.remove_subcommand().unwrap();
+                    let __clap_arg_matches = &mut __clap_arg_sub_matches;
+                    FromArgMatches::update_from_arg_matches_mut(__clap_arg, __clap_arg_matches)?
+                }
+                Self::Reset(ref mut __clap_arg) if "reset" == clap => {
+                    let (_, mut __clap_arg_sub_matches) = args.remove_subcommand().unwrap();
+                    let __clap_arg_matches = &mut __clap_arg_sub_matches;
+                    FromArgMatches::update_from_arg_matches_mut(__clap_arg, __clap_arg_matches)?
                 }
                 Self::Commit(ref mut __clap_arg) if "commit" == clap => {
                     let (_, mut __clap_arg_sub_matches) = args.remove_subcommand().unwrap();
@@ -142,6 +188,41 @@ impl FromArgMatches for YsCommand {
                     let __clap_arg_matches = &mut __clap_arg_sub_matches;
                     FromArgMatches::update_from_arg_matches_mut(__clap_arg, __clap_arg_matches)?
                 }
+                Self::Tag(ref mut __clap_arg) if "tag" == clap => {
+                    let (_, mut __clap_arg_sub_matches) = args.remove_subcommand().unwrap();
+                    let __clap_arg_matches = &mut __clap_arg_sub_matches;
+                    FromArgMatches::update_from_arg_matches_mut(__clap_arg, __clap_arg_matches)?
+                }
+                Self::Reflog(ref mut __clap_arg) if "reflog" == clap => {
+                    let (_, mut __clap_arg_sub_matches) = args.remove_subcommand().unwrap();
+                    let __clap_arg_matches = &mut __clap_arg_sub_matches;
+                    FromArgMatches::update_from_arg_matches_mut(__clap_arg, __clap_arg_matches)?
+                }
+                Self::Undo(ref mut __clap_arg) if "undo" == clap => {
+                    let (_, mut __clap_arg_sub_matches) = args.remove_subcommand().unwrap();
+                    let __clap_arg_matches = &mut __clap_arg_sub_matches;
+                    FromArgMatches::update_from_arg_matches_mut(__clap_arg, __clap_arg_matches)?
+                }
+                Self::Redo(ref mut __clap_arg) if "redo" == clap => {
+                    let (_, mut __clap_arg_sub_matches) = args.remove_subcommand().unwrap();
+                    let __clap_arg_matches = &mut __clap_arg_sub_matches;
+                    FromArgMatches::update_from_arg_matches_mut(__clap_arg, __clap_arg_matches)?
+                }
+                Self::Operation(ref mut __clap_arg) if "op" == clap => {
+                    let (_, mut __clap_arg_sub_matches) = args.remove_subcommand().unwrap();
+                    let __clap_arg_matches = &mut __clap_arg_sub_matches;
+                    FromArgMatches::update_from_arg_matches_mut(__clap_arg, __clap_arg_matches)?
+                }
+                Self::CheckIgnore(ref mut __clap_arg) if "check-ignore" == clap => {
+                    let (_, mut __clap_arg_sub_matches) = args.remove_subcommand().unwrap();
+                    let __clap_arg_matches = &mut __clap_arg_sub_matches;
+                    FromArgMatches::update_from_arg_matches_mut(__clap_arg, __clap_arg_matches)?
+                }
+                Self::Config(ref mut __clap_arg) if "config" == clap => {
+                    let (_, mut __clap_arg_sub_matches) = args.remove_subcommand().unwrap();
+                    let __clap_arg_matches = &mut __clap_arg_sub_matches;
+                    FromArgMatches::update_from_arg_matches_mut(__clap_arg, __clap_arg_matches)?
+                }
                 Self::Stash(ref mut __clap_arg) if "stash" == clap => {
                     let (_, mut __clap_arg_sub_matches) = args.remove_subcommand().unwrap();
                     let __clap_arg_matches = &mut __clap_arg_sub_matches;
@@ -178,7 +259,26 @@ impl Subcommand for YsCommand {
                 .long_about(None)
                 .alias("difference")
         })
-        .subcommand(Command::new("异变").alias("changes"))
+        .subcommand({
+            YuanShenStatus::augment_args(Command::new("异变"))
+                .about("观测当前世界的异变")
+                .long_about(None)
+                .alias("status")
+                .alias("changes")
+        })
+        .subcommand({
+            YuanShenAdd::augment_args(Command::new("暂存")).about("将文件加入暂存区").long_about(None).alias("add")
+        })
+        .subcommand({
+            YuanShenRemove::augment_args(Command::new("移除"))
+                .about("将文件从暂存区和工作目录中移除")
+                .long_about(None)
+                .alias("remove")
+                .alias("rm")
+        })
+        .subcommand({
+            YuanShenReset::augment_args(Command::new("重置")).about("取消暂存").long_about(None).alias("reset")
+        })
         .subcommand(YuanShenCommit::augment_args(Command::new("衍化")).alias("commit"))
         .subcommand({
             YuanShenCommit::augment_args(Command::new("塌缩"))
@@ -203,11 +303,33 @@ impl Subcommand for YsCommand {
         })
         .subcommand({
             YuanShenCheckout::augment_args(Command::new("跃迁"))
-                .about("切换到指定名称的世界线")
+                .about("切换到指定的世界线, 或者分离到指定的快照")
                 .long_about(None)
                 .alias("checkout")
         })
-        .subcommand(Command::new("branch"))
+        .subcommand({
+            YuanShenBranch::augment_args(Command::new("branch")).about("列出, 创建, 重命名或删除世界线").long_about(None)
+        })
+        .subcommand({
+            YuanShenTag::augment_args(Command::new("锚定")).about("为快照设置不会移动的标签").long_about(None).alias("tag")
+        })
+        .subcommand({
+            YuanShenReflog::augment_args(Command::new("reflog")).about("查看世界线和标签的移动历史").long_about(None)
+        })
+        .subcommand(YuanShenUndo::augment_args(Command::new("undo")).about("撤销最近一次操作").long_about(None))
+        .subcommand(YuanShenRedo::augment_args(Command::new("redo")).about("重做最近一次被撤销的操作").long_about(None))
+        .subcommand({
+            YuanShenOperation::augment_args(Command::new("op"))
+                .about("查看操作日志, 或者恢复到任意操作之后的状态")
+                .long_about(None)
+                .alias("operation")
+        })
+        .subcommand({
+            YuanShenCheckIgnore::augment_args(Command::new("check-ignore")).about("检查路径是否被忽略").long_about(None)
+        })
+        .subcommand({
+            YuanShenConfig::augment_args(Command::new("设定")).about("读取或修改配置").long_about(None).alias("config")
+        })
         .subcommand(YuanShenCommit::augment_args(Command::new("stash")))
         .subcommand({
             Command::new("逆化").about("这些对象有点太城市化了").long_about(None).alias("gc").alias("garbage-collect")
@@ -225,29 +347,29 @@ impl Subcommand for YsCommand {
 }
 
 #[tokio::main]
-pub async fn main() -> Result<(), YsError> {
+pub async fn main() -> Result<ExitCode, YsError> {
     let args = YuanShen::parse();
+    let repo = args.repo.as_deref();
     use YsCommand::*;
     match args.cmd {
-        Initialize(init) => init.initialize().await?,
-        Difference(diff) => diff.difference().await?,
-        Branch(b) => b.branch().await?,
-        Checkout(c) => c.checkout().await?,
-        Changes => {
-            let dir = current_dir()?;
-            let dot_rev = DotYuanShenClient::open(&dir).unwrap();
-            let mut store = dot_rev.store().unwrap();
-            let branch: String = dot_rev.get_branch_name().unwrap();
-            let old_tip: ObjectID = dot_rev.get_branch_id(&branch).unwrap();
-            let ignores: IgnoreRules = dot_rev.ignores().unwrap();
-            let directory = SnapShotDirectory::new(dir.as_path(), &ignores, &mut store).unwrap();
-            let snapshot: SnapShot = store.get_typed(old_tip).await.unwrap();
-            let old_directory: SnapShotDirectory = store.get_typed(snapshot.directory).await.unwrap();
-            serde_json::to_writer_pretty(stdout(), &old_directory.difference(&directory)).unwrap();
-        }
-        Commit(sub) => sub.commit().await.unwrap(),
+        Initialize(init) => init.initialize(repo).await?,
+        Difference(diff) => diff.difference(repo).await?,
+        Branch(b) => b.branch(repo).await?,
+        Tag(tag) => tag.tag(repo).await?,
+        Reflog(reflog) => reflog.reflog(repo).await?,
+        Undo(undo) => undo.undo(repo).await?,
+        Redo(redo) => redo.redo(repo).await?,
+        Operation(operation) => operation.operation(repo).await?,
+        Checkout(c) => c.checkout(repo).await?,
+        Status(status) => return Ok(status.status(repo).await),
+        CheckIgnore(check) => return Ok(check.check_ignore(repo)),
+        Config(config) => config.config(repo).await?,
+        Add(add) => add.add(repo).await?,
+        Remove(remove) => remove.remove(repo).await?,
+        Reset(reset) => reset.reset(repo).await?,
+        Commit(sub) => sub.commit(repo).await?,
         Squash(_) => {}
-        Merge(_) => {}
+        Merge(merge) => merge.merge(repo).await?,
         Rebase(_) => {}
         Reverse(_) => {}
         Orphan(_) => {}
@@ -255,5 +377,5 @@ pub async fn main() -> Result<(), YsError> {
         External(_) => {}
         GarbageCollect => {}
     }
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
@@ -0,0 +1,23 @@
+use clap::Args;
+use std::{
+    env::current_dir,
+    path::{Path, PathBuf},
+};
+use ys_core::{initialize::DotYuanShenClient, YsError};
+
+/// 将文件加入暂存区
+#[derive(Debug, Args)]
+pub struct YuanShenAdd {
+    /// 需要暂存的文件或目录, 已被追踪但已消失的文件会从暂存区中移除
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+}
+
+impl YuanShenAdd {
+    pub async fn add(self, repo: Option<&Path>) -> Result<(), YsError> {
+        let here = current_dir()?;
+        let ys = DotYuanShenClient::from_env(&here, repo)?;
+        let paths: Vec<PathBuf> = self.paths.iter().map(|path| here.join(path)).collect();
+        ys.add(&paths).await
+    }
+}
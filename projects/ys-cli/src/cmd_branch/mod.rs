@@ -1,26 +1,97 @@
 use clap::Args;
-use std::env::current_dir;
+use std::{env::current_dir, path::Path};
 use ys_core::{
     initialize::{DotYuanShenClient, YuanShenClient},
-    ObjectID, YsError,
+    BranchFilter, YsError, YsErrorKind,
 };
 
+/// 列出, 创建, 重命名或删除世界线
+///
+/// 不给出操作时列出所有世界线及其指向的快照, 当前所在的世界线以 `*` 标记.
 #[derive(Debug, Args)]
 pub struct YuanShenBranch {
-    #[arg(long)]
+    /// 列出时为名称中需要包含的文字; 删除时为要删除的世界线; 重命名时为旧名称和新名称
+    names: Vec<String>,
+    /// 只列出历史中包含该修订的世界线
+    #[arg(long, value_name = "REVISION")]
     contains: Option<String>,
-    #[arg(long)]
+    /// 只列出历史中不包含该修订的世界线
+    #[arg(long, value_name = "REVISION")]
     without: Option<String>,
+    /// 按名称过滤时忽略大小写
     #[arg(long, short)]
     ignore_case: bool,
+    /// 删除世界线, 要求它指向的快照已经并入当前世界线
+    #[arg(long, short, requires = "names", conflicts_with_all = ["rename", "create", "contains", "without"])]
+    delete: bool,
+    /// 重命名世界线, 只给出一个名称时重命名当前世界线
+    #[arg(long, short = 'm', conflicts_with_all = ["create", "contains", "without"])]
+    rename: bool,
+    /// 从给定的修订创建世界线, 默认从 `HEAD` 创建
+    #[arg(long, short, num_args = 1..=2, value_names = ["NAME", "START"], conflicts_with_all = ["contains", "without"])]
+    create: Option<Vec<String>>,
+    /// 删除尚未并入当前世界线的世界线, 或者覆盖已经存在的世界线
+    #[arg(long, short)]
+    force: bool,
 }
 
 impl YuanShenBranch {
-    pub async fn branch(self) -> Result<(), YsError> {
+    pub async fn branch(self, repo: Option<&Path>) -> Result<(), YsError> {
         let here = current_dir()?;
-        let dot_rev = DotYuanShenClient::open(&here)?;
-        let branch = dot_rev.get_branch_name()?;
-        println!("{}", branch);
+        let dot_rev = DotYuanShenClient::from_env(&here, repo)?;
+        if let Some(create) = &self.create {
+            let operation = dot_rev.begin_operation()?;
+            let start = dot_rev.resolve_revision(create.get(1).map(String::as_str).unwrap_or("HEAD")).await?;
+            dot_rev.create_branch_at(&create[0], start, self.force)?;
+            operation.record(format!("branch: create {}", create[0]))?;
+            return Ok(());
+        }
+        if self.delete {
+            let operation = dot_rev.begin_operation()?;
+            let mut result = Ok(());
+            for name in self.names.iter() {
+                match dot_rev.delete_branch(name, self.force).await {
+                    Ok(tip) => println!("已删除世界线 {} (曾指向 {})", name, tip),
+                    Err(e) => {
+                        result = Err(e);
+                        break;
+                    }
+                }
+            }
+            // 中途失败时已经删除的世界线也要记录, 以便撤销
+            operation.record(format!("branch: delete {}", self.names.join(", ")))?;
+            return result;
+        }
+        if self.rename {
+            // 先检查参数, 再获取仓库锁
+            let (old, new) = match self.names.as_slice() {
+                [new] => (dot_rev.get_branch_name()?, new.clone()),
+                [old, new] => (old.clone(), new.clone()),
+                _ => Err(YsErrorKind::InvalidArguments { reason: "--rename 需要新名称, 可以在前面给出旧名称" })?,
+            };
+            let operation = dot_rev.begin_operation()?;
+            dot_rev.rename_branch(&old, &new, self.force)?;
+            operation.record(format!("branch: rename {} to {}", old, new))?;
+            return Ok(());
+        }
+        let filter = BranchFilter {
+            pattern: self.names.first().cloned(),
+            ignore_case: self.ignore_case,
+            contains: match &self.contains {
+                Some(revision) => Some(dot_rev.resolve_revision(revision).await?),
+                None => None,
+            },
+            without: match &self.without {
+                Some(revision) => Some(dot_rev.resolve_revision(revision).await?),
+                None => None,
+            },
+        };
+        let branches = dot_rev.list_branches(&filter).await?;
+        let width = branches.iter().map(|branch| branch.name.chars().count()).max().unwrap_or(0);
+        for branch in branches {
+            let marker = if branch.current { '*' } else { ' ' };
+            println!("{} {:width$} {}", marker, branch.name, branch.tip, width = width);
+        }
         Ok(())
     }
 }
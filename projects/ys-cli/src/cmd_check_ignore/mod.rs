@@ -0,0 +1,53 @@
+use clap::Args;
+use std::{
+    env::current_dir,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+use ys_core::{initialize::DotYuanShenClient, YsError};
+
+/// 检查路径是否被忽略
+///
+/// 至少有一个路径被忽略时退出码为 0, 都没有被忽略时为 1, 出错时为 2.
+#[derive(Debug, Args)]
+pub struct YuanShenCheckIgnore {
+    /// 同时输出匹配的规则所在的文件, 行号和规则本身
+    #[arg(long, short)]
+    verbose: bool,
+    /// 需要检查的路径
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+}
+
+impl YuanShenCheckIgnore {
+    pub fn check_ignore(self, repo: Option<&Path>) -> ExitCode {
+        match self.run(repo) {
+            Ok(true) => ExitCode::SUCCESS,
+            Ok(false) => ExitCode::from(1),
+            Err(e) => {
+                eprintln!("{}", e);
+                ExitCode::from(2)
+            }
+        }
+    }
+    fn run(self, repo: Option<&Path>) -> Result<bool, YsError> {
+        let here = current_dir()?;
+        let ys = DotYuanShenClient::from_env(&here, repo)?;
+        let mut any = false;
+        for path in self.paths.iter() {
+            let Some(rule) = ys.check_ignore(&here.join(path))?
+            else {
+                continue;
+            };
+            if self.verbose {
+                let origin = rule.origin.as_ref().map(|origin| origin.display().to_string()).unwrap_or_default();
+                println!("{}:{}:{}\t{}", origin, rule.line, rule.source, path.display());
+            }
+            else if !rule.negated {
+                println!("{}", path.display());
+            }
+            any |= !rule.negated;
+        }
+        Ok(any)
+    }
+}
@@ -1,20 +1,73 @@
 use clap::Args;
-use std::env::current_dir;
+use std::{env::current_dir, path::Path};
 use ys_core::{
     initialize::{DotYuanShenClient, YuanShenClient},
-    YsError,
+    Head, ObjectID, YsError, YsErrorKind,
 };
 
+/// 切换到给定的世界线, 或者分离到给定的快照
+///
+/// 目标不是已有的世界线但可以解析为修订号时, `HEAD` 直接指向该快照, 之后的提交不属于任何世界线.
+/// 都不是时从当前快照创建同名的世界线.
 #[derive(Debug, Args)]
 pub struct YuanShenCheckout {
-    branch: String,
+    /// 世界线的名称或者修订号
+    target: String,
+    /// 即使目标是世界线也分离到它指向的快照
+    #[arg(long, short)]
+    detach: bool,
+    /// 丢弃工作目录中未提交的修改
+    #[arg(long, short)]
+    force: bool,
 }
 
 impl YuanShenCheckout {
-    pub async fn checkout(self) -> Result<(), YsError> {
+    pub async fn checkout(self, repo: Option<&Path>) -> Result<(), YsError> {
         let here = current_dir()?;
-        let ys = DotYuanShenClient::open(&here)?;
-        ys.create_branch(&self.branch)?;
-        ys.set_branch(&self.branch)
+        let ys = DotYuanShenClient::from_env(&here, repo)?;
+        let operation = ys.begin_operation()?;
+        let left = operation.before().head.detached();
+        let this_tip = ys.calculate_branch_id()?;
+        let head = if self.detach {
+            Head::Detached { detached: ys.resolve_revision(&self.target).await? }
+        }
+        else if ys.branch_exists(&self.target)? {
+            Head::Branch(self.target.clone())
+        }
+        else {
+            match ys.resolve_revision(&self.target).await {
+                Ok(id) => Head::Detached { detached: id },
+                // 只有无法识别的名称才创建世界线, 读取失败等错误照常报告
+                Err(e) if matches!(e.kind(), YsErrorKind::UnknownRevision { .. }) => Head::Branch(self.target.clone()),
+                Err(e) => Err(e)?,
+            }
+        };
+        let that_tip = match &head {
+            Head::Branch(branch) => ys.create_branch(branch)?,
+            Head::Detached { detached } => *detached,
+        };
+        if this_tip != that_tip {
+            if !self.force && !ys.status().await?.is_clean() {
+                Err(YsErrorKind::UncommittedChanges { path: ys.working_directory().to_path_buf() })?
+            }
+            ys.switch_working_tree(this_tip, that_tip).await?;
+        }
+        ys.set_head(&head)?;
+        operation.record(format!("checkout: {}", self.target))?;
+        if let Head::Detached { detached } = head {
+            eprintln!("HEAD 已分离于快照 {}, 提交不会移动任何世界线", detached);
+        }
+        warn_unreferenced(&ys, left).await
     }
 }
+
+/// 离开分离的 `HEAD` 之后, 提醒原先的快照已经不属于任何世界线或标签
+pub(crate) async fn warn_unreferenced(ys: &DotYuanShenClient, left: Option<ObjectID>) -> Result<(), YsError> {
+    if let Some(id) = left {
+        if ys.head()?.detached() != Some(id) && !ys.is_referenced(id).await? {
+            eprintln!("警告: 快照 {} 不属于任何世界线或标签, 操作日志过期后会被回收", id);
+            eprintln!("可以用 `ys branch --create <名称> {}` 保留它", id);
+        }
+    }
+    Ok(())
+}
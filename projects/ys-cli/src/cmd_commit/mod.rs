@@ -1,36 +1,76 @@
 use clap::Args;
-use std::env::current_dir;
+use std::{collections::BTreeSet, env::current_dir, path::Path};
 use ys_core::{
     initialize::{DotYuanShenClient, YuanShenClient},
-    IgnoreRules, ObjectID, ObjectStore, SnapShot, SnapShotData, SnapShotDirectory, YsError,
+    AuthorID, Head, IgnoreRules, ObjectID, ObjectStore, RefExpectation, SnapShot, SnapShotData, SnapShotDirectory, StagingIndex,
+    YsError,
 };
 
 #[derive(Debug, Args)]
 pub struct YuanShenCommit {
     #[clap(short, long)]
     message: String,
+    /// 作者的标识, 例如 `name <email>`, 默认使用配置项 `user.name` 和 `user.email`
     #[clap(long)]
     author: Option<String>,
     #[clap(long)]
     data: Option<String>,
+    /// 忽略暂存区, 直接提交整个工作目录
+    #[clap(short, long)]
+    all: bool,
 }
 
 impl YuanShenCommit {
-    pub async fn commit(self) -> Result<(), YsError> {
+    pub async fn commit(self, repo: Option<&Path>) -> Result<(), YsError> {
         let dir = current_dir()?;
-        let dot_rev = DotYuanShenClient::open(&dir).unwrap();
-        let mut store = dot_rev.store().unwrap();
-        let branch: String = dot_rev.get_branch_name().unwrap();
-        let old_tip: ObjectID = dot_rev.get_branch_id(&branch)?;
-        let ignores: IgnoreRules = dot_rev.ignores().unwrap();
-        let directory = SnapShotDirectory::new(dir.as_path(), &ignores, &mut store).unwrap();
-        let directory_id = store.put_typed(&directory).await.unwrap();
+        let dot_rev = DotYuanShenClient::from_env(&dir, repo)?;
+        let mut store = dot_rev.store()?;
+        let pending = dot_rev.begin_operation()?;
+        let head = dot_rev.head()?;
+        let old_tip: ObjectID = dot_rev.calculate_branch_id()?;
+        let directory = if self.all {
+            let ignores: IgnoreRules = dot_rev.ignores()?;
+            let mut cache = dot_rev.stat_cache();
+            let directory = SnapShotDirectory::new(dot_rev.working_directory(), &ignores, &mut store, &mut cache).await?;
+            cache.prune();
+            dot_rev.save_stat_cache(&mut cache)?;
+            dot_rev.save_index(&StagingIndex::from_directory(&directory))?;
+            *directory
+        }
+        else {
+            dot_rev.index().await?.to_directory()?
+        };
+        let author = match &self.author {
+            Some(author) => AuthorID::from(author.as_str()),
+            None => dot_rev.config()?.resolve()?.user.author_id().unwrap_or_else(AuthorID::current_user),
+        };
+        let ignores = dot_rev.ignores_within("")?;
+        let directory_id = store.put_typed(&directory).await?;
+        let operation = format!("commit: {}", self.message.lines().next().unwrap_or_default());
         let snap = SnapShot {
             directory: directory_id,
             previous: vec![old_tip].into_iter().collect(),
-            data: SnapShotData { kind: 0, message: self.message, authors: Default::default() },
+            data: SnapShotData {
+                kind: 0,
+                message: self.message,
+                authors: BTreeSet::from([author]),
+                datetime: SnapShotData::now(),
+            },
+            ignores: Some(store.put_typed(&ignores).await?),
         };
         let snap_id = store.put_typed(&snap).await?;
-        dot_rev.set_branch_snapshot_id(&branch, snap_id)
+        match head {
+            Head::Branch(branch) => {
+                // 其他进程在此期间移动了世界线时放弃这次提交, 而不是覆盖它们的修改
+                let mut transaction = dot_rev.transaction();
+                transaction.update(format!("branches/{}", branch), snap_id, RefExpectation::Value(old_tip));
+                transaction.operation(operation.as_str());
+                transaction.commit()?;
+            }
+            // 分离时只移动 `HEAD`, 新快照通过操作日志记录
+            Head::Detached { .. } => dot_rev.set_head(&Head::Detached { detached: snap_id })?,
+        }
+        pending.record(operation)?;
+        Ok(())
     }
 }
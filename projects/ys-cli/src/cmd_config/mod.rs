@@ -0,0 +1,139 @@
+use clap::{Args, Subcommand};
+use std::{
+    env::current_dir,
+    path::{Path, PathBuf},
+    process::exit,
+};
+use ys_core::{
+    check_config_key, initialize::DotYuanShenClient, set_config_value, unset_config_value, ConfigLayer, LayeredConfig,
+    YsError, YsErrorKind, CONFIG_KEYS,
+};
+
+/// 读取或修改配置
+///
+/// 配置按系统, 用户, 仓库, 环境变量的顺序叠加, 后者覆盖前者.
+/// 不指定层时, 读取合并后的结果; 修改 `user.*` 时写入用户的配置文件,
+/// 其余配置项写入随项目提交的仓库配置文件 `.config/yuan-shen/config.toml`.
+#[derive(Debug, Args)]
+pub struct YuanShenConfig {
+    #[command(subcommand)]
+    action: ConfigAction,
+    /// 只读取或修改系统的配置
+    #[arg(long, global = true, conflicts_with_all = ["user", "repository"])]
+    system: bool,
+    /// 只读取或修改当前用户的配置
+    #[arg(long, global = true, conflicts_with = "repository")]
+    user: bool,
+    /// 只读取或修改当前仓库的配置
+    #[arg(long, global = true)]
+    repository: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigAction {
+    /// 输出配置项的值, 没有设置时退出码为 1
+    Get {
+        /// 配置项, 例如 `user.name`
+        key: String,
+    },
+    /// 设置配置项, 值按 TOML 解析, 不符合类型时视为字符串
+    Set {
+        /// 配置项, 例如 `user.name`
+        key: String,
+        /// 新的值
+        value: String,
+    },
+    /// 列出所有设置了的配置项及其所在的层
+    List {
+        /// 同时列出所有可以设置的配置项和对应的环境变量
+        #[arg(long)]
+        all: bool,
+    },
+    /// 删除配置项
+    Unset {
+        /// 配置项, 例如 `user.name`
+        key: String,
+    },
+}
+
+impl YuanShenConfig {
+    pub async fn config(self, repo: Option<&Path>) -> Result<(), YsError> {
+        let here = current_dir()?;
+        // 仓库之外也可以读取和修改系统和用户的配置
+        let ys = DotYuanShenClient::from_env(&here, repo).ok();
+        let layer = self.layer();
+        // 修改配置文件时不读取其他层, 以免无法修复写错的配置
+        let load = || -> Result<LayeredConfig, YsError> {
+            let config = match &ys {
+                Some(ys) => ys.config()?,
+                None => LayeredConfig::load(None)?,
+            };
+            Ok(match layer {
+                Some(layer) => config.only(layer),
+                None => config,
+            })
+        };
+        match self.action {
+            ConfigAction::Get { key } => {
+                check_config_key(&key)?;
+                match load()?.get(&key) {
+                    Some((_, value)) => println!("{}", value),
+                    None => exit(1),
+                }
+            }
+            ConfigAction::List { all } => {
+                for (key, (source, value)) in load()?.entries() {
+                    println!("{}={}\t({})", key, value, source);
+                }
+                if all {
+                    println!();
+                    for (key, variable) in CONFIG_KEYS {
+                        println!("{}\t${}", key, variable);
+                    }
+                }
+            }
+            ConfigAction::Set { key, value } => {
+                let path = config_path(layer.unwrap_or_else(|| ConfigLayer::default_for(&key)), ys.as_ref())?;
+                set_config_value(&path, &key, &value)?;
+            }
+            ConfigAction::Unset { key } => {
+                let path = config_path(layer.unwrap_or_else(|| ConfigLayer::default_for(&key)), ys.as_ref())?;
+                if !unset_config_value(&path, &key)? {
+                    eprintln!("{:?} 中没有设置 {}", path, key);
+                    exit(1);
+                }
+            }
+        }
+        Ok(())
+    }
+    fn layer(&self) -> Option<ConfigLayer> {
+        if self.system {
+            Some(ConfigLayer::System)
+        }
+        else if self.user {
+            Some(ConfigLayer::User)
+        }
+        else if self.repository {
+            Some(ConfigLayer::Repository)
+        }
+        else {
+            None
+        }
+    }
+}
+
+/// 要修改的配置文件
+fn config_path(layer: ConfigLayer, ys: Option<&DotYuanShenClient>) -> Result<PathBuf, YsError> {
+    let path = match layer {
+        ConfigLayer::System => ConfigLayer::system_path(),
+        ConfigLayer::User => ConfigLayer::user_path(),
+        _ => match ys {
+            Some(ys) => Some(ys.config_path()),
+            None => Err(YsErrorKind::RepositoryNotFound { path: current_dir()? })?,
+        },
+    };
+    match path {
+        Some(path) => Ok(path),
+        None => Err(YsErrorKind::InvalidConfig { path: None, reason: "无法确定配置文件的位置".to_string() })?,
+    }
+}
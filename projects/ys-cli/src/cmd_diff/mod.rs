@@ -1,35 +1,127 @@
-use clap::Args;
-use std::{env::current_dir, process::exit};
+use clap::{Args, ValueEnum};
+use std::{
+    env::current_dir,
+    io::{stdout, IsTerminal, Write},
+    path::{Path, PathBuf},
+};
 use ys_core::{
-    initialize::{DotYuanShenClient, YuanShenClient},
-    ObjectID, ObjectStore, SnapShot, SnapShotDirectory, YsError,
+    differences::{DiffStat, RenameOptions},
+    initialize::DotYuanShenClient,
+    DiffSide, RevisionSpec, YsError, YsErrorKind,
 };
 
+/// 观测两个修订, 暂存区或工作目录之间的差异
+///
+/// 没有给出修订时比较暂存区与工作目录; 给出一个修订时比较该修订与工作目录; 给出两个修订时比较两者.
+/// 使用 `--cached` 时以暂存区代替工作目录, 没有给出修订时与 `HEAD` 比较.
+/// 修订可以是世界线名称, 完整或缩写的快照 id, 加上 `~n`, `^n` 或 `@{date}` 这样的后缀.
+/// 也可以给出一个 `a..b` 范围, `a...b` 则比较两者的共同祖先与 `b`.
+///
+/// 默认以统一格式输出文本文件的逐行修改, 二进制文件只提示内容不同.
 #[derive(Debug, Args)]
 pub struct YuanShenDifference {
-    branch: String,
+    /// 要比较的修订, 最多两个
+    #[arg(num_args = 0..=2)]
+    revisions: Vec<String>,
+    /// 只比较这些路径中的文件, 写在 `--` 之后
+    #[arg(last = true)]
+    paths: Vec<PathBuf>,
+    /// 与暂存区而不是工作目录比较
+    #[arg(long, alias = "staged")]
+    cached: bool,
+    /// 每段修改前后保留的上下文行数
+    #[arg(long, short = 'U', default_value_t = 3)]
+    unified: usize,
+    /// 是否使用颜色输出, 默认只在终端中使用
+    #[arg(long, value_enum, default_value = "auto")]
+    color: DiffColor,
+    /// 只列出路径及其修改类型, 不输出文件内容
+    #[arg(long)]
+    name_status: bool,
+    /// 只输出每个文件新增和删除的行数以及汇总
+    #[arg(long, conflicts_with = "name_status")]
+    stat: bool,
+    /// 视为重命名的最低相似度百分比
+    #[arg(long, short = 'M', default_value_t = 50, value_parser = clap::value_parser!(u8).range(0..=100))]
+    find_renames: u8,
+    /// 同时检测复制的文件
+    #[arg(long, short = 'C')]
+    find_copies: bool,
+    /// 不检测重命名, 移动的文件显示为删除和新增
+    #[arg(long, conflicts_with_all = ["find_renames", "find_copies"])]
+    no_renames: bool,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum DiffColor {
+    Auto,
+    Always,
+    Never,
 }
 
 impl YuanShenDifference {
-    pub async fn difference(self) -> Result<(), YsError> {
-        let dir = current_dir().unwrap();
-        let dot_rev = DotYuanShenClient::open(&dir)?;
-        let store = dot_rev.store()?;
-        let that_branch = self.branch.as_ref();
-        let this_branch: String = dot_rev.get_branch_name()?;
-        if !dot_rev.branch_exists(&that_branch)? {
-            eprintln!("no branch named {} exists", that_branch);
-            exit(1);
+    pub async fn difference(self, repo: Option<&Path>) -> Result<(), YsError> {
+        let dir = current_dir()?;
+        let dot_rev = DotYuanShenClient::from_env(&dir, repo)?;
+        let mut sides = vec![];
+        for revision in self.revisions.iter() {
+            match revision.parse()? {
+                RevisionSpec::Single(revision) => sides.push(DiffSide::Snapshot(dot_rev.resolve(&revision).await?)),
+                RevisionSpec::Range { from, to, symmetric } if self.revisions.len() == 1 && !self.cached => {
+                    let (old, new) = dot_rev.resolve_range(&from, &to, symmetric).await?;
+                    sides.extend([DiffSide::Snapshot(old), DiffSide::Snapshot(new)]);
+                }
+                RevisionSpec::Range { .. } => Err(YsErrorKind::InvalidRevision {
+                    revision: revision.clone(),
+                    reason: "范围不能与其他修订或 --cached 一起使用",
+                })?,
+            }
+        }
+        let (old, new) = match (sides.as_slice(), self.cached) {
+            ([], false) => (DiffSide::Index, DiffSide::WorkingTree),
+            ([], true) => (DiffSide::Snapshot(dot_rev.resolve_revision("HEAD").await?), DiffSide::Index),
+            ([old], false) => (*old, DiffSide::WorkingTree),
+            ([old], true) => (*old, DiffSide::Index),
+            ([old, new], false) => (*old, *new),
+            _ => Err(YsErrorKind::InvalidRevision {
+                revision: self.revisions.join(" "),
+                reason: "--cached 不能与两个修订一起使用",
+            })?,
+        };
+        let paths = self.paths.iter().map(|path| dot_rev.relative_path(&dir.join(path))).collect::<Result<Vec<_>, _>>()?;
+        let renames = RenameOptions { threshold: self.find_renames, copies: self.find_copies, ..Default::default() };
+        let renames = if self.no_renames { None } else { Some(&renames) };
+        if self.name_status {
+            for record in dot_rev.diff_records(&old, &new, renames, &paths).await? {
+                println!("{record}");
+            }
+            return Ok(());
+        }
+        if self.stat {
+            let mut total = DiffStat::default();
+            for (record, stat) in dot_rev.diff_stats(&old, &new, renames, &paths).await? {
+                if stat.binary_files > 0 {
+                    println!(" {} | Bin {} -> {} bytes", record.path, stat.bytes_removed, stat.bytes_added);
+                }
+                else {
+                    println!(" {} | +{} -{}", record.path, stat.lines_added, stat.lines_removed);
+                }
+                total += stat;
+            }
+            println!(" {}", total);
+            return Ok(());
+        }
+        let color = match self.color {
+            DiffColor::Auto => stdout().is_terminal(),
+            DiffColor::Always => true,
+            DiffColor::Never => false,
+        };
+        let patches = dot_rev.patches(&old, &new, self.unified, renames, &paths).await?;
+        let mut out = stdout().lock();
+        for patch in patches {
+            patch.write_unified(&mut out, color)?;
         }
-        let this_tip: ObjectID = dot_rev.get_branch_id(&this_branch)?;
-        let that_tip: ObjectID = dot_rev.get_branch_id(&that_branch)?;
-        let that_snapshot: SnapShot = store.get_typed(that_tip).await.expect("read that tip");
-        let that_branch_directory = store.get_typed(that_snapshot.directory).await.expect("read that directory");
-        let this_snapshot: SnapShot = store.get_typed(this_tip).await.expect("read this tip");
-        let this_branch_directory: SnapShotDirectory =
-            store.get_typed(this_snapshot.directory).await.expect("read this branch directory");
-        let diff = &this_branch_directory.difference(&that_branch_directory);
-        println!("{diff}");
+        out.flush()?;
         Ok(())
     }
 }
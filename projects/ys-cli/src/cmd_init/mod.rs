@@ -1,22 +1,38 @@
 use clap::Args;
-use std::{borrow::Cow, env::current_dir};
-use ys_core::{initialize::InitializeConfig, IgnoreRules, YsError};
+use std::{borrow::Cow, env::current_dir, fs::create_dir_all, path::Path};
+use ys_core::{
+    initialize::{requested_work_tree, InitializeConfig},
+    IgnoreRules, LayeredConfig, YsError,
+};
 
+/// 在当前目录, 或者 `--repo` 给出的目录创建仓库
+///
+/// 仓库已经存在时不会重新创建, 只把早期版本写入的世界线和标签改写为当前格式.
 #[derive(Debug, Args)]
 pub struct YuanShenInitialize {
-    /// override the name of the initial branch
+    /// override the name of the initial branch, defaults to `init.default-branch`
     #[clap(long, short = 'b')]
     initial_branch: Option<String>,
 }
 
 impl YuanShenInitialize {
-    pub async fn initialize(self) -> Result<(), YsError> {
+    pub async fn initialize(self, repo: Option<&Path>) -> Result<(), YsError> {
+        let here = current_dir()?;
+        let current = match requested_work_tree(&here, repo) {
+            Some(path) => {
+                create_dir_all(&path).map_err(|e| YsError::path_error(e, &path))?;
+                path
+            }
+            None => here,
+        };
+        // 工作目录中可能已经有随项目一起分发的 `.config/yuan-shen`
+        let settings = LayeredConfig::load(Some(&current.join(".config").join("yuan-shen")))?.resolve()?;
         let config = InitializeConfig {
-            current: current_dir()?,
             initial_branch: match self.initial_branch {
                 Some(s) => Cow::Owned(s),
-                None => Cow::Borrowed("master"),
+                None => Cow::Owned(settings.default_branch().to_string()),
             },
+            current,
             ignores: IgnoreRules::default(),
         };
         config.generate().await?;
@@ -1,4 +1,85 @@
 use clap::Args;
+use std::{collections::BTreeSet, env::current_dir, path::Path};
+use ys_core::{
+    differences::RenameOptions,
+    initialize::{DotYuanShenClient, YuanShenClient},
+    AuthorID, Head, ObjectStore, RefExpectation, SnapShot, SnapShotData, SnapShotDirectory, YsError, YsErrorKind,
+};
 
+/// 将给定修订的修改收束到当前世界线
+///
+/// 当前快照是目标的祖先时直接前进到目标; 否则以两者最近的共同祖先为基准按文件合并,
+/// 并创建一个同时以两者为前驱的快照. 一方重命名而另一方修改的文件, 修改会跟随重命名.
+/// 存在冲突时不做任何修改.
 #[derive(Debug, Args)]
-pub struct YuanShenMerge {}
+pub struct YuanShenMerge {
+    /// 要合并的修订
+    revision: String,
+    /// 合并快照的说明, 默认为 `merge <修订>`
+    #[arg(long, short)]
+    message: Option<String>,
+    /// 视为重命名的最低相似度百分比
+    #[arg(long, short = 'M', default_value_t = 50, value_parser = clap::value_parser!(u8).range(0..=100))]
+    find_renames: u8,
+}
+
+impl YuanShenMerge {
+    pub async fn merge(self, repo: Option<&Path>) -> Result<(), YsError> {
+        let here = current_dir()?;
+        let ys = DotYuanShenClient::from_env(&here, repo)?;
+        let pending = ys.begin_operation()?;
+        let head = ys.head()?;
+        let this_tip = ys.calculate_branch_id()?;
+        let that_tip = ys.resolve_revision(&self.revision).await?;
+        if ys.is_ancestor(that_tip, this_tip).await? {
+            eprintln!("{} 已经包含在当前世界线中", self.revision);
+            return Ok(());
+        }
+        if !ys.status().await?.is_clean() {
+            Err(YsErrorKind::UncommittedChanges { path: ys.working_directory().to_path_buf() })?
+        }
+        let mut store = ys.store()?;
+        let operation = format!("merge: {}", self.revision);
+        let tip = if ys.is_ancestor(this_tip, that_tip).await? {
+            that_tip
+        }
+        else {
+            let base = match ys.merge_base(this_tip, that_tip).await? {
+                Some(base) => store.get_typed(store.get_typed::<SnapShot>(base).await?.directory).await?,
+                None => SnapShotDirectory::default(),
+            };
+            let ours: SnapShotDirectory = store.get_typed(store.get_typed::<SnapShot>(this_tip).await?.directory).await?;
+            let theirs: SnapShotDirectory = store.get_typed(store.get_typed::<SnapShot>(that_tip).await?.directory).await?;
+            let options = RenameOptions { threshold: self.find_renames, ..Default::default() };
+            let merge = base.merge(&ours, &theirs, &store, &options).await?;
+            if !merge.is_clean() {
+                Err(YsErrorKind::MergeConflict { paths: merge.conflicts.into_iter().collect() })?
+            }
+            let author = ys.config()?.resolve()?.user.author_id().unwrap_or_else(AuthorID::current_user);
+            let snapshot = SnapShot {
+                directory: store.put_typed(&merge.index.to_directory()?).await?,
+                previous: BTreeSet::from([this_tip, that_tip]),
+                data: SnapShotData {
+                    kind: 0,
+                    message: self.message.unwrap_or_else(|| format!("merge {}", self.revision)),
+                    authors: BTreeSet::from([author]),
+                    datetime: SnapShotData::now(),
+                },
+                ignores: Some(store.put_typed(&ys.ignores_within("")?).await?),
+            };
+            store.put_typed(&snapshot).await?
+        };
+        ys.switch_working_tree(this_tip, tip).await?;
+        match head {
+            Head::Branch(branch) => {
+                let mut transaction = ys.transaction();
+                transaction.update(format!("branches/{}", branch), tip, RefExpectation::Value(this_tip));
+                transaction.operation(operation.as_str());
+                transaction.commit()?;
+            }
+            Head::Detached { .. } => ys.set_head(&Head::Detached { detached: tip })?,
+        }
+        pending.record(operation)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+use crate::cmd_checkout::warn_unreferenced;
+use clap::{Args, Subcommand};
+use std::{env::current_dir, path::Path};
+use ys_core::{
+    initialize::{DotYuanShenClient, YuanShenClient},
+    ObjectID, YsError, YsErrorKind,
+};
+
+/// 查看操作日志, 或者将仓库恢复到任意操作之后的状态
+///
+/// 每个修改引用的命令都会在操作日志中记录执行前后所有世界线, 标签和 `HEAD` 的状态.
+#[derive(Debug, Args)]
+pub struct YuanShenOperation {
+    #[command(subcommand)]
+    action: OperationAction,
+}
+
+#[derive(Debug, Subcommand)]
+enum OperationAction {
+    /// 从新到旧列出操作及其改动的引用
+    Log {
+        /// 最多列出的操作数
+        #[arg(long, short = 'n')]
+        limit: Option<usize>,
+    },
+    /// 将所有引用和 `HEAD` 恢复到给定操作之后的状态
+    Restore {
+        /// 操作的序号, 见 `ys op log`
+        id: usize,
+        /// 丢弃工作目录中未提交的修改
+        #[arg(long, short)]
+        force: bool,
+    },
+}
+
+impl YuanShenOperation {
+    pub async fn operation(self, repo: Option<&Path>) -> Result<(), YsError> {
+        let here = current_dir()?;
+        let ys = DotYuanShenClient::from_env(&here, repo)?;
+        match self.action {
+            OperationAction::Log { limit } => {
+                for entry in ys.operations()?.iter().rev().take(limit.unwrap_or(usize::MAX)) {
+                    println!("{} {}", entry.id, entry.description);
+                    if entry.before.head != entry.after.head {
+                        println!("    HEAD {} -> {}", entry.before.head, entry.after.head);
+                    }
+                    let show = |id: Option<ObjectID>| id.map(|id| id.to_string()).unwrap_or_else(|| "-".to_string());
+                    for (name, old, new) in entry.before.changes(&entry.after) {
+                        println!("    {} {} -> {}", name, show(old), show(new));
+                    }
+                }
+            }
+            OperationAction::Restore { id, force } => {
+                let entry = with_working_tree(&ys, force, |ys| ys.restore_operation(id)).await?;
+                println!("已恢复到操作 {} 之后的状态: {}", entry.id, entry.description);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 修改仓库状态, 当前快照因此改变时同步工作目录和暂存区
+///
+/// 没有给出 `force` 时要求工作目录中没有未提交的修改, 以免被覆盖.
+pub(crate) async fn with_working_tree<T>(
+    ys: &DotYuanShenClient,
+    force: bool,
+    restore: impl FnOnce(&DotYuanShenClient) -> Result<T, YsError>,
+) -> Result<T, YsError> {
+    if !force && !ys.status().await?.is_clean() {
+        Err(YsErrorKind::UncommittedChanges { path: ys.working_directory().to_path_buf() })?
+    }
+    let left = ys.head()?.detached();
+    let this_tip = ys.calculate_branch_id()?;
+    let result = restore(ys)?;
+    let that_tip = ys.calculate_branch_id()?;
+    if this_tip != that_tip {
+        ys.switch_working_tree(this_tip, that_tip).await?;
+    }
+    warn_unreferenced(ys, left).await?;
+    Ok(result)
+}
@@ -0,0 +1,26 @@
+use crate::cmd_operation::with_working_tree;
+use clap::Args;
+use std::{env::current_dir, path::Path};
+use ys_core::{initialize::DotYuanShenClient, YsError};
+
+/// 重做最近一次被撤销的操作
+///
+/// 撤销之后又执行了其他命令时不能再重做.
+#[derive(Debug, Args)]
+pub struct YuanShenRedo {
+    /// 丢弃工作目录中未提交的修改
+    #[arg(long, short)]
+    force: bool,
+}
+
+impl YuanShenRedo {
+    pub async fn redo(self, repo: Option<&Path>) -> Result<(), YsError> {
+        let here = current_dir()?;
+        let ys = DotYuanShenClient::from_env(&here, repo)?;
+        match with_working_tree(&ys, self.force, |ys| ys.redo()).await? {
+            Some(entry) => println!("已重做操作 {}: {}", entry.id, entry.description),
+            None => println!("没有可以重做的操作"),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use clap::Args;
+use std::{env::current_dir, path::Path};
+use ys_core::{
+    initialize::{DotYuanShenClient, YuanShenClient},
+    SnapShotData, YsError, YsErrorKind,
+};
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// 查看世界线和标签的移动历史
+///
+/// 从新到旧列出引用的每次移动, 第 n 条记录可以在修订号中写作 `name@{n}`.
+/// 超过 90 天的记录不再阻止垃圾回收, 可以用 `--expire` 提前清理.
+#[derive(Debug, Args)]
+pub struct YuanShenReflog {
+    /// 世界线或标签的名称, 默认为当前世界线
+    name: Option<String>,
+    /// 列出所有引用的历史
+    #[arg(long, short, conflicts_with = "name")]
+    all: bool,
+    /// 删除早于给定天数的历史记录
+    #[arg(long, value_name = "DAYS", conflicts_with_all = ["name", "all"])]
+    expire: Option<u64>,
+}
+
+impl YuanShenReflog {
+    pub async fn reflog(self, repo: Option<&Path>) -> Result<(), YsError> {
+        let here = current_dir()?;
+        let dot_rev = DotYuanShenClient::from_env(&here, repo)?;
+        if let Some(days) = self.expire {
+            let now = SnapShotData::now().unwrap_or_default();
+            let removed = dot_rev.expire_reflogs(now.saturating_sub(days.saturating_mul(DAY)))?;
+            println!("已删除 {} 条历史记录", removed);
+            return Ok(());
+        }
+        let references = match self.name {
+            _ if self.all => dot_rev.reflog_names()?,
+            None => vec![format!("branches/{}", dot_rev.get_branch_name()?)],
+            Some(name) if dot_rev.branch_exists(&name)? => vec![format!("branches/{}", name)],
+            Some(name) if dot_rev.tag_exists(&name)? => vec![format!("tags/{}", name)],
+            Some(name) => Err(YsErrorKind::MissingReference { name })?,
+        };
+        for reference in references {
+            let short = reference.split_once('/').map(|(_, name)| name).unwrap_or(&reference);
+            for (n, entry) in dot_rev.reflog(&reference)?.iter().rev().enumerate() {
+                println!("{} {}@{{{}}}: {}", entry.new, short, n, entry.operation);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+use clap::Args;
+use std::{
+    env::current_dir,
+    path::{Path, PathBuf},
+};
+use ys_core::{initialize::DotYuanShenClient, YsError};
+
+/// 将文件从暂存区和工作目录中移除
+#[derive(Debug, Args)]
+pub struct YuanShenRemove {
+    /// 需要移除的文件或目录
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+    /// 只从暂存区中移除, 保留工作目录中的文件
+    #[arg(long)]
+    cached: bool,
+    /// 即使工作目录中的文件有尚未暂存的修改也删除它们
+    #[arg(long, short)]
+    force: bool,
+}
+
+impl YuanShenRemove {
+    pub async fn remove(self, repo: Option<&Path>) -> Result<(), YsError> {
+        let here = current_dir()?;
+        let ys = DotYuanShenClient::from_env(&here, repo)?;
+        let paths: Vec<PathBuf> = self.paths.iter().map(|path| here.join(path)).collect();
+        for path in ys.remove(&paths, self.cached, self.force).await? {
+            println!("已移除 {}", path);
+        }
+        Ok(())
+    }
+}
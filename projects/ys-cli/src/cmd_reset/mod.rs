@@ -0,0 +1,22 @@
+use clap::Args;
+use std::{
+    env::current_dir,
+    path::{Path, PathBuf},
+};
+use ys_core::{initialize::DotYuanShenClient, YsError};
+
+/// 取消暂存, 将暂存区恢复为当前世界线的最新快照
+#[derive(Debug, Args)]
+pub struct YuanShenReset {
+    /// 需要取消暂存的文件或目录, 不指定时重置整个暂存区
+    paths: Vec<PathBuf>,
+}
+
+impl YuanShenReset {
+    pub async fn reset(self, repo: Option<&Path>) -> Result<(), YsError> {
+        let here = current_dir()?;
+        let ys = DotYuanShenClient::from_env(&here, repo)?;
+        let paths: Vec<PathBuf> = self.paths.iter().map(|path| here.join(path)).collect();
+        ys.reset(&paths).await
+    }
+}
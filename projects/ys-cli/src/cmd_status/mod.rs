@@ -0,0 +1,101 @@
+use clap::Args;
+use std::{collections::BTreeSet, env::current_dir, io::stdout, path::Path, process::ExitCode};
+use ys_core::{initialize::DotYuanShenClient, Head, WorkingStatus, YsError};
+
+/// 查看工作目录的状态
+///
+/// 工作目录干净时退出码为 0, 存在未提交的修改时为 1, 出错时为 2.
+#[derive(Debug, Args)]
+pub struct YuanShenStatus {
+    /// 以简短格式输出, 每行一个路径
+    #[arg(long, short)]
+    short: bool,
+    /// 以 JSON 格式输出
+    #[arg(long, conflicts_with = "short")]
+    json: bool,
+    /// 同时列出被忽略的路径
+    #[arg(long)]
+    ignored: bool,
+}
+
+impl YuanShenStatus {
+    pub async fn status(self, repo: Option<&Path>) -> ExitCode {
+        match self.run(repo).await {
+            Ok(true) => ExitCode::SUCCESS,
+            Ok(false) => ExitCode::from(1),
+            Err(e) => {
+                eprintln!("{}", e);
+                ExitCode::from(2)
+            }
+        }
+    }
+    async fn run(self, repo: Option<&Path>) -> Result<bool, YsError> {
+        let here = current_dir()?;
+        let dot_rev = DotYuanShenClient::from_env(&here, repo)?;
+        let mut status = dot_rev.status().await?;
+        if !self.ignored {
+            status.ignored.clear();
+        }
+        if self.json {
+            serde_json::to_writer_pretty(stdout(), &status).map_err(YsError::from)?;
+            println!();
+        }
+        else if self.short {
+            print_short(&status);
+        }
+        else {
+            print_long(&dot_rev.head()?, &status);
+        }
+        Ok(status.is_clean())
+    }
+}
+
+fn print_short(status: &WorkingStatus) {
+    let groups = [
+        ("A ", &status.added),
+        ("M ", &status.modified),
+        ("D ", &status.deleted),
+        (" M", &status.changed),
+        (" D", &status.missing),
+        ("??", &status.untracked),
+        ("!!", &status.ignored),
+    ];
+    for (symbol, paths) in groups {
+        for path in paths {
+            println!("{} {}", symbol, path);
+        }
+    }
+}
+
+fn print_long(head: &Head, status: &WorkingStatus) {
+    match head {
+        Head::Branch(branch) => println!("位于世界线 {}", branch),
+        Head::Detached { detached } => println!("HEAD 分离于快照 {}", detached),
+    }
+    let groups = [
+        ("新增的文件", &status.added),
+        ("修改的文件", &status.modified),
+        ("删除的文件", &status.deleted),
+        ("尚未暂存的修改", &status.changed),
+        ("尚未暂存的删除", &status.missing),
+        ("未追踪的文件", &status.untracked),
+        ("被忽略的文件", &status.ignored),
+    ];
+    for (title, paths) in groups {
+        print_group(title, paths);
+    }
+    if status.is_clean() {
+        println!("工作目录是干净的, 没有需要提交的修改");
+    }
+}
+
+fn print_group(title: &str, paths: &BTreeSet<String>) {
+    if paths.is_empty() {
+        return;
+    }
+    println!();
+    println!("{}:", title);
+    for path in paths {
+        println!("    {}", path);
+    }
+}
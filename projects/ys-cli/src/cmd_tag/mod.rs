@@ -0,0 +1,112 @@
+use clap::Args;
+use std::{
+    env::current_dir,
+    path::{Path, PathBuf},
+};
+use ys_core::{initialize::DotYuanShenClient, AuthorID, SnapShotData, TagObject, YsError};
+
+/// 创建, 列出, 删除或校验标签
+///
+/// 标签是不会随提交移动的引用, 用于标记发布的版本. 只给出名称时创建指向修订的轻量标签,
+/// 使用 `--annotate` 或 `--message` 时创建带有作者, 说明和可选消息认证码的附注标签.
+/// 消息认证码使用共享密钥计算, 只能在持有同一密钥的人之间证明标签没有被篡改, 不是数字签名.
+/// 不给出名称时列出所有标签.
+#[derive(Debug, Args)]
+pub struct YuanShenTag {
+    /// 标签名称, 列出标签时作为名称前缀
+    name: Option<String>,
+    /// 标签指向的修订, 默认为 `HEAD`
+    revision: Option<String>,
+    /// 创建附注标签
+    #[arg(long, short)]
+    annotate: bool,
+    /// 附注标签的说明, 隐含 `--annotate`
+    #[arg(long, short)]
+    message: Option<String>,
+    /// 创建标签的作者, 默认为当前系统用户
+    #[arg(long)]
+    tagger: Option<String>,
+    /// 计算或校验消息认证码使用的共享密钥文件, 内容为 32 字节或 64 个十六进制字符
+    #[arg(long)]
+    key: Option<PathBuf>,
+    /// 覆盖已经存在的标签
+    #[arg(long, short)]
+    force: bool,
+    /// 列出标签
+    #[arg(long, short, conflicts_with_all = ["delete", "verify", "revision"])]
+    list: bool,
+    /// 删除标签
+    #[arg(long, short, requires = "name", conflicts_with_all = ["verify", "revision"])]
+    delete: bool,
+    /// 校验标签的内容, 给出 `--key` 时同时校验消息认证码
+    #[arg(long, short, requires = "name", conflicts_with = "revision")]
+    verify: bool,
+}
+
+impl YuanShenTag {
+    pub async fn tag(self, repo: Option<&Path>) -> Result<(), YsError> {
+        let dir = current_dir()?;
+        let dot_rev = DotYuanShenClient::from_env(&dir, repo)?;
+        let key = match &self.key {
+            Some(path) => Some(TagObject::parse_key(&std::fs::read(path)?)?),
+            None => None,
+        };
+        let name = match self.name {
+            Some(name) if !self.list => name,
+            prefix => {
+                let prefix = prefix.unwrap_or_default();
+                for name in dot_rev.tag_names()?.into_iter().filter(|name| name.starts_with(&prefix)) {
+                    println!("{}", name);
+                }
+                return Ok(());
+            }
+        };
+        if self.delete {
+            let operation = dot_rev.begin_operation()?;
+            let id = dot_rev.delete_tag(&name)?;
+            println!("已删除标签 {} (曾指向 {})", name, id);
+            operation.record(format!("tag: delete {}", name))?;
+            return Ok(());
+        }
+        if self.verify {
+            match dot_rev.verify_tag(&name, key.as_ref()).await? {
+                None => println!("轻量标签 {} 指向 {}", name, dot_rev.get_tag_id(&name)?),
+                Some(tag) => {
+                    println!("附注标签 {} 指向 {}, 作者 {}", name, tag.target, tag.tagger);
+                    match (&tag.mac, key) {
+                        (_, Some(_)) => println!("消息认证码有效"),
+                        (Some(_), None) => println!("带有消息认证码, 没有给出 --key, 未校验"),
+                        (None, None) => println!("没有消息认证码"),
+                    }
+                }
+            }
+            return Ok(());
+        }
+        let operation = dot_rev.begin_operation()?;
+        let target = dot_rev.resolve_revision(self.revision.as_deref().unwrap_or("HEAD")).await?;
+        let description = format!("tag: create {}", name);
+        if !self.annotate && self.message.is_none() {
+            dot_rev.write_tag(&name, target, self.force)?;
+            operation.record(description)?;
+            return Ok(());
+        }
+        let tagger = match self.tagger {
+            Some(tagger) => AuthorID::from(tagger.as_str()),
+            None => dot_rev.config()?.resolve()?.user.author_id().unwrap_or_else(AuthorID::current_user),
+        };
+        let mut tag = TagObject {
+            target,
+            name,
+            tagger,
+            message: self.message.unwrap_or_default(),
+            datetime: SnapShotData::now(),
+            mac: None,
+        };
+        if let Some(key) = &key {
+            tag.authenticate(key)?;
+        }
+        dot_rev.create_annotated_tag(&tag, self.force).await?;
+        operation.record(description)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,26 @@
+use crate::cmd_operation::with_working_tree;
+use clap::Args;
+use std::{env::current_dir, path::Path};
+use ys_core::{initialize::DotYuanShenClient, YsError};
+
+/// 撤销最近一次操作, 将所有引用和当前世界线恢复到它之前的状态
+///
+/// 连续撤销会依次撤销更早的操作, 撤销过的操作可以用 `ys redo` 重做.
+#[derive(Debug, Args)]
+pub struct YuanShenUndo {
+    /// 丢弃工作目录中未提交的修改
+    #[arg(long, short)]
+    force: bool,
+}
+
+impl YuanShenUndo {
+    pub async fn undo(self, repo: Option<&Path>) -> Result<(), YsError> {
+        let here = current_dir()?;
+        let ys = DotYuanShenClient::from_env(&here, repo)?;
+        match with_working_tree(&ys, self.force, |ys| ys.undo()).await? {
+            Some(entry) => println!("已撤销操作 {}: {}", entry.id, entry.description),
+            None => println!("没有可以撤销的操作"),
+        }
+        Ok(())
+    }
+}
@@ -1,17 +1,30 @@
 #![feature(fs_try_exists)]
 
 pub use crate::{
-    cmd_branch::YuanShenBranch, cmd_checkout::YuanShenCheckout, cmd_commit::YuanShenCommit, cmd_diff::YuanShenDifference,
-    cmd_init::YuanShenInitialize, cmd_merge::YuanShenMerge, cmd_orphan::YuanShenOrphan, cmd_rebase::YuanShenRebase,
-    cmd_squash::YuanShenSquash,
+    cmd_add::YuanShenAdd, cmd_branch::YuanShenBranch, cmd_check_ignore::YuanShenCheckIgnore, cmd_checkout::YuanShenCheckout,
+    cmd_commit::YuanShenCommit, cmd_config::YuanShenConfig, cmd_diff::YuanShenDifference, cmd_init::YuanShenInitialize,
+    cmd_merge::YuanShenMerge, cmd_operation::YuanShenOperation, cmd_orphan::YuanShenOrphan, cmd_rebase::YuanShenRebase,
+    cmd_redo::YuanShenRedo, cmd_reflog::YuanShenReflog, cmd_remove::YuanShenRemove, cmd_reset::YuanShenReset,
+    cmd_squash::YuanShenSquash, cmd_status::YuanShenStatus, cmd_tag::YuanShenTag, cmd_undo::YuanShenUndo,
 };
 
+mod cmd_add;
 mod cmd_branch;
+mod cmd_check_ignore;
 mod cmd_checkout;
 mod cmd_commit;
+mod cmd_config;
 mod cmd_diff;
 mod cmd_init;
 mod cmd_merge;
+mod cmd_operation;
 mod cmd_orphan;
 mod cmd_rebase;
+mod cmd_redo;
+mod cmd_reflog;
+mod cmd_remove;
+mod cmd_reset;
 mod cmd_squash;
+mod cmd_status;
+mod cmd_tag;
+mod cmd_undo;
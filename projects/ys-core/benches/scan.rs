@@ -0,0 +1,70 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use std::{
+    fs::{create_dir_all, read, read_dir, write},
+    path::Path,
+};
+use tempfile::TempDir;
+use tokio::runtime::Runtime;
+use ys_core::{IgnoreRules, MemoryObjectStore, ObjectID, ObjectStore, SnapShotDirectory, StatCache};
+
+/// 生成一个包含大量小文件和少量大文件的工作目录
+fn synthetic_tree() -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for d in 0..16 {
+        let sub = dir.path().join(format!("dir-{}", d));
+        create_dir_all(&sub).unwrap();
+        for f in 0..64 {
+            write(sub.join(format!("file-{}.txt", f)), format!("{}-{}", d, f).repeat(256)).unwrap();
+        }
+    }
+    for f in 0..4 {
+        write(dir.path().join(format!("large-{}.bin", f)), vec![f as u8; 8 * 1024 * 1024]).unwrap();
+    }
+    dir
+}
+
+/// 逐个读取并哈希文件的参考实现
+async fn serial_scan(dir: &Path, store: &mut MemoryObjectStore) -> usize {
+    let mut count = 0;
+    for item in read_dir(dir).unwrap() {
+        let item = item.unwrap();
+        if item.file_type().unwrap().is_dir() {
+            count += Box::pin(serial_scan(&item.path(), store)).await;
+        }
+        else {
+            let bytes = read(item.path()).unwrap();
+            let id = ObjectID::from(blake3::hash(&bytes));
+            store.put_prehashed(id, &bytes).await.unwrap();
+            count += 1;
+        }
+    }
+    count
+}
+
+fn bench_scan(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let tree = synthetic_tree();
+    let ignores = IgnoreRules::default();
+    let mut group = c.benchmark_group("scan");
+    group.sample_size(10);
+    group.bench_function("serial", |b| {
+        b.iter_batched(
+            MemoryObjectStore::new,
+            |mut store| runtime.block_on(serial_scan(tree.path(), &mut store)),
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("parallel", |b| {
+        b.iter_batched(
+            MemoryObjectStore::new,
+            |mut store| {
+                runtime.block_on(SnapShotDirectory::new(tree.path(), &ignores, &mut store, &mut StatCache::default())).unwrap()
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_scan);
+criterion_main!(benches);
@@ -21,6 +21,10 @@ impl YsError {
     pub fn path_error<P: Into<PathBuf>>(error: std::io::Error, path: P) -> Self {
         Self { kind: Box::new(YsErrorKind::IO { error, path: Some(path.into()) }) }
     }
+    /// 错误的具体类型。
+    pub fn kind(&self) -> &YsErrorKind {
+        &self.kind
+    }
 }
 
 impl Error for YsError {}
@@ -60,22 +64,215 @@ pub enum YsErrorKind {
     InvalidObject {
         message: String,
     },
+    /// 目录树中的条目名称不安全, 写出时可能会越过工作目录或破坏 `.ys` 仓库。
+    InvalidEntryName {
+        /// 出问题的条目名称。
+        name: String,
+        /// 名称被拒绝的原因。
+        reason: &'static str,
+    },
+    /// 命令行或 API 给出的路径无法使用。
+    InvalidPath {
+        /// 出问题的路径。
+        path: PathBuf,
+        /// 路径被拒绝的原因。
+        reason: &'static str,
+    },
+    /// 命令行参数的个数或组合不合法。
+    InvalidArguments {
+        /// 参数被拒绝的原因。
+        reason: &'static str,
+    },
+    /// 无法解析的修订号, 既不是世界线名称, 也不对应任何快照。
+    UnknownRevision {
+        /// 给出的修订号。
+        revision: String,
+    },
+    /// 修订号不符合语法。
+    InvalidRevision {
+        /// 给出的修订号。
+        revision: String,
+        /// 修订号被拒绝的原因。
+        reason: &'static str,
+    },
+    /// 缩写的快照 id 对应多个快照。
+    AmbiguousRevision {
+        /// 给出的修订号。
+        revision: String,
+        /// 所有匹配的快照 id。
+        candidates: Vec<ObjectID>,
+    },
+    /// 世界线或标签的名称不合法。
+    InvalidReferenceName {
+        /// 出问题的名称。
+        name: String,
+        /// 名称被拒绝的原因。
+        reason: &'static str,
+    },
+    /// 要创建的引用已经存在。
+    ReferenceExists {
+        /// 引用的名称, 例如 `tags/v1.0`。
+        name: String,
+    },
+    /// 要读取或删除的引用不存在。
+    MissingReference {
+        /// 引用的名称, 例如 `tags/v1.0`。
+        name: String,
+    },
+    /// 引用文件的内容无法解析, 或者由更新版本的程序写入。
+    InvalidReference {
+        /// 引用文件的位置。
+        path: PathBuf,
+        /// 无法读取的原因。
+        reason: String,
+    },
+    /// 引用的锁文件已经存在, 其他进程正在修改同一个引用。
+    ReferenceLocked {
+        /// 引用的名称, 例如 `branches/main`。
+        name: String,
+        /// 锁文件的位置, 确认没有其他进程后可以手动删除。
+        lock: PathBuf,
+    },
+    /// 引用的当前值与事务期望的不同, 通常是因为其他进程先一步修改了它。
+    StaleReference {
+        /// 引用的名称, 例如 `branches/main`。
+        name: String,
+        /// 期望的值, `None` 表示期望引用不存在。
+        expected: Option<ObjectID>,
+        /// 实际的值, `None` 表示引用不存在。
+        actual: Option<ObjectID>,
+    },
+    /// 操作日志中没有给定序号的操作。
+    UnknownOperation {
+        /// 操作的序号。
+        id: usize,
+    },
+    /// 要删除的世界线中有尚未并入当前世界线的快照。
+    UnmergedBranch {
+        /// 世界线的名称。
+        name: String,
+        /// 世界线指向的快照。
+        tip: ObjectID,
+    },
+    /// 不能删除或覆盖当前所在的世界线。
+    CheckedOutBranch {
+        /// 世界线的名称。
+        name: String,
+    },
+    /// `HEAD` 处于分离状态, 没有当前所在的世界线。
+    DetachedHead {
+        /// `HEAD` 指向的快照。
+        id: ObjectID,
+    },
+    /// 工作目录中存在尚未提交的修改, 继续操作会覆盖它们。
+    UncommittedChanges {
+        /// 工作目录的位置。
+        path: PathBuf,
+    },
+    /// 配置文件无法解析, 或者配置项的名称或取值不合法。
+    InvalidConfig {
+        /// 出问题的配置文件, 来自命令行或环境变量时为 `None`。
+        path: Option<PathBuf>,
+        /// 配置被拒绝的原因。
+        reason: String,
+    },
+    /// 从给定目录向上查找, 直到根目录或文件系统边界都没有找到 `.ys` 仓库。
+    RepositoryNotFound {
+        /// 开始查找的目录。
+        path: PathBuf,
+    },
+    /// 合并时两方对同一批文件做了不同的修改。
+    MergeConflict {
+        /// 冲突的路径, 以 `/` 分隔。
+        paths: Vec<String>,
+    },
 }
 
 impl Display for YsErrorKind {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::IO { .. } => {
-                todo!()
-            }
-            Self::Serde { .. } => {
-                todo!()
+            Self::IO { error, path } => match path {
+                Some(path) => write!(f, "{:?}: {}", path, error),
+                None => write!(f, "{}", error),
+            },
+            Self::Serde { error } => {
+                write!(f, "序列化错误: {}", error)
             }
             Self::MissingObject { id } => {
                 write!(f, "找不到对象: {}", id)
             }
-            Self::InvalidObject { .. } => {
-                todo!()
+            Self::InvalidObject { message } => {
+                write!(f, "无效的对象: {}", message)
+            }
+            Self::InvalidEntryName { name, reason } => {
+                write!(f, "非法的条目名称 {:?}: {}", name, reason)
+            }
+            Self::InvalidPath { path, reason } => {
+                write!(f, "非法的路径 {:?}: {}", path, reason)
+            }
+            Self::InvalidArguments { reason } => {
+                write!(f, "参数不合法: {}", reason)
+            }
+            Self::UnknownRevision { revision } => {
+                write!(f, "找不到修订 {:?}", revision)
+            }
+            Self::InvalidRevision { revision, reason } => {
+                write!(f, "非法的修订 {:?}: {}", revision, reason)
+            }
+            Self::AmbiguousRevision { revision, candidates } => {
+                write!(f, "修订 {:?} 对应多个快照:", revision)?;
+                for id in candidates {
+                    write!(f, "\n    {}", id)?;
+                }
+                Ok(())
+            }
+            Self::InvalidReferenceName { name, reason } => {
+                write!(f, "非法的引用名称 {:?}: {}", name, reason)
+            }
+            Self::ReferenceExists { name } => {
+                write!(f, "引用 {} 已经存在", name)
+            }
+            Self::MissingReference { name } => {
+                write!(f, "找不到引用 {}", name)
+            }
+            Self::InvalidReference { path, reason } => {
+                write!(f, "无法读取引用文件 {:?}: {}", path, reason)
+            }
+            Self::ReferenceLocked { name, lock } => {
+                write!(f, "引用 {} 正在被其他进程修改, 锁文件 {:?} 已经存在", name, lock)
+            }
+            Self::StaleReference { name, expected, actual } => {
+                let show = |id: &Option<ObjectID>| id.map(|id| id.to_string()).unwrap_or_else(|| "不存在".to_string());
+                write!(f, "引用 {} 已被修改, 期望 {}, 实际 {}", name, show(expected), show(actual))
+            }
+            Self::UnknownOperation { id } => {
+                write!(f, "操作日志中没有序号为 {} 的操作", id)
+            }
+            Self::UnmergedBranch { name, tip } => {
+                write!(f, "世界线 {} 指向的快照 {} 尚未并入当前世界线", name, tip)
+            }
+            Self::CheckedOutBranch { name } => {
+                write!(f, "不能删除或覆盖当前所在的世界线 {}", name)
+            }
+            Self::DetachedHead { id } => {
+                write!(f, "HEAD 分离于快照 {}, 不在任何世界线上", id)
+            }
+            Self::UncommittedChanges { path } => {
+                write!(f, "工作目录 {:?} 中存在未提交的修改", path)
+            }
+            Self::InvalidConfig { path, reason } => match path {
+                Some(path) => write!(f, "无效的配置 {:?}: {}", path, reason),
+                None => write!(f, "无效的配置: {}", reason),
+            },
+            Self::RepositoryNotFound { path } => {
+                write!(f, "在 {:?} 及其上级目录中找不到 `.ys` 仓库", path)
+            }
+            Self::MergeConflict { paths } => {
+                write!(f, "合并时以下文件存在冲突:")?;
+                for path in paths {
+                    write!(f, "\n    {}", path)?;
+                }
+                Ok(())
             }
         }
     }
@@ -15,14 +15,33 @@ const DOT_YUAN_SHEN: &'static str = ".ys";
 pub use crate::{
     errors::{Result, YsError, YsErrorKind},
     objects::{
+        attributes::{looks_binary, AttributeRule, AttributeValue, PathAttributes, ResolvedAttributes, ATTRIBUTES_FILE},
         author_id::AuthorID,
-        ignore_rules::IgnoreRules,
+        config::{
+            check_config_key, set_config_value, unset_config_value, ConfigLayer, ConfigSource, CoreConfig, InitConfig,
+            LayeredConfig, UserConfig, YuanShenConfig, CONFIG_FILE, CONFIG_KEYS,
+        },
+        ignore_rules::{IgnoreRule, IgnoreRules, IGNORE_FILE},
         object_id::ObjectID,
         object_store::{file_system::LocalObjectStore, in_memory::MemoryObjectStore, ObjectStore},
     },
     snapshot::{
+        branch::{BranchFilter, BranchInfo},
         differences,
         directory::{DirectoryEntry, SnapShotDirectory},
-        initialize, SnapShot, SnapShotData,
+        head::Head,
+        index::StagingIndex,
+        initialize,
+        materialize::{MaterializeAction, MaterializeReport},
+        merge::TreeMerge,
+        operation::{OperationEntry, OperationKind, PendingOperation, RepositoryState},
+        patch::{diff_lines, unified_hunks, FilePatch, Hunk, LineChange},
+        reflog::{ReflogEntry, REFLOG_EXPIRY},
+        revision::{Ancestry, DiffSide, Revision, RevisionSpec},
+        stat_cache::{FileTime, StatCache, StatEntry},
+        status::WorkingStatus,
+        tag::{peel_tag, TagObject},
+        transaction::{RefExpectation, RefTransaction},
+        SnapShot, SnapShotData,
     },
 };
@@ -0,0 +1,206 @@
+use super::*;
+use crate::IgnoreRule;
+use std::{fs::read_to_string, io::ErrorKind};
+
+/// 每个目录中的路径属性文件名
+pub const ATTRIBUTES_FILE: &str = ".ys.attributes";
+
+/// 路径属性, 语法与 gitattributes 相同
+///
+/// 每一行是一个路径模式和若干属性: `attr` 设置属性, `-attr` 取消属性, `!attr` 恢复为未指定,
+/// `attr=value` 为属性赋值. `binary` 是 `binary -diff -merge -text` 的简写.
+///
+/// 规则按优先级从低到高排列, 与忽略规则一样从工作目录开始逐层进入各个目录的 `.ys.attributes`,
+/// 后出现的规则覆盖先出现的规则.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct PathAttributes {
+    rules: Vec<AttributeRule>,
+}
+
+/// `.ys.attributes` 中的一行规则
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct AttributeRule {
+    /// 路径模式, 匹配规则与忽略规则相同
+    pub pattern: IgnoreRule,
+    /// 按顺序应用的属性, `None` 表示恢复为未指定
+    pub attributes: Vec<(String, Option<AttributeValue>)>,
+}
+
+/// 属性的取值
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub enum AttributeValue {
+    /// `attr`
+    Set,
+    /// `-attr`
+    Unset,
+    /// `attr=value`
+    Value(String),
+}
+
+/// 某个路径最终生效的属性
+#[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResolvedAttributes {
+    /// 所有被指定的属性
+    pub values: BTreeMap<String, AttributeValue>,
+}
+
+impl PathAttributes {
+    /// 解析 `.ys.attributes` 文件的内容
+    pub fn parse(text: &str) -> Self {
+        let mut attributes = Self::default();
+        attributes.extend(text, "", None);
+        attributes
+    }
+    /// 追加一个属性文件中的规则, `base` 是属性文件所在的目录相对于工作目录的路径
+    pub fn extend(&mut self, text: &str, base: &str, origin: Option<PathBuf>) {
+        for (index, line) in text.lines().enumerate() {
+            let mut parts = line.split_whitespace();
+            let Some(pattern) = parts.next().filter(|pattern| !pattern.starts_with('#'))
+            else {
+                continue;
+            };
+            let Some(mut pattern) = IgnoreRule::parse(pattern, index + 1)
+            else {
+                continue;
+            };
+            if pattern.negated {
+                tracing::warn!("属性文件不支持以 `!` 开头的路径模式: {}", line);
+                continue;
+            }
+            pattern.source = line.to_string();
+            pattern.base = base.to_string();
+            pattern.origin = origin.clone();
+            let attributes = parts.map(parse_attribute).collect();
+            self.rules.push(AttributeRule { pattern, attributes });
+        }
+    }
+    /// 追加属性文件中的规则, 文件不存在时什么也不做
+    pub fn extend_file(&mut self, path: &Path, base: &str) -> Result<(), YsError> {
+        match read_to_string(path) {
+            Ok(text) => {
+                self.extend(&text, base, Some(path.to_path_buf()));
+                Ok(())
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(YsError::path_error(e, path)),
+        }
+    }
+    /// 进入一个目录, 返回对其中的路径生效的规则
+    ///
+    /// `prefix` 是目录相对于工作目录的路径, 目录中没有 `.ys.attributes` 时直接返回自身.
+    pub fn enter(&self, directory: &Path, prefix: &str) -> Result<Cow<'_, Self>, YsError> {
+        let path = directory.join(ATTRIBUTES_FILE);
+        if !path.is_file() {
+            return Ok(Cow::Borrowed(self));
+        }
+        let mut attributes = self.clone();
+        attributes.extend_file(&path, prefix)?;
+        Ok(Cow::Owned(attributes))
+    }
+    /// 解析得到的规则, 不包含空行和注释
+    pub fn rules(&self) -> &[AttributeRule] {
+        &self.rules
+    }
+    /// 计算文件最终生效的属性
+    ///
+    /// `relative` 是相对于工作目录的路径, 以 `/` 分隔.
+    pub fn resolve(&self, relative: &str) -> ResolvedAttributes {
+        let mut resolved = ResolvedAttributes::default();
+        for rule in self.rules.iter().filter(|rule| rule.pattern.matches(relative, false)) {
+            for (name, value) in rule.attributes.iter() {
+                resolved.apply(name, value.clone());
+            }
+        }
+        resolved
+    }
+}
+
+impl ResolvedAttributes {
+    /// 读取属性的值, 未指定时返回 `None`
+    pub fn get(&self, name: &str) -> Option<&AttributeValue> {
+        self.values.get(name)
+    }
+    /// 文件被标记为二进制, 不进行文本差异比较, 合并和换行符转换
+    pub fn is_binary(&self) -> bool {
+        matches!(self.get("binary"), Some(AttributeValue::Set)) || matches!(self.get("diff"), Some(AttributeValue::Unset))
+    }
+    /// 是否在提交时将 CRLF 换行符统一为 LF, 写出时再按 `eol` 转换
+    ///
+    /// `text=auto` 或者只指定了 `eol` 时根据内容判断.
+    pub fn normalize_line_endings(&self, content: &[u8]) -> bool {
+        match self.get("text") {
+            Some(AttributeValue::Set) => true,
+            Some(AttributeValue::Value(value)) if value == "auto" => !looks_binary(content),
+            None if self.end_of_line().is_some() => !looks_binary(content),
+            _ => false,
+        }
+    }
+    /// 写出文件时使用的换行符, `eol=lf` 或 `eol=crlf`
+    pub fn end_of_line(&self) -> Option<&str> {
+        match self.get("eol") {
+            Some(AttributeValue::Value(value)) => Some(value),
+            _ => None,
+        }
+    }
+    /// 按提交时的规则转换文件内容, 返回写入储存库的字节
+    pub fn to_stored(&self, content: Vec<u8>) -> Vec<u8> {
+        if !self.normalize_line_endings(&content) || !content.contains(&b'\r') {
+            return content;
+        }
+        let mut output = Vec::with_capacity(content.len());
+        for (index, byte) in content.iter().enumerate() {
+            if *byte == b'\r' && content.get(index + 1) == Some(&b'\n') {
+                continue;
+            }
+            output.push(*byte);
+        }
+        output
+    }
+    /// 按写出时的规则转换储存库中的内容, 返回写入工作目录的字节
+    ///
+    /// 储存库中的文本文件总是以 LF 结尾, `eol=crlf` 时将其转换为 CRLF.
+    pub fn to_working(&self, content: Vec<u8>) -> Vec<u8> {
+        if self.end_of_line() != Some("crlf") || !self.normalize_line_endings(&content) {
+            return content;
+        }
+        let mut output = Vec::with_capacity(content.len() + content.len() / 16);
+        for (index, byte) in content.iter().enumerate() {
+            if *byte == b'\n' && (index == 0 || content[index - 1] != b'\r') {
+                output.push(b'\r');
+            }
+            output.push(*byte);
+        }
+        output
+    }
+    fn apply(&mut self, name: &str, value: Option<AttributeValue>) {
+        if name == "binary" && value == Some(AttributeValue::Set) {
+            for name in ["diff", "merge", "text"] {
+                self.values.insert(name.to_string(), AttributeValue::Unset);
+            }
+        }
+        match value {
+            Some(value) => self.values.insert(name.to_string(), value),
+            None => self.values.remove(name),
+        };
+    }
+}
+
+/// 内容的前 8000 字节中包含 NUL 时视为二进制
+pub fn looks_binary(content: &[u8]) -> bool {
+    content.iter().take(8000).any(|byte| *byte == 0)
+}
+
+fn parse_attribute(attribute: &str) -> (String, Option<AttributeValue>) {
+    if let Some(name) = attribute.strip_prefix('-') {
+        (name.to_string(), Some(AttributeValue::Unset))
+    }
+    else if let Some(name) = attribute.strip_prefix('!') {
+        (name.to_string(), None)
+    }
+    else if let Some((name, value)) = attribute.split_once('=') {
+        (name.to_string(), Some(AttributeValue::Value(value.to_string())))
+    }
+    else {
+        (attribute.to_string(), Some(AttributeValue::Set))
+    }
+}
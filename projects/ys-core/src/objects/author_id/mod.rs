@@ -37,13 +37,37 @@ impl<'de> Deserialize<'de> for AuthorID {
     where
         D: Deserializer<'de>,
     {
-        match String::deserialize(deserializer) {
-            Ok(_) => {
-                todo!()
-            }
-            Err(_) => {
-                todo!()
-            }
+        /// 序列化时写出的字节数组, 或者手写的十六进制字符串
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum AuthorRepr {
+            Bytes([u8; 32]),
+            Hex(String),
         }
+        match AuthorRepr::deserialize(deserializer)? {
+            AuthorRepr::Bytes(bytes) => Ok(Self { hash256: Hash::from_bytes(bytes) }),
+            AuthorRepr::Hex(hex) => Ok(Self { hash256: Hash::from_hex(hex).map_err(serde::de::Error::custom)? }),
+        }
+    }
+}
+
+impl AuthorID {
+    /// 当前系统用户, 由环境变量 `USER` 或 `USERNAME` 计算
+    pub fn current_user() -> Self {
+        let name = std::env::var("USER").or_else(|_| std::env::var("USERNAME")).unwrap_or_default();
+        Self::from(name.as_str())
+    }
+}
+
+impl From<&str> for AuthorID {
+    /// 由作者的标识, 例如 `name <email>`, 计算作者 id
+    fn from(identity: &str) -> Self {
+        Self { hash256: blake3::hash(identity.as_bytes()) }
+    }
+}
+
+impl Display for AuthorID {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.hash256.to_hex())
     }
 }
@@ -0,0 +1,393 @@
+use super::*;
+use crate::{snapshot::transaction::replace_file, AuthorID};
+use std::{
+    env::{split_paths, var_os},
+    fmt::Formatter,
+    fs::{create_dir_all, read_to_string},
+    io::ErrorKind,
+};
+use toml::{Table, Value};
+
+/// 每一层配置目录中的配置文件名
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// 所有可以设置的配置项, 以及覆盖它们的环境变量
+pub const CONFIG_KEYS: &[(&str, &str)] = &[
+    ("init.default-branch", "YS_DEFAULT_BRANCH"),
+    ("user.name", "YS_USER_NAME"),
+    ("user.email", "YS_USER_EMAIL"),
+    ("core.compression-level", "YS_COMPRESSION_LEVEL"),
+    ("core.ignore-files", "YS_IGNORE_FILES"),
+];
+
+/// 合并所有配置层之后得到的配置
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct YuanShenConfig {
+    /// 创建仓库时使用的设置
+    pub init: InitConfig,
+    /// 提交者的身份
+    pub user: UserConfig,
+    /// 储存库和工作目录的设置
+    pub core: CoreConfig,
+}
+
+/// `[init]` 中的配置项
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct InitConfig {
+    /// 初始世界线的名称, 没有设置时为 `master`
+    pub default_branch: Option<String>,
+}
+
+/// `[user]` 中的配置项
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct UserConfig {
+    /// 提交者的名字
+    pub name: Option<String>,
+    /// 提交者的邮箱
+    pub email: Option<String>,
+}
+
+/// `[core]` 中的配置项
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct CoreConfig {
+    /// 写入对象储存库时的 zlib 压缩级别, 从 0 到 9, 0 或者没有设置时不压缩
+    pub compression_level: Option<u8>,
+    /// 额外的忽略规则文件, 相对路径以工作目录为起点
+    pub ignore_files: Vec<PathBuf>,
+}
+
+impl YuanShenConfig {
+    /// 取值超出允许的范围时返回原因
+    fn invalid_reason(&self) -> Option<&'static str> {
+        if self.core.compression_level.is_some_and(|level| level > 9) {
+            return Some("core.compression-level 必须在 0 到 9 之间");
+        }
+        None
+    }
+    /// 默认的初始世界线名称
+    pub fn default_branch(&self) -> &str {
+        self.init.default_branch.as_deref().unwrap_or("master")
+    }
+}
+
+impl UserConfig {
+    /// 由名字和邮箱计算作者 id, 两者都没有设置时返回 `None`
+    pub fn author_id(&self) -> Option<AuthorID> {
+        let identity = match (&self.name, &self.email) {
+            (Some(name), Some(email)) => format!("{} <{}>", name, email),
+            (Some(name), None) => name.clone(),
+            (None, Some(email)) => format!("<{}>", email),
+            (None, None) => return None,
+        };
+        Some(AuthorID::from(identity.as_str()))
+    }
+}
+
+/// 配置的来源, 按优先级从低到高排列
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ConfigLayer {
+    /// 对所有用户生效, 位于 `/etc/yuan-shen/config.toml`, 可以用 `YS_CONFIG_SYSTEM` 指定
+    System,
+    /// 对当前用户生效, 位于 `~/.config/yuan-shen/config.toml`, 可以用 `YS_CONFIG_USER` 指定
+    User,
+    /// 对当前仓库生效, 位于工作目录的 `.config/yuan-shen/config.toml`
+    Repository,
+    /// 由 [`CONFIG_KEYS`] 中的环境变量给出
+    Environment,
+}
+
+impl Display for ConfigLayer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::System => f.write_str("system"),
+            Self::User => f.write_str("user"),
+            Self::Repository => f.write_str("repository"),
+            Self::Environment => f.write_str("environment"),
+        }
+    }
+}
+
+impl ConfigLayer {
+    /// 没有指定层时修改配置项写入的层
+    ///
+    /// 仓库的配置文件位于工作目录中, 会随项目一起提交, 因此 `user.*` 这样的个人身份写入用户的配置.
+    pub fn default_for(key: &str) -> Self {
+        if key.starts_with("user.") {
+            Self::User
+        }
+        else {
+            Self::Repository
+        }
+    }
+    /// 系统配置文件的位置
+    pub fn system_path() -> Option<PathBuf> {
+        if let Some(path) = var_os("YS_CONFIG_SYSTEM").filter(|path| !path.is_empty()) {
+            return Some(PathBuf::from(path));
+        }
+        if cfg!(windows) {
+            Some(PathBuf::from(var_os("PROGRAMDATA")?).join("yuan-shen").join(CONFIG_FILE))
+        }
+        else {
+            Some(PathBuf::from("/etc/yuan-shen").join(CONFIG_FILE))
+        }
+    }
+    /// 用户配置文件的位置
+    pub fn user_path() -> Option<PathBuf> {
+        if let Some(path) = var_os("YS_CONFIG_USER").filter(|path| !path.is_empty()) {
+            return Some(PathBuf::from(path));
+        }
+        Some(user_config_directory()?.join(CONFIG_FILE))
+    }
+}
+
+/// 用户的配置目录, 位于 `$XDG_CONFIG_HOME/yuan-shen` 或 `~/.config/yuan-shen`
+pub(crate) fn user_config_directory() -> Option<PathBuf> {
+    let config = match var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(var_os("HOME").or_else(|| var_os("USERPROFILE"))?).join(".config"),
+    };
+    Some(config.join("yuan-shen"))
+}
+
+/// 一层配置的内容
+#[derive(Clone, Debug)]
+pub struct ConfigSource {
+    /// 配置所在的层
+    pub layer: ConfigLayer,
+    /// 配置文件的位置, 环境变量没有文件
+    pub path: Option<PathBuf>,
+    /// 配置文件中的所有表和值
+    pub table: Table,
+}
+
+/// 按优先级从低到高叠加的多层配置
+///
+/// 同一个配置项以优先级最高的一层为准, 表会逐项合并而不是整体覆盖.
+#[derive(Clone, Debug, Default)]
+pub struct LayeredConfig {
+    sources: Vec<ConfigSource>,
+}
+
+impl LayeredConfig {
+    /// 读取系统, 用户和仓库的配置文件, 然后应用环境变量
+    ///
+    /// `repository` 是仓库的配置目录, 即工作目录中的 `.config/yuan-shen`, 在仓库之外时为 `None`.
+    pub fn load(repository: Option<&Path>) -> Result<Self, YsError> {
+        let mut config = Self::default();
+        if let Some(path) = ConfigLayer::system_path() {
+            config.add_file(ConfigLayer::System, &path)?;
+        }
+        if let Some(path) = ConfigLayer::user_path() {
+            config.add_file(ConfigLayer::User, &path)?;
+        }
+        if let Some(directory) = repository {
+            config.add_file(ConfigLayer::Repository, &directory.join(CONFIG_FILE))?;
+        }
+        config.add_environment(|name| var_os(name).and_then(|value| value.into_string().ok()))?;
+        Ok(config)
+    }
+    /// 叠加一个配置文件, 文件不存在时什么也不做
+    pub fn add_file(&mut self, layer: ConfigLayer, path: &Path) -> Result<(), YsError> {
+        let table = read_config_file(path)?;
+        self.sources.push(ConfigSource { layer, path: Some(path.to_path_buf()), table });
+        Ok(())
+    }
+    /// 叠加 [`CONFIG_KEYS`] 中设置了的环境变量
+    pub fn add_environment(&mut self, lookup: impl Fn(&str) -> Option<String>) -> Result<(), YsError> {
+        let mut table = Table::new();
+        for (key, variable) in CONFIG_KEYS {
+            if let Some(text) = lookup(variable) {
+                insert_key(&mut table, key, typed_value(key, &text)?);
+            }
+        }
+        self.sources.push(ConfigSource { layer: ConfigLayer::Environment, path: None, table });
+        Ok(())
+    }
+    /// 所有配置层, 按优先级从低到高排列
+    pub fn sources(&self) -> &[ConfigSource] {
+        &self.sources
+    }
+    /// 只保留给定的一层
+    pub fn only(mut self, layer: ConfigLayer) -> Self {
+        self.sources.retain(|source| source.layer == layer);
+        self
+    }
+    /// 读取配置项最终生效的值, 以及它所在的层
+    pub fn get(&self, key: &str) -> Option<(ConfigLayer, &Value)> {
+        self.sources.iter().rev().find_map(|source| Some((source.layer, lookup_key(&source.table, key)?)))
+    }
+    /// 所有设置了的配置项最终生效的值, 按名称排序
+    pub fn entries(&self) -> BTreeMap<String, (ConfigLayer, &Value)> {
+        let mut entries = BTreeMap::new();
+        for source in self.sources.iter() {
+            flatten(&source.table, "", &mut |key, value| {
+                entries.insert(key, (source.layer, value));
+            });
+        }
+        entries
+    }
+    /// 合并所有配置层, 得到类型化的配置
+    pub fn resolve(&self) -> Result<YuanShenConfig, YsError> {
+        let mut merged = Table::new();
+        for source in self.sources.iter() {
+            parse_config(source.table.clone(), source.path.as_deref())?;
+            merge_table(&mut merged, &source.table);
+        }
+        parse_config(merged, None)
+    }
+}
+
+/// 在配置文件中设置一个配置项, 文件或所在目录不存在时创建它们
+///
+/// `text` 首先按 TOML 的值解析, 例如 `5` 或 `["a", "b"]`, 不符合配置项的类型时视为字符串.
+pub fn set_config_value(path: &Path, key: &str, text: &str) -> Result<(), YsError> {
+    let value = typed_value(key, text)?;
+    let mut table = read_config_file(path)?;
+    insert_key(&mut table, key, value);
+    write_config_file(path, &table)
+}
+
+/// 从配置文件中删除一个配置项, 返回它是否存在
+pub fn unset_config_value(path: &Path, key: &str) -> Result<bool, YsError> {
+    check_config_key(key)?;
+    let mut table = read_config_file(path)?;
+    let Some((parent, name)) = key.rsplit_once('.')
+    else {
+        return Ok(false);
+    };
+    let removed = match lookup_key_mut(&mut table, parent) {
+        Some(Value::Table(section)) => {
+            let removed = section.remove(name).is_some();
+            if section.is_empty() {
+                table.remove(parent);
+            }
+            removed
+        }
+        _ => false,
+    };
+    if removed {
+        write_config_file(path, &table)?;
+    }
+    Ok(removed)
+}
+
+/// 检查配置项的名称, 未知的名称多半是拼写错误
+pub fn check_config_key(key: &str) -> Result<(), YsError> {
+    if CONFIG_KEYS.iter().any(|(known, _)| *known == key) {
+        return Ok(());
+    }
+    Err(YsErrorKind::InvalidConfig { path: None, reason: format!("未知的配置项 {}", key) })?
+}
+
+/// 将命令行或环境变量中的文本转换为配置项的值
+fn typed_value(key: &str, text: &str) -> Result<Value, YsError> {
+    check_config_key(key)?;
+    let mut candidates = vec![];
+    if key == "core.ignore-files" && !text.trim_start().starts_with('[') {
+        let paths = split_paths(text).map(|path| Value::String(path.to_string_lossy().into_owned()));
+        candidates.push(Value::Array(paths.collect()));
+    }
+    if let Ok(mut table) = format!("value = {}", text).parse::<Table>() {
+        candidates.extend(table.remove("value"));
+    }
+    candidates.push(Value::String(text.to_string()));
+    let mut error = None;
+    for value in candidates {
+        let mut table = Table::new();
+        insert_key(&mut table, key, value.clone());
+        match parse_config(table, None) {
+            Ok(_) => return Ok(value),
+            Err(e) => error = Some(e),
+        }
+    }
+    Err(error.expect("at least one candidate"))
+}
+
+fn read_config_file(path: &Path) -> Result<Table, YsError> {
+    let text = match read_to_string(path) {
+        Ok(o) => o,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Table::new()),
+        Err(e) => Err(YsError::path_error(e, path))?,
+    };
+    text.parse::<Table>()
+        .map_err(|e| YsErrorKind::InvalidConfig { path: Some(path.to_path_buf()), reason: e.to_string() }.into())
+}
+
+fn write_config_file(path: &Path, table: &Table) -> Result<(), YsError> {
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).map_err(|e| YsError::path_error(e, parent))?;
+    }
+    let text = toml::to_string_pretty(table)
+        .map_err(|e| YsErrorKind::InvalidConfig { path: Some(path.to_path_buf()), reason: e.to_string() })?;
+    replace_file(path, text.as_bytes())
+}
+
+/// 将表转换为类型化的配置, 并检查取值的范围
+fn parse_config(table: Table, path: Option<&Path>) -> Result<YuanShenConfig, YsError> {
+    let invalid = |reason: String| YsErrorKind::InvalidConfig { path: path.map(Path::to_path_buf), reason };
+    let config = YuanShenConfig::deserialize(Value::Table(table)).map_err(|e| invalid(e.to_string()))?;
+    match config.invalid_reason() {
+        Some(reason) => Err(invalid(reason.to_string()))?,
+        None => Ok(config),
+    }
+}
+
+fn lookup_key<'a>(table: &'a Table, key: &str) -> Option<&'a Value> {
+    let (first, rest) = key.split_once('.').unwrap_or((key, ""));
+    match (table.get(first)?, rest) {
+        (value, "") => Some(value),
+        (Value::Table(inner), rest) => lookup_key(inner, rest),
+        _ => None,
+    }
+}
+
+fn lookup_key_mut<'a>(table: &'a mut Table, key: &str) -> Option<&'a mut Value> {
+    let (first, rest) = key.split_once('.').unwrap_or((key, ""));
+    match (table.get_mut(first)?, rest) {
+        (value, "") => Some(value),
+        (Value::Table(inner), rest) => lookup_key_mut(inner, rest),
+        _ => None,
+    }
+}
+
+fn insert_key(table: &mut Table, key: &str, value: Value) {
+    match key.split_once('.') {
+        None => {
+            table.insert(key.to_string(), value);
+        }
+        Some((first, rest)) => {
+            let inner = table.entry(first).or_insert_with(|| Value::Table(Table::new()));
+            if !inner.is_table() {
+                *inner = Value::Table(Table::new());
+            }
+            if let Value::Table(inner) = inner {
+                insert_key(inner, rest, value);
+            }
+        }
+    }
+}
+
+fn merge_table(base: &mut Table, layer: &Table) {
+    for (key, value) in layer {
+        match (base.get_mut(key), value) {
+            (Some(Value::Table(base)), Value::Table(layer)) => merge_table(base, layer),
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+fn flatten<'a>(table: &'a Table, prefix: &str, visit: &mut impl FnMut(String, &'a Value)) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+        match value {
+            Value::Table(inner) => flatten(inner, &key, visit),
+            _ => visit(key, value),
+        }
+    }
+}
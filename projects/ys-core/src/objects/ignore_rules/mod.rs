@@ -1,15 +1,116 @@
 use super::*;
+use crate::objects::config::user_config_directory;
+use std::{fs::read_to_string, io::ErrorKind};
 
-/// The set of file names which we will ignore at any level.
+pub use self::pattern::IgnoreRule;
+
+mod pattern;
+
+/// 每个目录中的忽略规则文件名
+pub const IGNORE_FILE: &str = ".ys.ignore";
+
+/// 忽略规则, 语法与 gitignore 相同
+///
+/// 规则按优先级从低到高排列: 用户的全局规则, 仓库的 `.ys/exclude`, 然后是从工作目录开始逐层进入的
+/// `.ys.ignore`. 最后一条匹配的规则决定路径是否被忽略, 因此更深的目录中的规则优先.
+/// 被忽略的目录不会被进入, 因此无法用 `!` 重新包含被忽略目录中的文件.
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub struct IgnoreRules {
-    pub glob: Cow<'static, str>,
+    glob: Cow<'static, str>,
+    rules: Vec<IgnoreRule>,
 }
 
 impl Default for IgnoreRules {
     fn default() -> Self {
-        IgnoreRules { glob: Cow::Borrowed(include_str!(".ys.ignore")) }
+        IgnoreRules::parse(include_str!(".ys.ignore"))
+    }
+}
+
+impl IgnoreRules {
+    /// 解析 `.ys.ignore` 文件的内容
+    pub fn parse<S>(glob: S) -> Self
+    where
+        S: Into<Cow<'static, str>>,
+    {
+        let glob = glob.into();
+        let mut rules = IgnoreRules { glob: glob.clone(), rules: vec![] };
+        rules.extend(&glob, "", None);
+        rules
+    }
+    /// 追加一个规则文件中的规则, `base` 是规则文件所在的目录相对于工作目录的路径
+    pub fn extend(&mut self, text: &str, base: &str, origin: Option<PathBuf>) {
+        for (index, line) in text.lines().enumerate() {
+            if let Some(mut rule) = IgnoreRule::parse(line, index + 1) {
+                rule.base = base.to_string();
+                rule.origin = origin.clone();
+                self.rules.push(rule);
+            }
+        }
+    }
+    /// 追加规则文件中的规则, 文件不存在时什么也不做
+    pub fn extend_file(&mut self, path: &Path, base: &str) -> Result<(), YsError> {
+        match read_to_string(path) {
+            Ok(text) => {
+                self.extend(&text, base, Some(path.to_path_buf()));
+                Ok(())
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(YsError::path_error(e, path)),
+        }
     }
+    /// 进入一个目录, 返回对其中的路径生效的规则
+    ///
+    /// `prefix` 是目录相对于工作目录的路径, 目录中没有 `.ys.ignore` 时直接返回自身.
+    pub fn enter(&self, directory: &Path, prefix: &str) -> Result<Cow<'_, Self>, YsError> {
+        let path = directory.join(IGNORE_FILE);
+        if !path.is_file() {
+            return Ok(Cow::Borrowed(self));
+        }
+        let mut rules = self.clone();
+        rules.extend_file(&path, prefix)?;
+        Ok(Cow::Owned(rules))
+    }
+    /// 用户的全局忽略规则文件, 位于 `$XDG_CONFIG_HOME/yuan-shen/ignore` 或 `~/.config/yuan-shen/ignore`
+    pub fn global_path() -> Option<PathBuf> {
+        Some(user_config_directory()?.join("ignore"))
+    }
+    /// 最初解析的规则文本, 之后追加的规则文件不包含在内
+    pub fn source(&self) -> &str {
+        &self.glob
+    }
+    /// 解析得到的规则, 不包含空行和注释
+    pub fn rules(&self) -> &[IgnoreRule] {
+        &self.rules
+    }
+    /// 找到决定路径是否被忽略的规则, 即最后一条匹配的规则
+    ///
+    /// `relative` 是相对于工作目录的路径, 以 `/` 分隔.
+    pub fn matched(&self, relative: &str, is_dir: bool) -> Option<&IgnoreRule> {
+        self.rules.iter().rev().find(|rule| rule.matches(relative, is_dir))
+    }
+    /// 检查路径是否被忽略
+    ///
+    /// `relative` 是相对于工作目录的路径, 以 `/` 分隔.
+    pub fn is_ignored(&self, relative: &str, is_dir: bool) -> bool {
+        self.matched(relative, is_dir).is_some_and(|rule| !rule.negated)
+    }
+}
+
+/// 序列化时的规则格式, 只保留原始文本和来源, 反序列化时重新解析
+#[derive(Serialize, Deserialize)]
+struct IgnoreRulesJson<'a> {
+    source: Cow<'a, str>,
+    rules: Vec<IgnoreRuleJson<'a>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct IgnoreRuleJson<'a> {
+    source: Cow<'a, str>,
+    line: usize,
+    #[serde(default, skip_serializing_if = "str::is_empty")]
+    base: Cow<'a, str>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    origin: Option<Cow<'a, Path>>,
 }
 
 impl Serialize for IgnoreRules {
@@ -17,7 +118,17 @@ impl Serialize for IgnoreRules {
     where
         S: Serializer,
     {
-        todo!()
+        let rules = self
+            .rules
+            .iter()
+            .map(|rule| IgnoreRuleJson {
+                source: Cow::Borrowed(&rule.source),
+                line: rule.line,
+                base: Cow::Borrowed(&rule.base),
+                origin: rule.origin.as_deref().map(Cow::Borrowed),
+            })
+            .collect();
+        IgnoreRulesJson { source: Cow::Borrowed(&self.glob), rules }.serialize(serializer)
     }
 }
 
@@ -26,6 +137,17 @@ impl<'de> Deserialize<'de> for IgnoreRules {
     where
         D: Deserializer<'de>,
     {
-        todo!()
+        let json = IgnoreRulesJson::deserialize(deserializer)?;
+        let mut rules = Vec::with_capacity(json.rules.len());
+        for rule in json.rules {
+            let Some(mut parsed) = IgnoreRule::parse(&rule.source, rule.line)
+            else {
+                return Err(serde::de::Error::custom(format!("第 {} 行不是有效的忽略规则: {:?}", rule.line, rule.source)));
+            };
+            parsed.base = rule.base.into_owned();
+            parsed.origin = rule.origin.map(Cow::into_owned);
+            rules.push(parsed);
+        }
+        Ok(IgnoreRules { glob: Cow::Owned(json.source.into_owned()), rules })
     }
 }
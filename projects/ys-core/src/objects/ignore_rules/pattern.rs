@@ -0,0 +1,197 @@
+use super::*;
+
+/// `.ys.ignore` 中的一条规则, 语法与 gitignore 相同
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct IgnoreRule {
+    /// 规则在文件中的原始文本
+    pub source: String,
+    /// 规则所在的行号, 从 1 开始
+    pub line: usize,
+    /// 以 `!` 开头的规则, 重新包含之前被忽略的路径
+    pub negated: bool,
+    /// 以 `/` 结尾的规则, 只匹配目录
+    pub directory_only: bool,
+    /// 包含 `/` 的规则, 相对于规则文件所在的目录匹配完整路径, 否则匹配任意层级的文件名
+    pub anchored: bool,
+    /// 规则文件所在的目录, 相对于工作目录, 规则只作用于其中的路径
+    pub base: String,
+    /// 规则所在的文件, 内置的规则没有来源文件
+    pub origin: Option<PathBuf>,
+    /// 以 `/` 分隔的模式
+    segments: Vec<Vec<char>>,
+}
+
+impl IgnoreRule {
+    /// 解析一行规则, 空行和注释返回 `None`
+    pub fn parse(source: &str, line: usize) -> Option<Self> {
+        let mut pattern = trim_trailing_spaces(source);
+        if pattern.is_empty() || pattern.starts_with('#') {
+            return None;
+        }
+        let negated = pattern.starts_with('!');
+        if negated || pattern.starts_with("\\!") || pattern.starts_with("\\#") {
+            pattern = &pattern[1..];
+        }
+        let directory_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        if pattern.is_empty() {
+            return None;
+        }
+        let anchored = pattern.contains('/');
+        let segments = pattern.trim_start_matches('/').split('/').map(|s| s.chars().collect()).collect();
+        Some(Self {
+            source: source.to_string(),
+            line,
+            negated,
+            directory_only,
+            anchored,
+            base: String::new(),
+            origin: None,
+            segments,
+        })
+    }
+    /// 检查相对于工作目录的路径是否与规则匹配, 不考虑 `!` 取反
+    pub fn matches(&self, relative: &str, is_dir: bool) -> bool {
+        if self.directory_only && !is_dir {
+            return false;
+        }
+        let relative = match self.base.as_str() {
+            "" => relative,
+            base => match relative.strip_prefix(base).and_then(|rest| rest.strip_prefix('/')) {
+                Some(rest) => rest,
+                None => return false,
+            },
+        };
+        if self.anchored {
+            let names: Vec<Vec<char>> = relative.split('/').map(|s| s.chars().collect()).collect();
+            match_segments(&self.segments, &names)
+        }
+        else {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            match_name(&self.segments[0], &name.chars().collect::<Vec<_>>())
+        }
+    }
+}
+
+/// 删除行尾未被 `\` 转义的空格
+fn trim_trailing_spaces(line: &str) -> &str {
+    let mut end = line.trim_end_matches(['\r', '\n']).len();
+    while line[..end].ends_with(' ') && !line[..end - 1].ends_with('\\') {
+        end -= 1;
+    }
+    &line[..end]
+}
+
+/// 逐段匹配路径, `**` 匹配任意多层目录, 位于末尾时至少匹配一层
+///
+/// 与 [match_name] 相同的双指针回溯, `**` 相当于文件名中的 `*`, 连续的 `**` 自然合并,
+/// 耗时与段数和路径层数之积成正比.
+fn match_segments(segments: &[Vec<char>], names: &[Vec<char>]) -> bool {
+    let is_globstar = |segment: &Vec<char>| segment.as_slice() == ['*', '*'];
+    // 末尾的 `**` 至少匹配一层, 等价于去掉最后一层之后匹配零层或多层
+    let names = match segments.last() {
+        Some(last) if is_globstar(last) => match names.split_last() {
+            Some((_, rest)) => rest,
+            None => return false,
+        },
+        _ => names,
+    };
+    let (mut s, mut n) = (0, 0);
+    // 最近一个 `**` 之后的模式位置, 以及它已经吞到的路径位置
+    let mut star = None;
+    while n < names.len() {
+        if segments.get(s).is_some_and(is_globstar) {
+            s += 1;
+            star = Some((s, n));
+            continue;
+        }
+        if segments.get(s).is_some_and(|segment| match_name(segment, &names[n])) {
+            s += 1;
+            n += 1;
+            continue;
+        }
+        match star {
+            Some((after, swallowed)) => {
+                s = after;
+                n = swallowed + 1;
+                star = Some((after, n));
+            }
+            None => return false,
+        }
+    }
+    segments[s..].iter().all(is_globstar)
+}
+
+/// 匹配单个文件名, 支持 `*`, `?`, `[...]` 和 `\` 转义
+///
+/// 使用双指针回溯: 只记住最近一个 `*` 的位置, 失配时让它多吞一个字符, 耗时与两者长度之积成正比.
+fn match_name(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // 最近一个 `*` 之后的模式位置, 以及它已经吞到的文件名位置
+    let mut star = None;
+    while n < name.len() {
+        if pattern.get(p) == Some(&'*') {
+            p += 1;
+            star = Some((p, n));
+            continue;
+        }
+        if let Some(width) = match_char(&pattern[p..], name[n]) {
+            p += width;
+            n += 1;
+            continue;
+        }
+        match star {
+            Some((after, swallowed)) => {
+                p = after;
+                n = swallowed + 1;
+                star = Some((after, n));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// 用模式开头的一项匹配一个字符, 成功时返回这一项在模式中占用的长度
+fn match_char(pattern: &[char], c: char) -> Option<usize> {
+    match pattern {
+        [] => None,
+        ['?', ..] => Some(1),
+        ['[', rest @ ..] => match match_class(rest) {
+            Some((class, negated, after)) => (class_contains(class, c) != negated).then_some(pattern.len() - after.len()),
+            None => (c == '[').then_some(1),
+        },
+        ['\\', escaped, ..] => (*escaped == c).then_some(2),
+        [literal, ..] => (*literal == c).then_some(1),
+    }
+}
+
+/// 拆分出 `[...]` 中的字符集, 返回字符集, 是否取反以及剩余的模式
+fn match_class(pattern: &[char]) -> Option<(&[char], bool, &[char])> {
+    let (negated, body) = match pattern.first() {
+        Some('!' | '^') => (true, &pattern[1..]),
+        _ => (false, pattern),
+    };
+    // 紧跟在开头的 `]` 是字符集的一部分
+    let end = body.iter().skip(1).position(|c| *c == ']')? + 1;
+    Some((&body[..end], negated, &body[end + 1..]))
+}
+
+fn class_contains(class: &[char], c: char) -> bool {
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == '-' {
+            if class[i] <= c && c <= class[i + 2] {
+                return true;
+            }
+            i += 3;
+        }
+        else {
+            if class[i] == c {
+                return true;
+            }
+            i += 1;
+        }
+    }
+    false
+}
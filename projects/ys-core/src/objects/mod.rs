@@ -15,9 +15,9 @@ use std::{
     path::{Path, PathBuf},
 };
 
+pub mod attributes;
 pub mod author_id;
+pub mod config;
 pub mod ignore_rules;
 pub mod object_id;
 pub mod object_store;
-
-
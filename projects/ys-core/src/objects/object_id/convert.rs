@@ -7,6 +7,12 @@ impl From<&Vec<u8>> for ObjectID {
     }
 }
 
+impl From<Hash> for ObjectID {
+    fn from(hash256: Hash) -> Self {
+        ObjectID { hash256 }
+    }
+}
+
 impl From<&[u8]> for ObjectID {
     fn from(bytes: &[u8]) -> Self {
         ObjectID { hash256: blake3::hash(&bytes) }
@@ -1,7 +1,7 @@
 use super::*;
 use crate::{
-
-    utils::{read_json, write_json, WriteHashID},
+    snapshot::transaction::{RefExpectation, RefTransaction},
+    utils::{vec_json, WriteHashID},
 };
 
 mod convert;
@@ -9,27 +9,73 @@ mod convert;
 mod tests;
 
 /// 256 位对象 ID
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ObjectID {
     hash256: Hash,
 }
 
+/// 引用文件当前的格式版本
+pub(crate) const REFERENCE_VERSION: u32 = 1;
+
+/// 世界线和标签的引用文件
+///
+/// 当前格式为 `{"version": 1, "target": "<hex>"}`. 早期版本还写入过 `{"tree-id": "<hex>"}`,
+/// JSON 字符串 `"<hex>"` 和不带引号的十六进制文本, 读取时都会被识别,
+/// 由 [DotYuanShenClient::migrate_references](crate::initialize::DotYuanShenClient::migrate_references) 改写为当前格式.
 #[derive(Clone, Debug, Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case")]
-pub struct BranchJson {
-    tree_id: String,
+#[serde(untagged)]
+enum ReferenceJson {
+    Versioned {
+        version: u32,
+        target: String,
+    },
+    #[serde(rename_all = "kebab-case")]
+    TreeID {
+        tree_id: String,
+    },
+    Bare(String),
 }
 
 impl ObjectID {
+    /// 读取世界线指向的快照
     pub fn read_branch(dot_ys: &Path, name: &str) -> Result<Self, YsError> {
-        let file = dot_ys.join("branches").join(name);
-        let json = read_json::<BranchJson>(&file)?;
-        Ok(Self { hash256: Hash::from_hex(&json.tree_id)? })
+        Self::read_reference(&dot_ys.join("branches").join(name))
     }
+    /// 将世界线指向该快照, 不检查世界线原先的值
     pub fn write_branch(&self, dot_ys: &Path, name: &str) -> Result<(), YsError> {
-        let file = dot_ys.join("branches").join(name);
-        let json = BranchJson { tree_id: self.hash256.to_string() };
-        write_json(&json, &file)
+        let mut transaction = RefTransaction::new(dot_ys);
+        transaction.update(format!("branches/{}", name), *self, RefExpectation::Any);
+        transaction.commit()
+    }
+    /// 读取引用文件, 世界线和标签使用相同的格式
+    pub(crate) fn read_reference(file: &Path) -> Result<Self, YsError> {
+        Ok(Self::parse_reference(file)?.0)
+    }
+    /// 解析引用文件, 同时返回它是否使用了旧格式
+    pub(crate) fn parse_reference(file: &Path) -> Result<(Self, bool), YsError> {
+        let text = std::fs::read_to_string(file).map_err(|e| YsError::path_error(e, file))?;
+        let invalid = |reason: String| YsErrorKind::InvalidReference { path: file.to_path_buf(), reason };
+        let text = text.trim();
+        let (hex, legacy) = match Hash::from_hex(text) {
+            Ok(_) => (text.to_string(), true),
+            Err(_) => match serde_json::from_str::<ReferenceJson>(text) {
+                Ok(ReferenceJson::Versioned { version, target }) if version <= REFERENCE_VERSION => (target, false),
+                Ok(ReferenceJson::Versioned { version, .. }) => {
+                    Err(invalid(format!("格式版本 {} 高于支持的版本 {}", version, REFERENCE_VERSION)))?
+                }
+                Ok(ReferenceJson::TreeID { tree_id }) => (tree_id, true),
+                Ok(ReferenceJson::Bare(hex)) => (hex, true),
+                Err(e) => Err(invalid(e.to_string()))?,
+            },
+        };
+        match Hash::from_hex(hex.trim()) {
+            Ok(hash256) => Ok((Self { hash256 }, legacy)),
+            Err(e) => Err(invalid(e.to_string()))?,
+        }
+    }
+    /// 引用文件的内容, 总是使用当前格式
+    pub(crate) fn reference_bytes(&self) -> Result<Vec<u8>, YsError> {
+        vec_json(&ReferenceJson::Versioned { version: REFERENCE_VERSION, target: self.hash256.to_string() })
     }
 }
 
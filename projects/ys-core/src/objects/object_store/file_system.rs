@@ -1,11 +1,16 @@
 use super::*;
 use crate::utils::vec_json;
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
 use std::fs::{create_dir, try_exists};
 
 /// 本地文件系统对象储存
+///
+/// 设置了压缩级别时新对象以 zlib 压缩后写入, 读取时按内容的哈希区分压缩和未压缩的对象,
+/// 因此同一个储存库中可以混合两种对象, 修改压缩级别不需要改写已有的对象.
 #[derive(Debug, Clone)]
 pub struct LocalObjectStore {
     root: PathBuf,
+    compression_level: u32,
 }
 
 impl LocalObjectStore {
@@ -15,7 +20,36 @@ impl LocalObjectStore {
             tracing::info!("正在创建储存库: {:?}", root);
             create_dir(&root)?;
         }
-        Ok(Self { root })
+        Ok(Self { root, compression_level: 0 })
+    }
+    /// 设置写入新对象时的压缩级别, 即配置项 `core.compression-level`, 0 表示不压缩, 超过 9 时按 9 处理
+    pub fn with_compression_level(mut self, level: u8) -> Self {
+        self.compression_level = u32::from(level.min(9));
+        self
+    }
+    /// 按压缩级别编码对象, 压缩后没有变小时原样保存
+    fn encode<'a>(&self, object: &'a [u8]) -> Result<Cow<'a, [u8]>, YsError> {
+        if self.compression_level == 0 {
+            return Ok(Cow::Borrowed(object));
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(self.compression_level));
+        encoder.write_all(object)?;
+        let compressed = encoder.finish()?;
+        Ok(if compressed.len() < object.len() { Cow::Owned(compressed) } else { Cow::Borrowed(object) })
+    }
+}
+
+/// 还原文件中保存的对象, 内容的哈希与 `id` 一致时是未压缩的对象
+///
+/// 既不是未压缩的对象, 解压后也与 `id` 不符时原样返回, 由调用者检查损坏.
+fn decode(id: ObjectID, stored: Vec<u8>) -> Vec<u8> {
+    if ObjectID::from(stored.as_slice()) == id {
+        return stored;
+    }
+    let mut object = Vec::new();
+    match ZlibDecoder::new(stored.as_slice()).read_to_end(&mut object) {
+        Ok(_) if ObjectID::from(object.as_slice()) == id => object,
+        _ => stored,
     }
 }
 
@@ -40,13 +74,39 @@ impl ObjectStore for LocalObjectStore {
         let mut f = std::fs::File::options().read(true).open(path)?;
         let mut v = Vec::new();
         f.read_to_end(&mut v)?;
-        Ok(v)
+        Ok(decode(id, v))
     }
 
-
+    /// 前缀少于 2 个字符时不会扫描整个储存库, 总是返回空列表
+    async fn find_prefix(&self, prefix: &str) -> Result<Vec<ObjectID>, YsError> {
+        if prefix.len() < HASH_HEADER_LENGTH {
+            return Ok(vec![]);
+        }
+        let (dir, rest) = prefix.split_at(HASH_HEADER_LENGTH);
+        let path = self.root.join(dir);
+        let entries = match std::fs::read_dir(&path) {
+            Ok(o) => o,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => Err(YsError::path_error(e, path))?,
+        };
+        let mut found = vec![];
+        for entry in entries {
+            let name = entry?.file_name();
+            // 忽略写入中途留下的临时文件等无法解析为 id 的文件
+            if let Some(id) =
+                name.to_str().filter(|name| name.starts_with(rest)).and_then(|name| format!("{}{}", dir, name).parse().ok())
+            {
+                found.push(id);
+            }
+        }
+        Ok(found)
+    }
 
     async fn put(&mut self, object: &[u8]) -> Result<ObjectID, YsError> {
-        let id: ObjectID = object.into();
+        self.put_prehashed(object.into(), object).await
+    }
+
+    async fn put_prehashed(&mut self, id: ObjectID, object: &[u8]) -> Result<ObjectID, YsError> {
         tracing::trace!("正在插入 {} 到 {:?}", id, self.root);
         let s: String = format!("{}", id);
         let sub: &str = &s[0..HASH_HEADER_LENGTH];
@@ -62,7 +122,7 @@ impl ObjectStore for LocalObjectStore {
             std::fs::create_dir(&subdir_path)?;
         }
         let mut f = File::options().create(true).write(true).open(path)?;
-        f.write(&object)?;
+        f.write_all(&self.encode(object)?)?;
         Ok(id)
     }
 
@@ -18,8 +18,8 @@ impl MemoryObjectStore {
 
 impl ObjectStore for MemoryObjectStore {
 
-    async fn has(&self, _: ObjectID) -> Result<bool, YsError> {
-        return Ok(true);
+    async fn has(&self, id: ObjectID) -> Result<bool, YsError> {
+        Ok(self.btree.contains_key(&id))
     }
 
     async fn get(&self, id: ObjectID) -> Result<Vec<u8>, YsError> {
@@ -28,9 +28,16 @@ impl ObjectStore for MemoryObjectStore {
             None => Ok(vec![]),
         }
     }
+
+    async fn find_prefix(&self, prefix: &str) -> Result<Vec<ObjectID>, YsError> {
+        Ok(self.btree.keys().filter(|id| id.to_string().starts_with(prefix)).copied().collect())
+    }
     
     async fn put(&mut self, object: &[u8]) -> Result<ObjectID, YsError> {
-        let id: ObjectID = object.into();
+        self.put_prehashed(object.into(), object).await
+    }
+
+    async fn put_prehashed(&mut self, id: ObjectID, object: &[u8]) -> Result<ObjectID, YsError> {
         match self.btree.entry(id) {
             // id 不存在, 插入新对象
             Entry::Vacant(v) => {
@@ -26,6 +26,15 @@ pub trait ObjectStore {
     /// - `Result<Option<Vec<u8>>, Self::Error>`: 如果对象存在，返回包含对象数据的`Vec<u8>`的`Result::Ok`；如果对象不存在，返回`Result::Ok(None)`；如果发生错误，返回`Result::Err(error)`，其中`error`是`Self::Error`类型。
     fn get(&self, id: ObjectID) -> impl Future<Output = Result<Vec<u8>, YsError>> + Send;
 
+    /// 查找 id 以给定十六进制前缀开头的所有对象。
+    ///
+    /// # 参数
+    /// - `prefix`: 小写的十六进制前缀。
+    ///
+    /// # 返回值
+    /// - `Result<Vec<ObjectID>, Self::Error>`: 所有匹配的对象 id, 没有匹配时返回空列表。
+    fn find_prefix(&self, prefix: &str) -> impl Future<Output = Result<Vec<ObjectID>, YsError>> + Send;
+
     /// 从存储中读取对象。
     ///
     /// # 参数
@@ -50,6 +59,19 @@ pub trait ObjectStore {
     /// - `Result<ObjectID, Self::Error>`: 如果对象成功插入，返回该对象的唯一标识符`ObjectID`的`Result::Ok`；如果插入失败，返回`Result::Err(error)`，其中`error`是`Self::Error`类型。
     fn put(&mut self, object: &[u8]) -> impl Future<Output = Result<ObjectID, YsError>> + Send;
 
+    /// 将已经计算过 id 的对象插入存储, 避免重复哈希。
+    ///
+    /// # 参数
+    /// - `id`: 对象的唯一标识符, 调用者必须保证它就是`object`的哈希值。
+    /// - `object`: 要插入存储的对象数据的字节切片。
+    ///
+    /// # 返回值
+    /// - `Result<ObjectID, Self::Error>`: 与`put`相同, 默认实现会忽略`id`并重新计算哈希。
+    async fn put_prehashed(&mut self, id: ObjectID, object: &[u8]) -> Result<ObjectID, YsError> {
+        let _ = id;
+        self.put(object).await
+    }
+
     /// 将对象插入存储。
     ///
     /// # 参数
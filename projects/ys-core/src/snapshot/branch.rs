@@ -0,0 +1,81 @@
+use super::*;
+use crate::{
+    initialize::{DotYuanShenClient, YuanShenClient},
+    YsErrorKind,
+};
+
+/// 列出世界线时的一条记录
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct BranchInfo {
+    /// 世界线的名称
+    pub name: String,
+    /// 世界线指向的快照
+    pub tip: ObjectID,
+    /// 是否是当前所在的世界线
+    pub current: bool,
+}
+
+/// 列出世界线时使用的过滤条件, 默认列出所有世界线
+#[derive(Clone, Debug, Default)]
+pub struct BranchFilter {
+    /// 名称中需要包含的文字
+    pub pattern: Option<String>,
+    /// 按名称过滤时忽略大小写
+    pub ignore_case: bool,
+    /// 只保留历史中包含该快照的世界线
+    pub contains: Option<ObjectID>,
+    /// 只保留历史中不包含该快照的世界线
+    pub without: Option<ObjectID>,
+}
+
+impl BranchFilter {
+    fn matches_name(&self, name: &str) -> bool {
+        match &self.pattern {
+            None => true,
+            Some(pattern) if self.ignore_case => name.to_lowercase().contains(&pattern.to_lowercase()),
+            Some(pattern) => name.contains(pattern.as_str()),
+        }
+    }
+}
+
+impl DotYuanShenClient {
+    /// 按名称顺序列出满足条件的世界线
+    pub async fn list_branches(&self, filter: &BranchFilter) -> Result<Vec<BranchInfo>, YsError> {
+        let head = self.head()?;
+        let mut branches = vec![];
+        for name in self.branch_names()? {
+            if !filter.matches_name(&name) {
+                continue;
+            }
+            let tip = self.get_branch_id(&name)?;
+            if let Some(id) = filter.contains {
+                if !self.is_ancestor(id, tip).await? {
+                    continue;
+                }
+            }
+            if let Some(id) = filter.without {
+                if self.is_ancestor(id, tip).await? {
+                    continue;
+                }
+            }
+            branches.push(BranchInfo { current: head.branch() == Some(name.as_str()), name, tip });
+        }
+        Ok(branches)
+    }
+    /// 删除世界线, 返回它原先指向的快照
+    ///
+    /// 不能删除当前所在的世界线. 没有给出 `force` 时, 世界线指向的快照必须已经出现在当前世界线的历史中,
+    /// 否则删除后这些快照将无法通过任何世界线找到.
+    pub async fn delete_branch(&self, name: &str, force: bool) -> Result<ObjectID, YsError> {
+        if self.head()?.branch() == Some(name) {
+            Err(YsErrorKind::CheckedOutBranch { name: name.to_string() })?
+        }
+        if !force && self.branch_exists(name)? {
+            let tip = self.get_branch_id(name)?;
+            if !self.is_ancestor(tip, self.calculate_branch_id()?).await? {
+                Err(YsErrorKind::UnmergedBranch { name: name.to_string(), tip })?
+            }
+        }
+        self.remove_branch(name)
+    }
+}
@@ -0,0 +1,126 @@
+use super::*;
+use crate::{
+    differences::DifferenceRecord,
+    initialize::DotYuanShenClient,
+    snapshot::{
+        index::StagingIndex,
+        materialize::{attributes_within, ensure_directory, write_file, MaterializeAction, MaterializeReport},
+    },
+    PathAttributes, ResolvedAttributes, ATTRIBUTES_FILE,
+};
+use std::{
+    collections::btree_map::Entry,
+    fs::{remove_dir, remove_file},
+    io::ErrorKind,
+};
+
+impl SnapShotDirectory {
+    /// 将工作目录从当前目录树切换到目标目录树
+    ///
+    /// 只会改动两棵树之间存在差异的路径: 先删除目标树中不存在的文件, 并清理因此变空且目标树中没有的目录,
+    /// 再写入新增和修改的文件并创建目标树中的空目录. 未被追踪的文件会被保留.
+    /// 属性文件最先写入, 其余文件按写入后的 `.ys.attributes` 转换换行符.
+    pub async fn checkout<Store: ObjectStore>(
+        &self,
+        target: &SnapShotDirectory,
+        store: &Store,
+        path: &Path,
+    ) -> Result<MaterializeReport, YsError> {
+        self.check_names()?;
+        target.check_names()?;
+        let mut report = MaterializeReport::default();
+        let changes: Vec<DifferenceRecord> = self.changes(target).collect();
+        let mut emptied = BTreeSet::new();
+        for record in changes.iter().filter(|record| record.new.is_none()) {
+            let file = path.join(&record.path);
+            match remove_file(&file) {
+                Ok(()) => report.record(&file, MaterializeAction::RemoveFile),
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => Err(YsError::path_error(e, file))?,
+            }
+            emptied.extend(record.path.match_indices('/').map(|(index, _)| &record.path[..index]));
+        }
+        // 逆序遍历时子目录先于父目录
+        for relative in emptied.into_iter().rev().filter(|relative| !target.has_directory(relative)) {
+            let directory = path.join(relative);
+            match remove_dir(&directory) {
+                Ok(()) => report.record(&directory, MaterializeAction::RemoveDirectory),
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) if e.kind() == ErrorKind::DirectoryNotEmpty => {
+                    tracing::warn!("目录 {:?} 中仍有未被追踪的文件, 已保留", directory);
+                }
+                Err(e) => Err(YsError::path_error(e, directory))?,
+            }
+        }
+        let (own, rest): (Vec<_>, Vec<_>) =
+            changes.iter().partition(|record| record.path.rsplit('/').next() == Some(ATTRIBUTES_FILE));
+        let mut cache: BTreeMap<&str, PathAttributes> = BTreeMap::new();
+        for (index, record) in own.iter().chain(rest.iter()).enumerate() {
+            let Some(id) = record.new
+            else {
+                continue;
+            };
+            let parent = record.path.rsplit_once('/').map(|(parent, _)| parent).unwrap_or("");
+            if !parent.is_empty() {
+                ensure_directory(&path.join(parent), &mut report)?;
+            }
+            let attributes = if index < own.len() {
+                ResolvedAttributes::default()
+            }
+            else {
+                let attributes = match cache.entry(parent) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => entry.insert(attributes_within(path, parent)?),
+                };
+                attributes.resolve(&record.path)
+            };
+            write_file(store, id, &path.join(&record.path), &attributes, &mut report).await?;
+        }
+        // 空目录不会出现在差异中, 按目标树补齐
+        let mut empty = vec![];
+        target.empty_directories("", &mut empty);
+        for relative in empty {
+            ensure_directory(&path.join(relative), &mut report)?;
+        }
+        Ok(report)
+    }
+    /// 收集目录树中所有不包含任何条目的子目录, 路径以 `/` 分隔
+    fn empty_directories(&self, prefix: &str, empty: &mut Vec<String>) {
+        for (name, entry) in self.root.iter() {
+            if let DirectoryEntry::Directory(dir) = entry {
+                let relative = join_relative(prefix, name);
+                if dir.root.is_empty() {
+                    empty.push(relative);
+                }
+                else {
+                    dir.empty_directories(&relative, empty);
+                }
+            }
+        }
+    }
+    /// 目录树中是否存在给定的子目录, 路径以 `/` 分隔
+    fn has_directory(&self, relative: &str) -> bool {
+        let mut directory = self;
+        for name in relative.split('/') {
+            match directory.root.get(name) {
+                Some(DirectoryEntry::Directory(dir)) => directory = dir,
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+impl DotYuanShenClient {
+    /// 将工作目录和暂存区从快照 `from` 的内容切换到快照 `to` 的内容, 见 [SnapShotDirectory::checkout]
+    pub async fn switch_working_tree(&self, from: ObjectID, to: ObjectID) -> Result<MaterializeReport, YsError> {
+        let store = self.store()?;
+        let from: SnapShot = store.get_typed(from).await?;
+        let to: SnapShot = store.get_typed(to).await?;
+        let from: SnapShotDirectory = store.get_typed(from.directory).await?;
+        let to: SnapShotDirectory = store.get_typed(to.directory).await?;
+        let report = from.checkout(&to, &store, self.working_directory()).await?;
+        self.save_index(&StagingIndex::from_directory(&to))?;
+        Ok(report)
+    }
+}
@@ -1,8 +1,17 @@
 use super::*;
+use crate::{
+    looks_binary,
+    snapshot::{index::StagingIndex, patch::diff_lines},
+    LineChange,
+};
+use std::{
+    collections::{btree_map::Entry, HashMap},
+    ops::AddAssign,
+};
 
 /// SnapShotDifference 结构体定义了快照之间的差异
 /// 包括删除的项、新增的项以及修改的项。每个项都通过其对应的路径进行标识。
-#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+#[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SnapShotDifference {
     /// 被删除的文件或目录路径集合
     pub deleted: BTreeSet<String>,
@@ -10,6 +19,73 @@ pub struct SnapShotDifference {
     pub added: BTreeMap<String, DirectoryEntry>,
     /// 被修改的文件或目录信息映射
     pub modified: BTreeMap<String, DifferenceEntry>,
+    /// 被重命名的文件, 键是新路径, 只记录在最外层的差异中, 路径以 `/` 分隔
+    ///
+    /// 对应的新增和删除仍然保留在 `added` 和 `deleted` 中.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub renamed: BTreeMap<String, FileMove>,
+    /// 从旧目录树中的文件复制而来的文件, 键是新路径, 只记录在最外层的差异中, 路径以 `/` 分隔
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub copied: BTreeMap<String, FileMove>,
+}
+
+/// 重命名或复制的来源
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct FileMove {
+    /// 来源文件在旧目录树中的路径, 以 `/` 分隔
+    pub from: String,
+    /// 内容相似度的百分比, 内容完全相同时为 100
+    pub similarity: u8,
+}
+
+/// 单个文件的变化, 由 [`SnapShotDirectory::changes`] 和 [`SnapShotDifference::records`] 按路径排序产生
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct DifferenceRecord {
+    /// 相对于工作目录的完整路径, 以 `/` 分隔
+    pub path: String,
+    /// 变化的类型, 目录会被展开, 不会出现目录本身
+    pub kind: DifferenceStackType,
+    /// 旧版本的 id, 重命名和复制时是来源文件的 id, 新增的文件没有
+    pub old: Option<ObjectID>,
+    /// 新版本的 id, 删除的文件没有
+    pub new: Option<ObjectID>,
+    /// 重命名或复制的来源
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<FileMove>,
+}
+
+/// 差异的统计数据, 文本文件按行统计, 二进制文件按整个文件统计
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffStat {
+    /// 发生变化的文件数量
+    pub files_changed: usize,
+    /// 新增的行数
+    pub lines_added: usize,
+    /// 删除的行数
+    pub lines_removed: usize,
+    /// 新增的字节数
+    pub bytes_added: usize,
+    /// 删除的字节数
+    pub bytes_removed: usize,
+    /// 发生变化的二进制文件数量, 这些文件只统计字节数
+    pub binary_files: usize,
+}
+
+/// 检测重命名和复制的选项
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RenameOptions {
+    /// 相似度不低于此百分比的文件才会被视为重命名或复制, 100 表示只检测内容完全相同的文件
+    pub threshold: u8,
+    /// 是否检测复制, 内容相同时来源可以是旧目录树中的任意文件, 否则只能是被修改的文件
+    pub copies: bool,
+    /// 需要比较内容的文件对数量上限, 超过时只检测内容完全相同的文件
+    pub limit: usize,
+}
+
+impl Default for RenameOptions {
+    fn default() -> Self {
+        Self { threshold: 50, copies: false, limit: 100_000 }
+    }
 }
 
 /// DifferenceEntry 枚举定义了差异条目的类型，可以是文件或目录。
@@ -23,7 +99,7 @@ pub enum DifferenceEntry {
 }
 
 /// DifferenceStackType 枚举定义了差异栈的操作类型，包括删除、添加和修改。
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DifferenceStackType {
     /// 被删除的路径
     Deleted,
@@ -31,6 +107,10 @@ pub enum DifferenceStackType {
     Added,
     /// 被修改的路径及条目信息
     Modified,
+    /// 被重命名的文件
+    Renamed,
+    /// 被复制的文件
+    Copied,
 }
 
 /// DifferenceStackItem 枚举定义了差异栈的具体项，对应于不同类型的差异操作。
@@ -61,7 +141,7 @@ impl DirectoryEntry {
             (File(_), Directory(d)) => Some(DifferenceEntry::Directory(Box::new(SnapShotDifference {
                 deleted: BTreeSet::new(),
                 added: d.root.clone(),
-                modified: BTreeMap::new(),
+                ..Default::default()
             }))),
             (Directory(d), Directory(d_)) => {
                 if d == d_ {
@@ -101,12 +181,131 @@ impl SnapShotDirectory {
                     .and_then(|other_dir_entry| dir_entry.difference(other_dir_entry).map(|diff| (file_name.clone(), diff)))
             })
             .collect();
-        SnapShotDifference { added, deleted, modified }
+        SnapShotDifference { added, deleted, modified, ..Default::default() }
+    }
+    /// 计算差异, 并检测被重命名和复制的文件
+    ///
+    /// 内容完全相同的文件优先配对, 同名的来源优先; 其余新增的文件与候选来源逐一比较内容,
+    /// 相似度不低于阈值的文件对按相似度从高到低配对. 每个被删除的文件最多被重命名一次,
+    /// 开启复制检测时同一个来源可以被复制多次.
+    pub async fn difference_with_renames<Store: ObjectStore>(
+        &self,
+        other: &SnapShotDirectory,
+        store: &Store,
+        options: &RenameOptions,
+    ) -> Result<SnapShotDifference, YsError> {
+        let mut difference = self.difference(other);
+        let old = StagingIndex::from_directory(self).entries;
+        let new = StagingIndex::from_directory(other).entries;
+        let targets: Vec<(&String, ObjectID)> =
+            new.iter().filter(|(path, _)| !old.contains_key(*path)).map(|(path, id)| (path, *id)).collect();
+        if targets.is_empty() {
+            return Ok(difference);
+        }
+        // 候选来源及其是否已被删除
+        let sources: Vec<(&String, ObjectID, bool)> = old
+            .iter()
+            .filter_map(|(path, id)| match new.get(path) {
+                None => Some((path, *id, true)),
+                Some(_) if options.copies => Some((path, *id, false)),
+                Some(_) => None,
+            })
+            .collect();
+        let mut by_id: HashMap<ObjectID, Vec<usize>> = HashMap::new();
+        for (s, (_, source_id, _)) in sources.iter().enumerate() {
+            by_id.entry(*source_id).or_default().push(s);
+        }
+        let mut pairs: Vec<(u8, bool, usize, usize)> = vec![];
+        for (t, (target, target_id)) in targets.iter().enumerate() {
+            for s in by_id.get(target_id).into_iter().flatten().copied() {
+                pairs.push((100, file_name(target) == file_name(sources[s].0), t, s));
+            }
+        }
+        // 只有被删除或被修改的文件才会参与内容比较
+        let similar: Vec<usize> =
+            (0..sources.len()).filter(|s| sources[*s].2 || new.get(sources[*s].0) != Some(&sources[*s].1)).collect();
+        if options.threshold < 100 && targets.len() * similar.len() <= options.limit {
+            let mut contents: BTreeMap<ObjectID, Vec<u8>> = BTreeMap::new();
+            for id in targets.iter().map(|(_, id)| *id).chain(similar.iter().map(|s| sources[*s].1)) {
+                if let Entry::Vacant(entry) = contents.entry(id) {
+                    entry.insert(store.get(id).await?);
+                }
+            }
+            for (t, (target, target_id)) in targets.iter().enumerate() {
+                for s in similar.iter().copied() {
+                    let (source, source_id, _) = sources[s];
+                    if *target_id == source_id {
+                        continue;
+                    }
+                    let score = similarity(&contents[&source_id], &contents[target_id]);
+                    if score >= options.threshold {
+                        pairs.push((score, file_name(target) == file_name(source), t, s));
+                    }
+                }
+            }
+        }
+        pairs.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)).then(a.2.cmp(&b.2)).then(a.3.cmp(&b.3)));
+        let mut paired = vec![false; targets.len()];
+        let mut renamed = vec![false; sources.len()];
+        for (score, _, t, s) in pairs {
+            let (source, _, deleted) = sources[s];
+            if paired[t] {
+                continue;
+            }
+            let entry = FileMove { from: source.clone(), similarity: score };
+            if deleted && !renamed[s] {
+                renamed[s] = true;
+                difference.renamed.insert(targets[t].0.clone(), entry);
+            }
+            else if options.copies {
+                difference.copied.insert(targets[t].0.clone(), entry);
+            }
+            else {
+                continue;
+            }
+            paired[t] = true;
+        }
+        Ok(difference)
     }
 }
 
-impl Display for SnapShotDifference {
-    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+impl SnapShotDifference {
+    /// 旧目录树中被重命名的文件在新目录树中的路径, 合并时用于让修改跟随重命名的文件
+    pub fn renamed_to(&self, path: &str) -> Option<&str> {
+        self.renamed.iter().find(|(_, entry)| entry.from == path).map(|(target, _)| target.as_str())
+    }
+}
+
+/// 路径中的文件名
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// 按行计算两段内容的相似度百分比, 即共有的行的字节数占较大一方的比例
+fn similarity(old: &[u8], new: &[u8]) -> u8 {
+    let larger = old.len().max(new.len());
+    if larger == 0 {
+        return 100;
+    }
+    let mut lines: HashMap<&[u8], usize> = HashMap::new();
+    for line in old.split_inclusive(|b| *b == b'\n') {
+        *lines.entry(line).or_default() += 1;
+    }
+    let mut common = 0;
+    for line in new.split_inclusive(|b| *b == b'\n') {
+        if let Some(count) = lines.get_mut(line).filter(|count| **count > 0) {
+            *count -= 1;
+            common += line.len();
+        }
+    }
+    (common * 100 / larger) as u8
+}
+
+impl SnapShotDifference {
+    /// 按路径排序列出所有变化的路径, 新增的目录会被展开到其中的文件, 被删除的目录只列出目录本身
+    ///
+    /// 被重命名的文件以新路径列出, 其来源不再单独列出.
+    pub fn paths(&self) -> std::vec::IntoIter<(String, DifferenceStackType)> {
         let mut stack: Vec<DifferenceStackItem> = vec![];
 
         for (path, dir_entry) in self.added.clone() {
@@ -160,9 +359,37 @@ impl Display for SnapShotDifference {
             }
         }
 
-        for (path, diff_item) in diff_paths {
-            writeln!(f, "{}", diff_item.character_symbol())?;
-            path.to_str().unwrap();
+        let mut diff_paths: BTreeMap<String, DifferenceStackType> = diff_paths
+            .into_iter()
+            .map(|(path, item)| (path.iter().map(|name| name.to_string_lossy()).collect::<Vec<_>>().join("/"), item))
+            .collect();
+        for (path, entry) in self.renamed.iter() {
+            if let Some(DifferenceStackType::Deleted) = diff_paths.get(&entry.from) {
+                diff_paths.remove(&entry.from);
+            }
+            diff_paths.insert(path.clone(), DifferenceStackType::Renamed);
+        }
+        for path in self.copied.keys() {
+            diff_paths.insert(path.clone(), DifferenceStackType::Copied);
+        }
+        diff_paths.into_iter().collect::<Vec<_>>().into_iter()
+    }
+}
+
+impl Display for SnapShotDifference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        for (path, diff_item) in self.paths() {
+            let entry = match diff_item {
+                DifferenceStackType::Renamed => self.renamed.get(&path),
+                DifferenceStackType::Copied => self.copied.get(&path),
+                _ => None,
+            };
+            match entry {
+                Some(entry) => {
+                    writeln!(f, "{}{:03} {} -> {}", diff_item.character_symbol(), entry.similarity, entry.from, path)?
+                }
+                None => writeln!(f, "{} {}", diff_item.character_symbol(), path)?,
+            }
         }
         Ok(())
     }
@@ -175,6 +402,142 @@ impl DifferenceStackType {
             Self::Deleted => 'D',
             Self::Added => 'A',
             Self::Modified => 'M',
+            Self::Renamed => 'R',
+            Self::Copied => 'C',
+        }
+    }
+}
+
+impl SnapShotDirectory {
+    /// 列出两棵目录树之间所有文件的变化, 按路径排序
+    ///
+    /// 文件和目录互相替换时, 会分别列出旧路径下被删除的文件和新路径下新增的文件.
+    pub fn changes(&self, other: &SnapShotDirectory) -> std::vec::IntoIter<DifferenceRecord> {
+        let old = StagingIndex::from_directory(self).entries;
+        let new = StagingIndex::from_directory(other).entries;
+        let paths: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+        let mut records = vec![];
+        for path in paths {
+            let (old, new) = (old.get(path).copied(), new.get(path).copied());
+            let kind = match (old, new) {
+                (Some(a), Some(b)) if a == b => continue,
+                (Some(_), Some(_)) => DifferenceStackType::Modified,
+                (None, _) => DifferenceStackType::Added,
+                (_, None) => DifferenceStackType::Deleted,
+            };
+            records.push(DifferenceRecord { path: path.clone(), kind, old, new, from: None });
+        }
+        records.into_iter()
+    }
+}
+
+impl SnapShotDifference {
+    /// 列出两棵目录树之间所有文件的变化, 并应用本差异中检测到的重命名和复制
+    ///
+    /// `old` 和 `new` 必须是计算本差异时使用的目录树. 重命名的来源不再单独列出.
+    pub fn records(&self, old: &SnapShotDirectory, new: &SnapShotDirectory) -> std::vec::IntoIter<DifferenceRecord> {
+        let files = StagingIndex::from_directory(old).entries;
+        let sources: BTreeSet<&String> = self.renamed.values().map(|entry| &entry.from).collect();
+        let mut records = vec![];
+        for mut record in old.changes(new) {
+            let moved = match record.kind {
+                DifferenceStackType::Deleted if sources.contains(&record.path) => continue,
+                DifferenceStackType::Added => match (self.renamed.get(&record.path), self.copied.get(&record.path)) {
+                    (Some(entry), _) => Some((DifferenceStackType::Renamed, entry)),
+                    (None, Some(entry)) => Some((DifferenceStackType::Copied, entry)),
+                    (None, None) => None,
+                },
+                _ => None,
+            };
+            if let Some((kind, entry)) = moved {
+                record.kind = kind;
+                record.old = files.get(&entry.from).copied();
+                record.from = Some(entry.clone());
+            }
+            records.push(record);
         }
+        records.into_iter()
+    }
+}
+
+impl DiffStat {
+    /// 比较一个文件的两个版本, 不存在的一方为 `None`
+    pub fn between(old: Option<&[u8]>, new: Option<&[u8]>) -> Self {
+        let mut stat = Self { files_changed: 1, ..Self::default() };
+        let (old, new) = (old.unwrap_or_default(), new.unwrap_or_default());
+        if looks_binary(old) || looks_binary(new) {
+            stat.bytes_removed = old.len();
+            stat.bytes_added = new.len();
+            stat.binary_files = 1;
+            return stat;
+        }
+        let old: Vec<&[u8]> = old.split_inclusive(|b| *b == b'\n').collect();
+        let new: Vec<&[u8]> = new.split_inclusive(|b| *b == b'\n').collect();
+        let (mut i, mut j) = (0, 0);
+        for change in diff_lines(&old, &new) {
+            match change {
+                LineChange::Equal => (i, j) = (i + 1, j + 1),
+                LineChange::Delete => {
+                    stat.lines_removed += 1;
+                    stat.bytes_removed += old[i].len();
+                    i += 1;
+                }
+                LineChange::Insert => {
+                    stat.lines_added += 1;
+                    stat.bytes_added += new[j].len();
+                    j += 1;
+                }
+            }
+        }
+        stat
+    }
+    /// 统计一组文件变化, 文件内容从储存库中读取
+    pub async fn collect<'a, Store: ObjectStore>(
+        records: impl IntoIterator<Item = &'a DifferenceRecord>,
+        store: &Store,
+    ) -> Result<Self, YsError> {
+        let mut total = Self::default();
+        for record in records {
+            let old = match record.old {
+                Some(id) => Some(store.get(id).await?),
+                None => None,
+            };
+            let new = match record.new {
+                Some(id) => Some(store.get(id).await?),
+                None => None,
+            };
+            total += Self::between(old.as_deref(), new.as_deref());
+        }
+        Ok(total)
+    }
+}
+
+impl AddAssign for DiffStat {
+    fn add_assign(&mut self, other: Self) {
+        self.files_changed += other.files_changed;
+        self.lines_added += other.lines_added;
+        self.lines_removed += other.lines_removed;
+        self.bytes_added += other.bytes_added;
+        self.bytes_removed += other.bytes_removed;
+        self.binary_files += other.binary_files;
+    }
+}
+
+impl Display for DifferenceRecord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match &self.from {
+            Some(entry) => write!(f, "{}{:03} {} -> {}", self.kind.character_symbol(), entry.similarity, entry.from, self.path),
+            None => write!(f, "{} {}", self.kind.character_symbol(), self.path),
+        }
+    }
+}
+
+impl Display for DiffStat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{} 个文件发生变化, 新增 {} 行 ({} 字节), 删除 {} 行 ({} 字节)",
+            self.files_changed, self.lines_added, self.bytes_added, self.lines_removed, self.bytes_removed
+        )
     }
 }
@@ -1,41 +1,65 @@
 use std::{
     collections::BTreeMap,
-    fs::{read_dir, File},
-    io::{Read, Write},
-    path::{Path, PathBuf},
+    path::{Component, Path},
 };
 
-use serde::{Deserialize, Serialize};
-
-use crate::{IgnoreRules, ObjectID, ObjectStore, YsError};
+use serde::{Deserialize, Deserializer, Serialize};
 
+use crate::{ObjectID, YsError, YsErrorKind, DOT_YUAN_SHEN};
 
 /// A directory tree, with [`ObjectID`]s at the leaves.
-#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Default)]
 pub struct SnapShotDirectory {
     #[serde(flatten)]
     pub root: BTreeMap<String, DirectoryEntry>,
 }
 
-
+impl<'de> Deserialize<'de> for SnapShotDirectory {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let root = BTreeMap::<String, DirectoryEntry>::deserialize(deserializer)?;
+        for name in root.keys() {
+            SnapShotDirectory::check_name(name).map_err(serde::de::Error::custom)?;
+        }
+        Ok(Self { root })
+    }
+}
 
 impl SnapShotDirectory {
-    /// Write out the directory structure at the given directory path.
+    /// 检查条目名称能否安全地写出到工作目录中
     ///
-    /// The target directory must already exist.
-    pub async fn write<Store: ObjectStore>(&self, store: &Store, path: &Path) -> Result<(), YsError> {
-        if read_dir(path).is_ok() {
-            for (file_name, entry) in self.root.iter() {
-                match entry {
-                    DirectoryEntry::File(id) => {
-                        let v = store.get(*id).await?;
-                        let mut f = File::options().create(true).write(true).open(path.join(file_name))?;
-                        f.write(&v)?;
-                    }
-                    DirectoryEntry::Directory(dir) => {
-                        dir.write(store, PathBuf::from(path).join(file_name).as_path()).await?;
-                    }
-                }
+    /// 名称必须是单个普通路径组件, 不能包含分隔符, 也不能指向 `.ys` 仓库.
+    pub fn check_name(name: &str) -> Result<(), YsError> {
+        let mut components = Path::new(name).components();
+        let normal = match (components.next(), components.next()) {
+            (Some(Component::Normal(o)), None) => o.to_str() == Some(name),
+            _ => false,
+        };
+        let reason = if name.is_empty() {
+            "名称为空"
+        }
+        else if name.contains(['/', '\\', '\0']) {
+            "名称中包含路径分隔符或空字符"
+        }
+        else if name.eq_ignore_ascii_case(DOT_YUAN_SHEN) {
+            "名称指向了 `.ys` 仓库"
+        }
+        else if !normal {
+            "名称不是一个普通的路径组件"
+        }
+        else {
+            return Ok(());
+        };
+        Err(YsErrorKind::InvalidEntryName { name: name.to_string(), reason })?
+    }
+    /// 递归检查目录树中的所有条目名称, 在写出到磁盘之前调用
+    pub fn check_names(&self) -> Result<(), YsError> {
+        for (name, entry) in self.root.iter() {
+            Self::check_name(name)?;
+            if let DirectoryEntry::Directory(dir) = entry {
+                dir.check_names()?;
             }
         }
         Ok(())
@@ -47,33 +71,3 @@ pub enum DirectoryEntry {
     Directory(Box<SnapShotDirectory>),
     File(ObjectID),
 }
-
-impl SnapShotDirectory {
-    pub fn new<Store: ObjectStore>(dir: &Path, ignores: &IgnoreRules, store: &mut Store) -> Result<Box<Self>, YsError> {
-        let mut root = BTreeMap::new();
-        for f in std::fs::read_dir(dir)? {
-            let dir_entry = f?;
-            if ignores.glob.contains(&dir_entry.file_name().into_string().unwrap()) {
-                continue;
-            }
-            let file_type = dir_entry.file_type()?;
-            if file_type.is_dir() {
-                let directory = SnapShotDirectory::new(dir_entry.path().as_path(), ignores, store)?;
-                root.insert(dir_entry.file_name().into_string().unwrap(), DirectoryEntry::Directory(directory));
-            }
-            else if file_type.is_file() {
-                let id = ObjectID::try_from(dir_entry.path().as_path())?;
-                root.insert(dir_entry.file_name().into_string().unwrap(), DirectoryEntry::File(id));
-                let mut v = Vec::new();
-                let mut obj_file = File::options().read(true).open(dir_entry.path())?;
-                obj_file.read_to_end(&mut v)?;
-                todo!()
-                // store.insert(&v).await.map_err(Error::Store)?;
-            }
-            else {
-                eprintln!("TODO support things which aren't files or directories: {:?}", dir_entry.file_name());
-            }
-        }
-        Ok(Box::new(SnapShotDirectory { root }))
-    }
-}
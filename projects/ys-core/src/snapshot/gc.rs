@@ -0,0 +1,80 @@
+use super::*;
+use crate::{
+    initialize::{DotYuanShenClient, YuanShenClient},
+    snapshot::{index::StagingIndex, reflog::REFLOG_EXPIRY, tag::TagObject},
+};
+
+/// 遍历时对象的种类, 决定如何找到它引用的对象
+#[derive(Copy, Clone, Debug)]
+enum Reachable {
+    /// 引用直接指向的对象, 快照或者标签对象
+    Reference,
+    /// 快照的目录结构
+    Directory,
+    /// 文件内容或忽略规则, 不会引用其他对象
+    Blob,
+}
+
+impl DotYuanShenClient {
+    /// 垃圾回收的根, 即所有世界线和标签直接指向的对象, 分离的 `HEAD` 指向的快照, 以及引用历史和操作日志中尚未过期的记录
+    ///
+    /// 附注标签的根是标签对象本身, 它指向的快照通过标签对象到达.
+    /// 引用历史和操作日志中的记录在 [REFLOG_EXPIRY] 之内保留前后两个状态的对象, 以便找回被覆盖的快照或者撤销操作.
+    pub fn gc_roots(&self) -> Result<BTreeSet<ObjectID>, YsError> {
+        let mut roots = BTreeSet::new();
+        for branch in self.branch_names()? {
+            roots.insert(self.get_branch_id(&branch)?);
+        }
+        for tag in self.tag_names()? {
+            roots.insert(self.get_tag_id(&tag)?);
+        }
+        roots.extend(self.head()?.detached());
+        let expired = SnapShotData::now().unwrap_or_default().saturating_sub(REFLOG_EXPIRY);
+        for name in self.reflog_names()? {
+            for entry in self.reflog(&name)?.into_iter().filter(|entry| entry.datetime >= expired) {
+                roots.extend(entry.old);
+                roots.insert(entry.new);
+            }
+        }
+        for entry in self.operations()?.into_iter().filter(|entry| entry.datetime >= expired) {
+            roots.extend(entry.before.references().into_values());
+            roots.extend(entry.after.references().into_values());
+            roots.extend(entry.before.head.detached());
+            roots.extend(entry.after.head.detached());
+        }
+        Ok(roots)
+    }
+    /// 从 [DotYuanShenClient::gc_roots] 出发可以到达的所有对象, 其余对象都可以被回收
+    ///
+    /// 标签对象引用其目标, 快照引用目录结构, 忽略规则和前驱, 目录结构引用其中的所有文件.
+    pub async fn reachable_objects(&self) -> Result<BTreeSet<ObjectID>, YsError> {
+        let store = self.store()?;
+        let mut reachable = BTreeSet::new();
+        let mut pending: Vec<_> = self.gc_roots()?.into_iter().map(|id| (id, Reachable::Reference)).collect();
+        while let Some((id, kind)) = pending.pop() {
+            if !reachable.insert(id) {
+                continue;
+            }
+            match kind {
+                Reachable::Reference => {
+                    let bytes = store.get(id).await?;
+                    if let Ok(tag) = serde_json::from_slice::<TagObject>(&bytes) {
+                        pending.push((tag.target, Reachable::Reference));
+                    }
+                    else if let Ok(snapshot) = serde_json::from_slice::<SnapShot>(&bytes) {
+                        pending.push((snapshot.directory, Reachable::Directory));
+                        pending.extend(snapshot.ignores.map(|id| (id, Reachable::Blob)));
+                        pending.extend(snapshot.previous.into_iter().map(|id| (id, Reachable::Reference)));
+                    }
+                }
+                Reachable::Directory => {
+                    let directory: SnapShotDirectory = store.get_typed(id).await?;
+                    let files = StagingIndex::from_directory(&directory).entries.into_values();
+                    pending.extend(files.map(|id| (id, Reachable::Blob)));
+                }
+                Reachable::Blob => {}
+            }
+        }
+        Ok(reachable)
+    }
+}
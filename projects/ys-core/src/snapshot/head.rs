@@ -0,0 +1,68 @@
+use super::*;
+use crate::{
+    initialize::{DotYuanShenClient, YuanShenClient},
+    snapshot::tag::peel_tag,
+};
+
+/// 当前所在的位置, 保存在 `.ys/branch` 中
+///
+/// 位于世界线上时文件内容是世界线的名称; 分离时是与引用文件相同的带版本的格式,
+/// 世界线的名称不能包含 `{`, 因此两者不会混淆.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Head {
+    /// 位于给定的世界线上, 提交会移动这条世界线
+    Branch(String),
+    /// 直接指向给定的快照, 提交只会移动 `HEAD`, 不属于任何世界线
+    Detached {
+        /// 当前的快照
+        detached: ObjectID,
+    },
+}
+
+impl Head {
+    /// 当前所在的世界线, 分离时返回 `None`
+    pub fn branch(&self) -> Option<&str> {
+        match self {
+            Self::Branch(name) => Some(name),
+            Self::Detached { .. } => None,
+        }
+    }
+    /// 分离时指向的快照, 位于世界线上时返回 `None`
+    pub fn detached(&self) -> Option<ObjectID> {
+        match self {
+            Self::Branch(_) => None,
+            Self::Detached { detached } => Some(*detached),
+        }
+    }
+}
+
+impl Display for Head {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Branch(name) => f.write_str(name),
+            Self::Detached { detached } => write!(f, "{}", detached),
+        }
+    }
+}
+
+impl DotYuanShenClient {
+    /// 检查快照能否从某条世界线或某个标签到达
+    ///
+    /// 分离状态下的提交不属于任何世界线, 离开后只能通过操作日志找回, 过期后会被垃圾回收.
+    pub async fn is_referenced(&self, id: ObjectID) -> Result<bool, YsError> {
+        for branch in self.branch_names()? {
+            if self.is_ancestor(id, self.get_branch_id(&branch)?).await? {
+                return Ok(true);
+            }
+        }
+        let store = self.store()?;
+        for tag in self.tag_names()? {
+            let target = peel_tag(&store, self.get_tag_id(&tag)?).await?;
+            if self.is_ancestor(id, target).await? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
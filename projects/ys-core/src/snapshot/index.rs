@@ -0,0 +1,219 @@
+use super::*;
+use crate::{
+    initialize::{DotYuanShenClient, YuanShenClient},
+    snapshot::scan::read_stored,
+    PathAttributes, YsErrorKind,
+};
+use std::{
+    fs::{remove_dir, remove_file, rename},
+    io::ErrorKind,
+};
+
+/// 暂存区, 储存在 `.ys/index` 中
+///
+/// 记录下一次提交的完整文件列表, 键是相对于工作目录的路径, 以 `/` 分隔.
+/// 暂存区中的文件不会与其祖先路径上的文件同时存在.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StagingIndex {
+    /// 暂存的文件及其内容的 id
+    pub entries: BTreeMap<String, ObjectID>,
+}
+
+impl StagingIndex {
+    /// 列出目录树中的所有文件
+    pub fn from_directory(directory: &SnapShotDirectory) -> Self {
+        let mut index = Self::default();
+        index.extend_directory("", directory);
+        index
+    }
+    /// 将目录树中的文件加入暂存区, `prefix` 是目录树相对于工作目录的路径
+    pub fn extend_directory(&mut self, prefix: &str, directory: &SnapShotDirectory) {
+        for (name, entry) in directory.root.iter() {
+            let relative = join_relative(prefix, name);
+            match entry {
+                DirectoryEntry::File(id) => self.stage(&relative, *id),
+                DirectoryEntry::Directory(dir) => self.extend_directory(&relative, dir),
+            }
+        }
+    }
+    /// 根据暂存区组装出下一次提交的目录树
+    pub fn to_directory(&self) -> Result<SnapShotDirectory, YsError> {
+        let mut root = SnapShotDirectory::default();
+        for (path, id) in self.entries.iter() {
+            let mut names = path.split('/').peekable();
+            let mut directory = &mut root;
+            while let Some(name) = names.next() {
+                SnapShotDirectory::check_name(name)?;
+                if names.peek().is_none() {
+                    directory.root.insert(name.to_string(), DirectoryEntry::File(*id));
+                    break;
+                }
+                let entry = directory
+                    .root
+                    .entry(name.to_string())
+                    .or_insert_with(|| DirectoryEntry::Directory(Box::default()));
+                directory = match entry {
+                    DirectoryEntry::Directory(dir) => dir,
+                    DirectoryEntry::File(_) => Err(YsErrorKind::InvalidObject {
+                        message: format!("暂存区中的 {:?} 同时是文件和目录", name),
+                    })?,
+                };
+            }
+        }
+        Ok(root)
+    }
+    /// 读取暂存区文件, 文件不存在时返回 `None`
+    pub fn load(path: &Path) -> Result<Option<Self>, YsError> {
+        match File::options().read(true).open(path) {
+            Ok(file) => Ok(Some(serde_json::from_reader(file)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(YsError::path_error(e, path)),
+        }
+    }
+    /// 将暂存区写入磁盘, 先写入临时文件再重命名, 保证不会留下写了一半的暂存区
+    pub fn save(&self, path: &Path) -> Result<(), YsError> {
+        let temp = path.with_extension("tmp");
+        std::fs::write(&temp, vec_json(self)?).map_err(|e| YsError::path_error(e, &temp))?;
+        rename(&temp, path).map_err(|e| YsError::path_error(e, path))?;
+        Ok(())
+    }
+    /// 暂存一个文件, 与它冲突的祖先文件和子路径都会被移除
+    pub fn stage(&mut self, relative: &str, id: ObjectID) {
+        self.unstage(relative);
+        for (index, _) in relative.match_indices('/') {
+            self.entries.remove(&relative[..index]);
+        }
+        self.entries.insert(relative.to_string(), id);
+    }
+    /// 移除路径本身以及其下的所有文件, 返回被移除的路径, 空路径表示整个工作目录
+    pub fn unstage(&mut self, relative: &str) -> Vec<String> {
+        let removed: Vec<String> = self.entries.keys().filter(|path| is_within(path, relative)).cloned().collect();
+        for path in removed.iter() {
+            self.entries.remove(path);
+        }
+        removed
+    }
+    /// 将路径本身以及其下的所有文件恢复为 `head` 中的状态
+    pub fn reset(&mut self, relative: &str, head: &StagingIndex) {
+        self.unstage(relative);
+        for (path, id) in head.entries.iter().filter(|(path, _)| is_within(path, relative)) {
+            self.stage(path, *id);
+        }
+    }
+}
+
+impl DotYuanShenClient {
+    /// 当前世界线最新快照的目录树
+    pub async fn head_directory(&self) -> Result<SnapShotDirectory, YsError> {
+        let store = self.store()?;
+        let snapshot: SnapShot = store.get_typed(self.calculate_branch_id()?).await?;
+        store.get_typed(snapshot.directory).await
+    }
+    /// 读取暂存区, 尚未创建暂存区时与当前世界线的最新快照相同
+    pub async fn index(&self) -> Result<StagingIndex, YsError> {
+        match self.read_index()? {
+            Some(index) => Ok(index),
+            None => Ok(StagingIndex::from_directory(&self.head_directory().await?)),
+        }
+    }
+    /// 将工作目录中的路径加入暂存区
+    ///
+    /// 目录会被完整扫描, 其中已经消失的文件会从暂存区中移除; 已被追踪但已消失的文件同样会被移除.
+    pub async fn add(&self, paths: &[PathBuf]) -> Result<(), YsError> {
+        let mut store = self.store()?;
+        let mut cache = self.stat_cache();
+        let mut index = self.index().await?;
+        for path in paths {
+            let relative = self.relative_path(path)?;
+            let full = self.working_directory().join(&relative);
+            match full.metadata() {
+                Ok(metadata) if metadata.is_dir() => {
+                    let (ignores, attributes) = match relative.rsplit_once('/') {
+                        _ if relative.is_empty() => (self.ignores()?, PathAttributes::default()),
+                        Some((parent, _)) => (self.ignores_within(parent)?, self.attributes_within(parent)?),
+                        None => (self.ignores_within("")?, self.attributes_within("")?),
+                    };
+                    let directory =
+                        SnapShotDirectory::scan(&full, &relative, &ignores, &attributes, &mut store, &mut cache).await?;
+                    index.unstage(&relative);
+                    index.extend_directory(&relative, &directory);
+                }
+                Ok(metadata) if metadata.is_file() => {
+                    let id = store.put(&read_stored(&full, &self.attributes(&full)?)?).await?;
+                    cache.insert(&relative, &metadata, id);
+                    index.stage(&relative, id);
+                }
+                Ok(_) => tracing::warn!("暂不支持文件和目录以外的条目: {:?}", full),
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    if index.unstage(&relative).is_empty() {
+                        Err(YsError::path_error(e, full))?
+                    }
+                }
+                Err(e) => Err(YsError::path_error(e, full))?,
+            }
+        }
+        self.save_stat_cache(&mut cache)?;
+        self.save_index(&index)
+    }
+    /// 将路径从暂存区中移除, 返回被移除的文件
+    ///
+    /// `cached` 为假时同时删除工作目录中的文件, 并清理因此变空的目录.
+    /// 工作目录中的文件有尚未暂存的修改时拒绝删除, 以免丢失修改, 除非给出 `force`.
+    pub async fn remove(&self, paths: &[PathBuf], cached: bool, force: bool) -> Result<Vec<String>, YsError> {
+        let changed = if cached || force { BTreeSet::new() } else { self.status().await?.changed };
+        let mut index = self.index().await?;
+        let mut removed = vec![];
+        for path in paths {
+            let relative = self.relative_path(path)?;
+            let files = index.unstage(&relative);
+            if files.is_empty() {
+                Err(YsErrorKind::InvalidPath { path: path.clone(), reason: "路径没有被追踪" })?
+            }
+            if let Some(file) = files.iter().find(|file| changed.contains(*file)) {
+                let path = self.working_directory().join(file);
+                Err(YsErrorKind::InvalidPath { path, reason: "文件有尚未暂存的修改, 使用 --force 强制移除" })?
+            }
+            removed.extend(files);
+        }
+        if !cached {
+            for relative in removed.iter() {
+                let full = self.working_directory().join(relative);
+                match remove_file(&full) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == ErrorKind::NotFound => {}
+                    Err(e) => Err(YsError::path_error(e, full))?,
+                }
+                let mut parent = Path::new(relative.as_str()).parent();
+                while let Some(dir) = parent.filter(|dir| !dir.as_os_str().is_empty()) {
+                    if remove_dir(self.working_directory().join(dir)).is_err() {
+                        break;
+                    }
+                    parent = dir.parent();
+                }
+            }
+        }
+        self.save_index(&index)?;
+        Ok(removed)
+    }
+    /// 将路径在暂存区中的状态恢复为当前世界线的最新快照, 不改动工作目录
+    ///
+    /// 没有给出路径时重置整个暂存区.
+    pub async fn reset(&self, paths: &[PathBuf]) -> Result<(), YsError> {
+        let head = StagingIndex::from_directory(&self.head_directory().await?);
+        let mut index = self.index().await?;
+        if paths.is_empty() {
+            index = head;
+        }
+        else {
+            for path in paths {
+                index.reset(&self.relative_path(path)?, &head);
+            }
+        }
+        self.save_index(&index)
+    }
+}
+
+/// 路径是否等于 `prefix` 或位于其中, 空的 `prefix` 包含所有路径
+pub(crate) fn is_within(path: &str, prefix: &str) -> bool {
+    prefix.is_empty() || path == prefix || path.strip_prefix(prefix).is_some_and(|rest| rest.starts_with('/'))
+}
@@ -1,16 +1,73 @@
 use super::*;
 use crate::{
-    utils::{read_json, truncate_write, write_json},
-    YsErrorKind,
+    objects::ignore_rules::{IgnoreRule, IGNORE_FILE},
+    snapshot::{
+        head::Head,
+        index::StagingIndex,
+        materialize::attributes_within,
+        operation::OperationEntry,
+        reflog::{reflog_path, ReflogEntry},
+        transaction::{replace_file, RefExpectation, RefTransaction},
+    },
+    utils::{append_json_line, read_json_lines},
+    LayeredConfig, PathAttributes, ResolvedAttributes, YsErrorKind, CONFIG_FILE,
 };
+use std::{env::var_os, path::Component};
+
+/// 列出引用目录中的所有名称, 目录不存在时返回空列表, 正在修改的引用留下的锁文件不会被列出
+fn list_references(directory: &Path) -> Result<Vec<String>, YsError> {
+    let entries = match read_dir(directory) {
+        Ok(o) => o,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => Err(YsError::path_error(e, directory))?,
+    };
+    let mut names = vec![];
+    for entry in entries {
+        match entry?.file_name().to_str() {
+            Some(name) if !name.ends_with(".lock") => names.push(name.to_string()),
+            _ => {}
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// 检查世界线或标签的名称能否用作 `.ys` 中的文件名, 并且不会与修订号的语法冲突
+pub(crate) fn check_reference_name(name: &str) -> Result<(), YsError> {
+    let reason = if name.is_empty() {
+        "名称不能为空"
+    }
+    else if matches!(name, "HEAD" | "@") {
+        "与当前世界线的别名冲突"
+    }
+    else if name.starts_with(['.', '-']) {
+        "不能以 `.` 或 `-` 开头"
+    }
+    else if name.contains("..") || name.contains("@{") {
+        "不能包含 `..` 或 `@{`"
+    }
+    else if name.ends_with(".lock") {
+        "不能以 `.lock` 结尾, 以免与锁文件混淆"
+    }
+    else if name.chars().any(|c| c.is_whitespace() || c.is_control() || "/\\~^:?*[]{}".contains(c)) {
+        "不能包含空白, 路径分隔符或修订号中使用的符号"
+    }
+    else {
+        return Ok(());
+    };
+    Err(YsErrorKind::InvalidReferenceName { name: name.to_string(), reason })?
+}
 
 /// `.ys` 文件夹
 #[derive(Debug)]
 pub struct DotYuanShenClient {
-    dot_root: PathBuf,
+    pub(crate) dot_root: PathBuf,
     dot_config: PathBuf,
 }
 
+/// 对象储存库在 `.ys` 中的位置, 初始化和之后打开仓库时必须一致
+const STORE_DIRECTORY: &str = "store";
+
 #[derive(Debug)]
 pub struct InitializeConfig {
     pub current: PathBuf,
@@ -19,42 +76,61 @@ pub struct InitializeConfig {
 }
 
 impl InitializeConfig {
-    ///
+    /// 在 `current` 中创建仓库并写入初始快照, 仓库已经存在时只把旧格式的引用改写为当前格式
     pub async fn generate(&self) -> Result<DotYuanShenClient, YsError> {
         let root = self.current.join(DOT_YUAN_SHEN);
         let config = self.current.join(".config").join("yuan-shen");
         if read_dir(&root).is_ok() {
-            return Ok(DotYuanShenClient { dot_root: root, dot_config: config });
+            // 重新初始化已有的仓库时升级旧格式的引用
+            let client = DotYuanShenClient { dot_root: root, dot_config: config };
+            client.migrate_references()?;
+            return Ok(client);
         }
         create_dir_all(&root)?;
         self.generate_branches()?;
-        self.generate_configs()?;
+        let ignores = self.generate_configs()?;
         // 创建初始提交
-        let mut store = LocalObjectStore::new(self.join("store"))?;
+        let mut store = LocalObjectStore::new(self.join(STORE_DIRECTORY))?;
         let directory = SnapShotDirectory::default();
         let directory = store.put_typed(&directory).await?;
         let snapshot = SnapShot {
             directory,
             previous: BTreeSet::new(),
-            data: SnapShotData { kind: 0, message: "Project initialized!".to_string(), authors: Default::default() },
+            data: SnapShotData {
+                kind: 0,
+                message: "Project initialized!".to_string(),
+                authors: Default::default(),
+                datetime: SnapShotData::now(),
+            },
+            ignores: Some(store.put_typed(&ignores).await?),
         };
         let snapshot_id = store.put_typed(&snapshot).await?;
-        write_json(&snapshot_id, &root.join("branches").join(self.initial_branch.as_ref()))?;
+        let mut transaction = RefTransaction::new(&root);
+        transaction.update(format!("branches/{}", self.initial_branch), snapshot_id, RefExpectation::Missing);
+        transaction.operation("init");
+        transaction.commit()?;
 
         Ok(DotYuanShenClient { dot_root: root, dot_config: config })
     }
     fn generate_branches(&self) -> std::io::Result<()> {
         // Specify the current branch
-        let mut file = File::options().create(true).write(true).open(self.join("branch"))?;
-        file.write(self.initial_branch.as_bytes())?;
+        std::fs::write(self.join("branch"), self.initial_branch.as_bytes())?;
         // Create the default branch
         create_dir(self.join("branches"))
     }
-    fn generate_configs(&self) -> std::io::Result<()> {
-        let ignore = self.current.join(".ys.ignore");
-        let mut file = File::options().create(true).write(true).open(ignore)?;
-        file.write(self.ignores.glob.as_bytes())?;
-        Ok(())
+    /// 写入根目录的 `.ys.ignore`, 项目中已经有这个文件时保留其中的规则, 返回实际使用的规则
+    fn generate_configs(&self) -> Result<IgnoreRules, YsError> {
+        let path = self.current.join(IGNORE_FILE);
+        match File::options().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                file.write_all(self.ignores.source().as_bytes()).map_err(|e| YsError::path_error(e, &path))?;
+                Ok(self.ignores.clone())
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                Ok(IgnoreRules::parse(read_to_string(&path).map_err(|e| YsError::path_error(e, &path))?))
+            }
+            Err(e) => Err(YsError::path_error(e, &path))?,
+        }
     }
     fn join(&self, path: &str) -> PathBuf {
         self.current.join(DOT_YUAN_SHEN).join(path)
@@ -74,15 +150,73 @@ impl DotYuanShenClient {
         if !dot_root.exists() {
             Err(YsError::path_error(std::io::Error::new(std::io::ErrorKind::NotFound, "Folder `.ys` does not exist"), path))?
         }
+        // 仓库的配置目录是可选的, 不存在时只使用系统和用户的配置
         let dot_config = path.join(".config").join("yuan-shen");
-        // if !dot_config.exists() {
-        //     Err(YsError::path_error(
-        //         std::io::Error::new(std::io::ErrorKind::NotFound, "Folder `.config/yuan-shen` does not exist"),
-        //         path,
-        //     ))?
-        // }
         Ok(Self { dot_root, dot_config })
     }
+
+    /// 从给定目录开始逐级向上查找 `.ys` 仓库, 打开找到的第一个
+    ///
+    /// 除非给出 `across_filesystem`, 查找在遇到另一个文件系统的挂载点时停止.
+    pub fn discover(start: &Path, across_filesystem: bool) -> Result<Self, YsError> {
+        let filesystem = filesystem_id(start);
+        for directory in start.ancestors() {
+            if !across_filesystem && filesystem.is_some() && filesystem_id(directory) != filesystem {
+                break;
+            }
+            if directory.join(DOT_YUAN_SHEN).is_dir() {
+                return Self::open(directory);
+            }
+        }
+        Err(YsErrorKind::RepositoryNotFound { path: start.to_path_buf() })?
+    }
+
+    /// 按照命令行参数和环境变量打开命令行所在位置的仓库
+    ///
+    /// 给出 `repo` (命令行的 `--repo`) 或设置了 [`YS_DIR`] 时直接打开指定的仓库, 见 [requested_work_tree].
+    /// 否则从 `current` 向上查找, 设置了 [`YS_DISCOVERY_ACROSS_FILESYSTEM`] 时会越过文件系统的边界.
+    pub fn from_env(current: &Path, repo: Option<&Path>) -> Result<Self, YsError> {
+        if let Some(path) = requested_work_tree(current, repo) {
+            let path = path.canonicalize().map_err(|e| YsError::path_error(e, &path))?;
+            return Self::open(&path);
+        }
+        let across =
+            var_os(YS_DISCOVERY_ACROSS_FILESYSTEM).is_some_and(|value| !matches!(value.to_str(), Some("" | "0" | "false")));
+        Self::discover(current, across)
+    }
+}
+
+/// 环境变量, 指定仓库的工作目录或 `.ys` 文件夹, 不再向上查找
+pub const YS_DIR: &str = "YS_DIR";
+
+/// 明确指定的仓库的工作目录, `repo` 优先于 [`YS_DIR`], 都没有给出时返回 `None`
+///
+/// 两者都可以是工作目录或其中的 `.ys` 文件夹, 相对路径以 `current` 为起点. 指定的目录不一定已经存在.
+pub fn requested_work_tree(current: &Path, repo: Option<&Path>) -> Option<PathBuf> {
+    let dir = match repo {
+        Some(repo) => current.join(repo),
+        None => current.join(var_os(YS_DIR).filter(|dir| !dir.is_empty())?),
+    };
+    match dir.file_name() {
+        Some(name) if name == DOT_YUAN_SHEN => Some(dir.parent().map_or_else(|| dir.clone(), Path::to_path_buf)),
+        _ => Some(dir),
+    }
+}
+
+/// 环境变量, 设置后向上查找仓库时允许越过文件系统的边界
+pub const YS_DISCOVERY_ACROSS_FILESYSTEM: &str = "YS_DISCOVERY_ACROSS_FILESYSTEM";
+
+/// 路径所在的文件系统, 无法判断时返回 `None`
+#[cfg(unix)]
+fn filesystem_id(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    path.metadata().ok().map(|metadata| metadata.dev())
+}
+
+/// 路径所在的文件系统, 无法判断时返回 `None`
+#[cfg(not(unix))]
+fn filesystem_id(_: &Path) -> Option<u64> {
+    None
 }
 
 /// Describe the capabilities of the YuanShen client
@@ -108,16 +242,19 @@ impl YuanShenClient for DotYuanShenClient {
         ObjectID::read_branch(&self.dot_root, branch)
     }
     fn calculate_branch_id(&self) -> Result<ObjectID, YsError> {
-        let branch = self.get_branch_name()?;
-        self.get_branch_id(&branch)
+        match self.head()? {
+            Head::Branch(branch) => self.get_branch_id(&branch),
+            Head::Detached { detached } => Ok(detached),
+        }
     }
     fn get_branch_name(&self) -> Result<String, YsError> {
-        Ok(read_to_string(&self.dot_root.join("branch"))?)
+        match self.head()? {
+            Head::Branch(branch) => Ok(branch),
+            Head::Detached { detached } => Err(YsErrorKind::DetachedHead { id: detached })?,
+        }
     }
     fn set_branch(&self, new: &str) -> Result<(), YsError> {
-        let branch_path = self.dot_root.join("branch");
-        truncate_write(branch_path, new.as_bytes())?;
-        Ok(())
+        replace_file(&self.dot_root.join("branch"), new.as_bytes())
     }
 
     fn create_branch(&self, name: &str) -> Result<ObjectID, YsError> {
@@ -127,15 +264,46 @@ impl YuanShenClient for DotYuanShenClient {
         }
         else {
             let snapshot_id = self.calculate_branch_id()?;
-            truncate_write(path, snapshot_id.to_string().as_bytes())?;
+            let mut transaction = self.transaction();
+            transaction.update(format!("branches/{}", name), snapshot_id, RefExpectation::Missing);
+            transaction.operation(format!("branch: created from {}", self.head()?));
+            transaction.commit()?;
             Ok(snapshot_id)
         }
     }
 }
 
 impl DotYuanShenClient {
+    /// 开始一个修改引用的事务
+    pub fn transaction(&self) -> RefTransaction {
+        RefTransaction::new(&self.dot_root)
+    }
+
+    /// 读取当前所在的位置
+    pub fn head(&self) -> Result<Head, YsError> {
+        let path = self.dot_root.join("branch");
+        let text = read_to_string(&path).map_err(|e| YsError::path_error(e, &path))?;
+        if text.trim_start().starts_with('{') {
+            Ok(Head::Detached { detached: ObjectID::read_reference(&path)? })
+        }
+        else {
+            Ok(Head::Branch(text))
+        }
+    }
+
+    /// 切换到给定的世界线, 或者分离到给定的快照, 不改动工作目录
+    pub fn set_head(&self, head: &Head) -> Result<(), YsError> {
+        match head {
+            Head::Branch(branch) => self.set_branch(branch),
+            Head::Detached { detached } => replace_file(&self.dot_root.join("branch"), &detached.reference_bytes()?),
+        }
+    }
+
+    /// 将世界线指向给定的快照, 不检查世界线原先的值
     pub fn set_branch_snapshot_id(&self, branch: &str, object_id: ObjectID) -> Result<(), YsError> {
-        write_json(&object_id, &self.dot_root.join("branches").join(&branch))
+        let mut transaction = self.transaction();
+        transaction.update(format!("branches/{}", branch), object_id, RefExpectation::Any);
+        transaction.commit()
     }
 
     /// Checks whether a branch with a given name exists
@@ -143,11 +311,301 @@ impl DotYuanShenClient {
         Ok(try_exists(self.dot_root.join("branches").join(&branch))?)
     }
 
+    /// 所有世界线的名称, 按名称排序
+    pub fn branch_names(&self) -> Result<Vec<String>, YsError> {
+        list_references(&self.dot_root.join("branches"))
+    }
+
+    /// 从给定的快照创建世界线, 已经存在时只有给出 `force` 才会覆盖
+    ///
+    /// 当前所在的世界线不能被覆盖, 否则工作目录和暂存区会与世界线指向的快照不一致.
+    pub fn create_branch_at(&self, name: &str, id: ObjectID, force: bool) -> Result<(), YsError> {
+        check_reference_name(name)?;
+        if self.branch_exists(name)? {
+            if !force {
+                Err(YsErrorKind::ReferenceExists { name: format!("branches/{}", name) })?
+            }
+            if self.head()?.branch() == Some(name) {
+                Err(YsErrorKind::CheckedOutBranch { name: name.to_string() })?
+            }
+        }
+        let mut transaction = self.transaction();
+        transaction.update(format!("branches/{}", name), id, if force { RefExpectation::Any } else { RefExpectation::Missing });
+        transaction.operation(if force { "branch: reset" } else { "branch: created" });
+        transaction.commit()
+    }
+
+    /// 删除世界线的引用文件, 返回它原先指向的快照, 不做任何检查
+    pub fn remove_branch(&self, name: &str) -> Result<ObjectID, YsError> {
+        let id = self.existing_branch_id(name)?;
+        let mut transaction = self.transaction();
+        transaction.delete(format!("branches/{}", name), RefExpectation::Value(id));
+        transaction.commit()?;
+        Ok(id)
+    }
+
+    /// 重命名世界线, 当前所在的世界线被重命名时同时切换到新名称
+    ///
+    /// 新名称已经存在时只有给出 `force` 才会覆盖.
+    pub fn rename_branch(&self, old: &str, new: &str, force: bool) -> Result<(), YsError> {
+        let id = self.existing_branch_id(old)?;
+        if old == new {
+            return Ok(());
+        }
+        check_reference_name(new)?;
+        if !force && self.branch_exists(new)? {
+            Err(YsErrorKind::ReferenceExists { name: format!("branches/{}", new) })?
+        }
+        let mut transaction = self.transaction();
+        transaction.update(format!("branches/{}", new), id, if force { RefExpectation::Any } else { RefExpectation::Missing });
+        transaction.inherit_reflog(format!("branches/{}", old));
+        transaction.delete(format!("branches/{}", old), RefExpectation::Value(id));
+        transaction.operation(format!("branch: renamed {} to {}", old, new));
+        transaction.commit()?;
+        if self.head()?.branch() == Some(old) {
+            self.set_branch(new)?;
+        }
+        Ok(())
+    }
+
+    /// 读取已经存在的世界线, 不存在时返回 [YsErrorKind::MissingReference]
+    fn existing_branch_id(&self, name: &str) -> Result<ObjectID, YsError> {
+        if check_reference_name(name).is_err() || !self.branch_exists(name)? {
+            Err(YsErrorKind::MissingReference { name: format!("branches/{}", name) })?
+        }
+        self.get_branch_id(name)
+    }
+
+    /// 所有标签的名称, 按名称排序
+    pub fn tag_names(&self) -> Result<Vec<String>, YsError> {
+        list_references(&self.dot_root.join("tags"))
+    }
+
+    /// 检查给定名称的标签是否存在, 名称不合法时视为不存在
+    pub fn tag_exists(&self, name: &str) -> Result<bool, YsError> {
+        Ok(check_reference_name(name).is_ok() && try_exists(self.dot_root.join("tags").join(name))?)
+    }
+
+    /// 读取标签指向的对象, 附注标签指向标签对象, 轻量标签直接指向快照
+    pub fn get_tag_id(&self, name: &str) -> Result<ObjectID, YsError> {
+        if !self.tag_exists(name)? {
+            Err(YsErrorKind::MissingReference { name: format!("tags/{}", name) })?
+        }
+        ObjectID::read_reference(&self.dot_root.join("tags").join(name))
+    }
+
+    /// 把早期版本写入的世界线和标签改写为当前格式, 返回被改写的引用名称
+    ///
+    /// 所有引用在同一个事务中改写, 引用的值不变, 也不会留下引用历史.
+    pub fn migrate_references(&self) -> Result<Vec<String>, YsError> {
+        let mut transaction = self.transaction();
+        transaction.operation("migrate");
+        let mut migrated = vec![];
+        for (kind, names) in [("branches", self.branch_names()?), ("tags", self.tag_names()?)] {
+            for name in names {
+                let name = format!("{}/{}", kind, name);
+                if let (id, true) = ObjectID::parse_reference(&self.dot_root.join(&name))? {
+                    transaction.update(name.as_str(), id, RefExpectation::Value(id));
+                    migrated.push(name);
+                }
+            }
+        }
+        if !migrated.is_empty() {
+            transaction.commit()?;
+        }
+        Ok(migrated)
+    }
+
+    /// 将标签指向给定的对象
+    ///
+    /// 标签一经创建就不应移动, 已经存在时只有给出 `force` 才会覆盖.
+    pub fn write_tag(&self, name: &str, id: ObjectID, force: bool) -> Result<(), YsError> {
+        if !force && self.tag_exists(name)? {
+            Err(YsErrorKind::ReferenceExists { name: format!("tags/{}", name) })?
+        }
+        let mut transaction = self.transaction();
+        transaction.update(format!("tags/{}", name), id, if force { RefExpectation::Any } else { RefExpectation::Missing });
+        transaction.operation(if force { "tag: moved" } else { "tag: created" });
+        transaction.commit()
+    }
+
+    /// 删除标签, 返回它原先指向的对象
+    pub fn delete_tag(&self, name: &str) -> Result<ObjectID, YsError> {
+        let id = self.get_tag_id(name)?;
+        let mut transaction = self.transaction();
+        transaction.delete(format!("tags/{}", name), RefExpectation::Value(id));
+        transaction.commit()?;
+        Ok(id)
+    }
+
+    /// 引用的历史, 按时间顺序排列, 例如 `reflog("branches/main")`
+    pub fn reflog(&self, name: &str) -> Result<Vec<ReflogEntry>, YsError> {
+        read_json_lines(&reflog_path(&self.dot_root, name))
+    }
+
+    /// 所有有历史记录的引用的名称, 例如 `branches/main` 和 `tags/v1.0`
+    pub fn reflog_names(&self) -> Result<Vec<String>, YsError> {
+        let mut names = vec![];
+        for kind in ["branches", "tags"] {
+            for name in list_references(&self.dot_root.join("logs").join(kind))? {
+                names.push(format!("{}/{}", kind, name));
+            }
+        }
+        Ok(names)
+    }
+
+    /// 操作日志, 按时间顺序排列
+    pub fn operations(&self) -> Result<Vec<OperationEntry>, YsError> {
+        read_json_lines(&self.dot_root.join("operations"))
+    }
+
+    /// 在操作日志的末尾追加一条记录
+    pub(crate) fn append_operation(&self, entry: &OperationEntry) -> Result<(), YsError> {
+        append_json_line(&self.dot_root.join("operations"), entry)
+    }
+
+    /// 工作目录, 即 `.ys` 文件夹所在的目录
+    pub fn working_directory(&self) -> &Path {
+        self.dot_root.parent().unwrap_or(&self.dot_root)
+    }
+
+    /// 读取工作目录的元数据缓存
+    pub fn stat_cache(&self) -> StatCache {
+        StatCache::load(&self.dot_root.join("stat-cache"))
+    }
+
+    /// 保存工作目录的元数据缓存
+    pub fn save_stat_cache(&self, cache: &mut StatCache) -> Result<(), YsError> {
+        cache.save(&self.dot_root.join("stat-cache"))
+    }
+
+    /// 读取暂存区, 尚未创建暂存区时返回 `None`
+    pub fn read_index(&self) -> Result<Option<StagingIndex>, YsError> {
+        StagingIndex::load(&self.dot_root.join("index"))
+    }
+
+    /// 保存暂存区
+    pub fn save_index(&self, index: &StagingIndex) -> Result<(), YsError> {
+        index.save(&self.dot_root.join("index"))
+    }
+
+    /// 将路径转换为相对于工作目录的路径, 以 `/` 分隔, 空字符串表示工作目录本身
+    ///
+    /// 相对路径以工作目录为起点. 指向工作目录之外或 `.ys` 仓库之中的路径会被拒绝.
+    pub fn relative_path(&self, path: &Path) -> Result<String, YsError> {
+        let outside = || YsErrorKind::InvalidPath { path: path.to_path_buf(), reason: "路径不在工作目录中" };
+        let full = self.working_directory().join(path);
+        let rest = full.strip_prefix(self.working_directory()).map_err(|_| outside())?;
+        let mut names = vec![];
+        for component in rest.components() {
+            match component {
+                Component::Normal(name) => names.push(
+                    name.to_str()
+                        .ok_or(YsErrorKind::InvalidPath { path: path.to_path_buf(), reason: "路径不是有效的 UTF-8" })?,
+                ),
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    names.pop().ok_or_else(outside)?;
+                }
+                Component::RootDir | Component::Prefix(_) => Err(outside())?,
+            }
+        }
+        if names.first().is_some_and(|name| name.eq_ignore_ascii_case(DOT_YUAN_SHEN)) {
+            Err(YsErrorKind::InvalidPath { path: path.to_path_buf(), reason: "路径位于 `.ys` 仓库之中" })?
+        }
+        Ok(names.join("/"))
+    }
+
+    /// 对目录中的路径生效的属性规则, 包括从工作目录到该目录的每一层 `.ys.attributes`
+    ///
+    /// `directory` 是相对于工作目录的路径, 以 `/` 分隔.
+    pub fn attributes_within(&self, directory: &str) -> Result<PathAttributes, YsError> {
+        attributes_within(self.working_directory(), directory)
+    }
+
+    /// 计算路径最终生效的属性
+    pub fn attributes(&self, path: &Path) -> Result<ResolvedAttributes, YsError> {
+        let relative = self.relative_path(path)?;
+        let parent = relative.rsplit_once('/').map(|(parent, _)| parent).unwrap_or("");
+        Ok(self.attributes_within(parent)?.resolve(&relative))
+    }
+
+    /// 找到决定路径是否被忽略的规则
+    ///
+    /// 路径所在的某一层目录被忽略时, 返回忽略该目录的规则. 返回 `!` 规则表示路径被重新包含.
+    pub fn check_ignore(&self, path: &Path) -> Result<Option<IgnoreRule>, YsError> {
+        let relative = self.relative_path(path)?;
+        let names: Vec<&str> = relative.split('/').filter(|name| !name.is_empty()).collect();
+        let mut rules = self.ignores()?.enter(self.working_directory(), "")?.into_owned();
+        let mut prefix = String::new();
+        for (index, name) in names.iter().enumerate() {
+            prefix = join_relative(&prefix, name);
+            let full = self.working_directory().join(&prefix);
+            let last = index + 1 == names.len();
+            let is_dir = !last || full.is_dir();
+            match rules.matched(&prefix, is_dir) {
+                Some(rule) if last || !rule.negated => return Ok(Some(rule.clone())),
+                _ => {}
+            }
+            if !last {
+                rules = rules.enter(&full, &prefix)?.into_owned();
+            }
+        }
+        Ok(None)
+    }
+
+    /// 叠加系统, 用户, 仓库和环境变量的配置
+    pub fn config(&self) -> Result<LayeredConfig, YsError> {
+        LayeredConfig::load(Some(&self.dot_config))
+    }
+
+    /// 仓库的配置文件, 位于工作目录的 `.config/yuan-shen/config.toml`
+    pub fn config_path(&self) -> PathBuf {
+        self.dot_config.join(CONFIG_FILE)
+    }
+
+    /// 仓库的对象储存库, 位于 `.ys/store`, 与初始化时写入初始快照的位置相同
+    ///
+    /// 写入时使用配置项 `core.compression-level` 的压缩级别.
     pub fn store(&self) -> Result<LocalObjectStore, YsError> {
-        Ok(LocalObjectStore::new(self.dot_root.clone())?)
+        let level = self.config()?.resolve()?.core.compression_level.unwrap_or(0);
+        Ok(LocalObjectStore::new(self.dot_root.join(STORE_DIRECTORY))?.with_compression_level(level))
     }
 
+    /// 对整个工作目录生效的忽略规则, 包括用户的全局规则, 配置项 `core.ignore-files` 和仓库的 `.ys/exclude`
+    ///
+    /// 各个目录中的 `.ys.ignore` 会在遍历时通过 [`IgnoreRules::enter`] 逐层加入.
     pub fn ignores(&self) -> Result<IgnoreRules, YsError> {
-        Ok(read_json(&self.dot_root.join("ignores"))?)
+        let mut rules = IgnoreRules::parse("");
+        if let Some(global) = IgnoreRules::global_path() {
+            rules.extend_file(&global, "")?;
+        }
+        for file in self.config()?.resolve()?.core.ignore_files {
+            rules.extend_file(&self.working_directory().join(file), "")?;
+        }
+        rules.extend_file(&self.dot_root.join("exclude"), "")?;
+        Ok(rules)
+    }
+
+    /// 读取快照记录的忽略规则, 没有记录时使用默认规则
+    pub async fn snapshot_ignores(&self, snapshot: &SnapShot) -> Result<IgnoreRules, YsError> {
+        match snapshot.ignores {
+            Some(id) => self.store()?.get_typed(id).await,
+            None => Ok(IgnoreRules::default()),
+        }
+    }
+
+    /// 对目录中的路径生效的忽略规则, 包括从工作目录到该目录的每一层 `.ys.ignore`
+    ///
+    /// `directory` 是相对于工作目录的路径, 以 `/` 分隔.
+    pub fn ignores_within(&self, directory: &str) -> Result<IgnoreRules, YsError> {
+        let mut rules = self.ignores()?;
+        let mut prefix = String::new();
+        rules = rules.enter(self.working_directory(), "")?.into_owned();
+        for name in directory.split('/').filter(|name| !name.is_empty()) {
+            prefix = join_relative(&prefix, name);
+            rules = rules.enter(&self.working_directory().join(&prefix), &prefix)?.into_owned();
+        }
+        Ok(rules)
     }
 }
@@ -0,0 +1,173 @@
+use super::*;
+use crate::{snapshot::scan::read_stored, PathAttributes, ResolvedAttributes, ATTRIBUTES_FILE};
+use std::{
+    fs::{remove_dir_all, remove_file, symlink_metadata},
+    io::ErrorKind,
+};
+
+/// 写出目录树时对某个路径执行的操作
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MaterializeAction {
+    /// 创建了缺失的目录
+    CreateDirectory,
+    /// 写入了文件, 原有内容会被截断
+    WriteFile,
+    /// 删除了文件
+    RemoveFile,
+    /// 删除了目录及其中的全部内容
+    RemoveDirectory,
+}
+
+/// 写出目录树的结果, 按执行顺序记录每一个被改动的路径
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MaterializeReport {
+    /// 被改动的路径及对其执行的操作
+    pub actions: Vec<(PathBuf, MaterializeAction)>,
+}
+
+impl MaterializeReport {
+    /// 没有改动任何路径
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+    pub(crate) fn record(&mut self, path: &Path, action: MaterializeAction) {
+        tracing::trace!("{:?}: {:?}", action, path);
+        self.actions.push((path.to_path_buf(), action));
+    }
+}
+
+impl SnapShotDirectory {
+    /// 将目录树完整地写出到给定路径
+    ///
+    /// 缺失的目录会被创建, 内容不同的文件会被截断重写, 不在目录树中且没有被忽略的路径会被删除.
+    /// 文件按写出的 `.ys.attributes` 转换换行符.
+    pub async fn materialize<Store: ObjectStore>(
+        &self,
+        store: &Store,
+        path: &Path,
+        ignores: &IgnoreRules,
+    ) -> Result<MaterializeReport, YsError> {
+        self.check_names()?;
+        let mut report = MaterializeReport::default();
+        self.materialize_into(store, path, "", ignores, &PathAttributes::default(), &mut report).await?;
+        Ok(report)
+    }
+
+    async fn materialize_into<Store: ObjectStore>(
+        &self,
+        store: &Store,
+        path: &Path,
+        prefix: &str,
+        ignores: &IgnoreRules,
+        attributes: &PathAttributes,
+        report: &mut MaterializeReport,
+    ) -> Result<(), YsError> {
+        ensure_directory(path, report)?;
+        let entered = ignores.enter(path, prefix)?;
+        let ignores: &IgnoreRules = &entered;
+        for item in read_dir(path).map_err(|e| YsError::path_error(e, path))? {
+            let item = item?;
+            let Ok(name) = item.file_name().into_string()
+            else {
+                continue;
+            };
+            if name.eq(DOT_YUAN_SHEN) || self.root.contains_key(&name) {
+                continue;
+            }
+            if ignores.is_ignored(&join_relative(prefix, &name), item.file_type()?.is_dir()) {
+                continue;
+            }
+            remove_path(&item.path(), report)?;
+        }
+        // 先写出属性文件, 其余文件才能按其中的规则转换
+        if let Some(DirectoryEntry::File(id)) = self.root.get(ATTRIBUTES_FILE) {
+            write_file(store, *id, &path.join(ATTRIBUTES_FILE), &ResolvedAttributes::default(), report).await?;
+        }
+        let attributes = attributes.enter(path, prefix)?;
+        for (name, entry) in self.root.iter().filter(|(name, _)| *name != ATTRIBUTES_FILE) {
+            let entry_path = path.join(name);
+            let relative = join_relative(prefix, name);
+            match entry {
+                DirectoryEntry::File(id) => {
+                    write_file(store, *id, &entry_path, &attributes.resolve(&relative), report).await?
+                }
+                DirectoryEntry::Directory(dir) => {
+                    Box::pin(dir.materialize_into(store, &entry_path, &relative, ignores, &attributes, report)).await?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 确保给定路径是一个目录, 占据该路径的文件会被删除
+pub(crate) fn ensure_directory(path: &Path, report: &mut MaterializeReport) -> Result<(), YsError> {
+    match symlink_metadata(path) {
+        Ok(o) if o.is_dir() => return Ok(()),
+        Ok(_) => {
+            remove_file(path).map_err(|e| YsError::path_error(e, path))?;
+            report.record(path, MaterializeAction::RemoveFile);
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => Err(YsError::path_error(e, path))?,
+    }
+    create_dir_all(path).map_err(|e| YsError::path_error(e, path))?;
+    report.record(path, MaterializeAction::CreateDirectory);
+    Ok(())
+}
+
+/// 将对象按路径属性转换后写入到给定路径, 内容相同的文件不会被重写
+pub(crate) async fn write_file<Store: ObjectStore>(
+    store: &Store,
+    id: ObjectID,
+    path: &Path,
+    attributes: &ResolvedAttributes,
+    report: &mut MaterializeReport,
+) -> Result<(), YsError> {
+    match symlink_metadata(path) {
+        Ok(o) if o.is_dir() => {
+            remove_dir_all(path).map_err(|e| YsError::path_error(e, path))?;
+            report.record(path, MaterializeAction::RemoveDirectory);
+        }
+        Ok(o) if o.is_file() && ObjectID::from(read_stored(path, attributes)?.as_slice()) == id => {
+            return Ok(());
+        }
+        _ => {}
+    }
+    let bytes = attributes.to_working(store.get(id).await?);
+    let mut file =
+        File::options().create(true).write(true).truncate(true).open(path).map_err(|e| YsError::path_error(e, path))?;
+    file.write_all(&bytes).map_err(|e| YsError::path_error(e, path))?;
+    report.record(path, MaterializeAction::WriteFile);
+    Ok(())
+}
+
+/// 对目录中的文件生效的属性规则, 逐层读取 `root` 到该目录的 `.ys.attributes`
+///
+/// `directory` 是相对于 `root` 的路径, 以 `/` 分隔.
+pub(crate) fn attributes_within(root: &Path, directory: &str) -> Result<PathAttributes, YsError> {
+    let mut attributes = PathAttributes::default().enter(root, "")?.into_owned();
+    let mut prefix = String::new();
+    for name in directory.split('/').filter(|name| !name.is_empty()) {
+        prefix = join_relative(&prefix, name);
+        attributes = attributes.enter(&root.join(&prefix), &prefix)?.into_owned();
+    }
+    Ok(attributes)
+}
+
+/// 删除给定路径上的文件或目录
+pub(crate) fn remove_path(path: &Path, report: &mut MaterializeReport) -> Result<(), YsError> {
+    match symlink_metadata(path) {
+        Ok(o) if o.is_dir() => {
+            remove_dir_all(path).map_err(|e| YsError::path_error(e, path))?;
+            report.record(path, MaterializeAction::RemoveDirectory);
+        }
+        Ok(_) => {
+            remove_file(path).map_err(|e| YsError::path_error(e, path))?;
+            report.record(path, MaterializeAction::RemoveFile);
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => Err(YsError::path_error(e, path))?,
+    }
+    Ok(())
+}
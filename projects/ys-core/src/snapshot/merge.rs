@@ -0,0 +1,105 @@
+use super::*;
+use crate::{
+    differences::{RenameOptions, SnapShotDifference},
+    snapshot::index::StagingIndex,
+};
+
+/// 三方合并两棵目录树的结果
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TreeMerge {
+    /// 合并后的文件列表, 存在冲突时冲突的路径保留当前一方的版本
+    pub index: StagingIndex,
+    /// 两方都修改了且修改不同的路径, 以当前一方的路径为准
+    pub conflicts: BTreeSet<String>,
+}
+
+impl TreeMerge {
+    /// 是否没有冲突
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+impl SnapShotDirectory {
+    /// 以 `self` 为共同祖先, 按文件合并 `ours` 和 `theirs`
+    ///
+    /// 两方的重命名都会被检测, 一方重命名而另一方修改时, 修改跟随重命名后的文件.
+    /// 只比较文件的 id, 两方对同一个文件做了不同修改, 或者一方修改而另一方删除时记为冲突.
+    pub async fn merge<Store: ObjectStore>(
+        &self,
+        ours: &SnapShotDirectory,
+        theirs: &SnapShotDirectory,
+        store: &Store,
+        options: &RenameOptions,
+    ) -> Result<TreeMerge, YsError> {
+        let options = RenameOptions { copies: false, ..*options };
+        let ours_moves = self.difference_with_renames(ours, store, &options).await?;
+        let theirs_moves = self.difference_with_renames(theirs, store, &options).await?;
+        let ours_moves = moved_paths(&ours_moves);
+        let theirs_moves = moved_paths(&theirs_moves);
+        let base = StagingIndex::from_directory(self).entries;
+        let ours = StagingIndex::from_directory(ours).entries;
+        let theirs = StagingIndex::from_directory(theirs).entries;
+        let mut merge = TreeMerge { index: StagingIndex { entries: ours.clone() }, conflicts: BTreeSet::new() };
+        for (path, base_id) in base.iter() {
+            let path = path.as_str();
+            let ours_path = ours_moves.get(path).copied().unwrap_or(path);
+            let theirs_path = theirs_moves.get(path).copied().unwrap_or(path);
+            let (ours_id, theirs_id) = (ours.get(ours_path), theirs.get(theirs_path));
+            if theirs_path == path && theirs_id == Some(base_id) {
+                continue;
+            }
+            let target = match (ours_path == path, theirs_path == path) {
+                (true, _) => theirs_path,
+                (false, true) => ours_path,
+                (false, false) if ours_path == theirs_path => ours_path,
+                (false, false) => {
+                    merge.conflicts.insert(ours_path.to_string());
+                    continue;
+                }
+            };
+            let merged = if ours_id == Some(base_id) || ours_id == theirs_id {
+                theirs_id
+            }
+            else if theirs_id == Some(base_id) {
+                ours_id
+            }
+            else {
+                merge.conflicts.insert(ours_path.to_string());
+                continue;
+            };
+            if target != ours_path {
+                if ours.get(target).is_some_and(|id| Some(id) != merged) {
+                    merge.conflicts.insert(target.to_string());
+                    continue;
+                }
+                merge.index.entries.remove(ours_path);
+            }
+            match merged {
+                Some(id) => merge.index.entries.insert(target.to_string(), *id),
+                None => merge.index.entries.remove(target),
+            };
+        }
+        let renamed: BTreeSet<&str> = theirs_moves.values().copied().collect();
+        for (path, theirs_id) in theirs.iter().filter(|(path, _)| !base.contains_key(*path)) {
+            if renamed.contains(path.as_str()) {
+                continue;
+            }
+            match merge.index.entries.get(path) {
+                Some(id) if id == theirs_id => {}
+                Some(_) => {
+                    merge.conflicts.insert(path.clone());
+                }
+                None => {
+                    merge.index.entries.insert(path.clone(), *theirs_id);
+                }
+            }
+        }
+        Ok(merge)
+    }
+}
+
+/// 被重命名的文件从旧路径到新路径的映射
+fn moved_paths(difference: &SnapShotDifference) -> BTreeMap<&str, &str> {
+    difference.renamed.iter().map(|(target, entry)| (entry.from.as_str(), target.as_str())).collect()
+}
@@ -1,6 +1,8 @@
 use crate::{
-    errors::YsError, snapshot::directory::SnapShotDirectory, AuthorID, DirectoryEntry, IgnoreRules, LocalObjectStore, ObjectID,
-    ObjectStore,   DOT_YUAN_SHEN,
+    errors::YsError,
+    snapshot::{directory::SnapShotDirectory, stat_cache::StatCache},
+    utils::{read_json, vec_json},
+    AuthorID, DirectoryEntry, IgnoreRules, LocalObjectStore, ObjectID, ObjectStore, DOT_YUAN_SHEN,
 };
 use serde::{Deserialize, Serialize};
 use std::{
@@ -10,11 +12,28 @@ use std::{
     fs::{create_dir, create_dir_all, read_dir, read_to_string, try_exists, File},
     io::Write,
     path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
+pub mod branch;
+mod checkout;
 pub mod differences;
 pub mod directory;
+mod gc;
+pub mod head;
+pub mod index;
 pub mod initialize;
+pub mod materialize;
+pub mod merge;
+pub mod operation;
+pub mod patch;
+pub mod reflog;
+pub mod revision;
+mod scan;
+pub mod stat_cache;
+pub mod status;
+pub mod tag;
+pub mod transaction;
 
 /// 快照
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,6 +43,12 @@ pub struct SnapShot {
     /// 快照的前驱节点, 可能没有, 或者一个, 或者多个
     pub previous: BTreeSet<ObjectID>,
     pub data: SnapShotData,
+    /// 生成目录结构时在工作目录根部生效的忽略规则的 id, 旧版本的快照没有记录
+    ///
+    /// 包括用户的全局规则, `.ys/exclude` 和根目录的 `.ys.ignore`.
+    /// 子目录中的 `.ys.ignore` 作为普通文件保存在目录结构中, 不包含在内.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ignores: Option<ObjectID>,
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
@@ -34,7 +59,16 @@ pub struct SnapShotData {
     pub message: String,
     /// The author ids of the commit.
     pub authors: BTreeSet<AuthorID>,
-    // pub datetime: SystemTime
+    /// 创建快照的时间, 自 UNIX 纪元起的秒数, 旧版本的快照没有记录
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub datetime: Option<u64>,
+}
+
+impl SnapShotData {
+    /// 当前时间, 用于填写 [SnapShotData::datetime]
+    pub fn now() -> Option<u64> {
+        SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|time| time.as_secs())
+    }
 }
 
 #[derive(Copy, Debug, Clone)]
@@ -44,6 +78,14 @@ pub enum SnapShotKind {
     Test,
 }
 
+/// 拼接以 `/` 分隔的相对路径
+pub(crate) fn join_relative(prefix: &str, name: &str) -> String {
+    match prefix {
+        "" => name.to_string(),
+        _ => format!("{}/{}", prefix, name),
+    }
+}
+
 impl Eq for SnapShot {}
 
 impl PartialEq for SnapShot {
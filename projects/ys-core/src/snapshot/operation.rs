@@ -0,0 +1,253 @@
+use super::*;
+use crate::{
+    initialize::{DotYuanShenClient, YuanShenClient},
+    snapshot::{
+        head::Head,
+        transaction::{RefExpectation, RefLock},
+    },
+    YsErrorKind,
+};
+
+/// 仓库在某一时刻的状态, 即当前所在的世界线和所有引用的值
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepositoryState {
+    /// 当前所在的世界线, 或者分离的 `HEAD` 指向的快照
+    pub head: Head,
+    /// 所有世界线指向的快照
+    pub branches: BTreeMap<String, ObjectID>,
+    /// 所有标签指向的对象
+    pub tags: BTreeMap<String, ObjectID>,
+}
+
+impl RepositoryState {
+    /// 所有引用, 以 `branches/main` 或 `tags/v1.0` 这样的完整名称为键
+    pub fn references(&self) -> BTreeMap<String, ObjectID> {
+        let branches = self.branches.iter().map(|(name, id)| (format!("branches/{}", name), *id));
+        let tags = self.tags.iter().map(|(name, id)| (format!("tags/{}", name), *id));
+        branches.chain(tags).collect()
+    }
+    /// 从 `self` 变为 `other` 时值发生变化的引用, 以及变化前后的值
+    pub fn changes(&self, other: &Self) -> Vec<(String, Option<ObjectID>, Option<ObjectID>)> {
+        let (old, new) = (self.references(), other.references());
+        let names: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let (old, new) = (old.get(name).copied(), new.get(name).copied());
+                (old != new).then(|| (name.clone(), old, new))
+            })
+            .collect()
+    }
+}
+
+/// 操作的种类, 决定撤销和重做时如何查找目标
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OperationKind {
+    /// 普通命令, 例如提交和切换世界线
+    Command,
+    /// 撤销给定的操作, 恢复到它之前的状态
+    Undo(usize),
+    /// 重做被给定的撤销操作撤销的操作, 恢复到撤销之前的状态
+    Redo(usize),
+    /// 恢复到给定操作之后的状态
+    Restore(usize),
+}
+
+/// 操作日志中的一条记录, 按顺序保存在 `.ys/operations` 中, 每行一条
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OperationEntry {
+    /// 操作的序号, 从 0 开始
+    pub id: usize,
+    /// 操作的种类
+    pub kind: OperationKind,
+    /// 操作的说明, 例如 `commit: 修复换行`
+    pub description: String,
+    /// 执行操作的作者
+    pub author: AuthorID,
+    /// 操作的时间, 自 UNIX 纪元起的秒数
+    pub datetime: u64,
+    /// 操作之前仓库的状态
+    pub before: RepositoryState,
+    /// 操作之后仓库的状态
+    pub after: RepositoryState,
+}
+
+/// 正在执行的命令, 由 [DotYuanShenClient::begin_operation] 创建
+///
+/// 从创建到 [PendingOperation::record] 完成之间持有仓库锁 `.ys/operations.lock`, 其他命令, 撤销和恢复
+/// 无法同时执行, 记录的前后状态只包含这一个命令的修改. 没有记录就被丢弃时只释放锁.
+#[derive(Debug)]
+pub struct PendingOperation<'a> {
+    client: &'a DotYuanShenClient,
+    before: RepositoryState,
+    _lock: RefLock,
+}
+
+impl PendingOperation<'_> {
+    /// 命令开始之前仓库的状态
+    pub fn before(&self) -> &RepositoryState {
+        &self.before
+    }
+    /// 记录命令并释放仓库锁
+    ///
+    /// 命令没有改变仓库状态时不记录, 返回 `None`.
+    pub fn record(self, description: impl Into<String>) -> Result<Option<OperationEntry>, YsError> {
+        self.client.record(OperationKind::Command, description.into(), self.before)
+    }
+}
+
+impl DotYuanShenClient {
+    /// 获取仓库锁, 同一时刻只有一个进程可以执行会被记录的操作
+    fn lock_repository(&self) -> Result<RefLock, YsError> {
+        RefLock::acquire(&self.dot_root.join("operations"), "operations")
+    }
+    /// 开始一次命令, 获取仓库锁并记录当前状态, 修改仓库之前调用
+    pub fn begin_operation(&self) -> Result<PendingOperation<'_>, YsError> {
+        let lock = self.lock_repository()?;
+        Ok(PendingOperation { client: self, before: self.repository_state()?, _lock: lock })
+    }
+    /// 读取给定序号的操作
+    pub fn operation(&self, id: usize) -> Result<OperationEntry, YsError> {
+        let entry = self.operations()?.into_iter().find(|entry| entry.id == id);
+        Ok(entry.ok_or(YsErrorKind::UnknownOperation { id })?)
+    }
+    /// 仓库当前的状态
+    pub fn repository_state(&self) -> Result<RepositoryState, YsError> {
+        let mut state = RepositoryState { head: self.head()?, branches: BTreeMap::new(), tags: BTreeMap::new() };
+        for name in self.branch_names()? {
+            let id = self.get_branch_id(&name)?;
+            state.branches.insert(name, id);
+        }
+        for name in self.tag_names()? {
+            let id = self.get_tag_id(&name)?;
+            state.tags.insert(name, id);
+        }
+        Ok(state)
+    }
+    /// 调用者需要持有仓库锁
+    fn record(
+        &self,
+        kind: OperationKind,
+        description: String,
+        before: RepositoryState,
+    ) -> Result<Option<OperationEntry>, YsError> {
+        let after = self.repository_state()?;
+        // 撤销和恢复即使没有改变状态也要记录, 否则下一次撤销会再次选中同一个目标
+        if after == before && kind == OperationKind::Command {
+            return Ok(None);
+        }
+        let entry = OperationEntry {
+            // 不使用条数, 以免日志被截断后重复使用序号
+            id: self.operations()?.last().map_or(0, |entry| entry.id + 1),
+            kind,
+            description,
+            author: AuthorID::current_user(),
+            datetime: SnapShotData::now().unwrap_or_default(),
+            before,
+            after,
+        };
+        self.append_operation(&entry)?;
+        Ok(Some(entry))
+    }
+    /// 将所有引用和 `HEAD` 恢复为给定的状态, 不改动工作目录
+    ///
+    /// 所有引用在同一个事务中修改, 期间被其他进程修改过的引用会使恢复失败.
+    fn restore_state(&self, state: &RepositoryState, operation: &str) -> Result<(), YsError> {
+        let current = self.repository_state()?;
+        let changes = current.changes(state);
+        if !changes.is_empty() {
+            let mut transaction = self.transaction();
+            for (name, old, new) in changes {
+                let expected = old.map_or(RefExpectation::Missing, RefExpectation::Value);
+                match new {
+                    Some(new) => transaction.update(name, new, expected),
+                    None => transaction.delete(name, expected),
+                };
+            }
+            transaction.operation(operation);
+            transaction.commit()?;
+        }
+        if current.head != state.head {
+            self.set_head(&state.head)?;
+        }
+        Ok(())
+    }
+    /// 恢复到给定操作之后的状态, 返回被恢复的操作
+    pub fn restore_operation(&self, id: usize) -> Result<OperationEntry, YsError> {
+        let _lock = self.lock_repository()?;
+        let target = self.operation(id)?;
+        let before = self.repository_state()?;
+        let description = format!("restore: {}", target.description);
+        self.restore_state(&target.after, &description)?;
+        self.record(OperationKind::Restore(id), description, before)?;
+        Ok(target)
+    }
+    /// 撤销最近一次尚未撤销的操作, 返回被撤销的操作, 没有可以撤销的操作时返回 `None`
+    ///
+    /// 连续撤销会依次撤销更早的操作, 撤销本身只能通过 [DotYuanShenClient::redo] 重做.
+    pub fn undo(&self) -> Result<Option<OperationEntry>, YsError> {
+        let _lock = self.lock_repository()?;
+        let operations = self.operations()?;
+        let undone: BTreeSet<usize> = operations
+            .iter()
+            .filter_map(|entry| match entry.kind {
+                OperationKind::Undo(id) => Some(id),
+                _ => None,
+            })
+            .collect();
+        let target = operations
+            .into_iter()
+            .rev()
+            .find(|entry| !matches!(entry.kind, OperationKind::Undo(_)) && !undone.contains(&entry.id));
+        let target = match target {
+            Some(target) => target,
+            None => return Ok(None),
+        };
+        let before = self.repository_state()?;
+        let description = format!("undo: {}", target.description);
+        self.restore_state(&target.before, &description)?;
+        self.record(OperationKind::Undo(target.id), description, before)?;
+        Ok(Some(target))
+    }
+    /// 重做最近一次被撤销的操作, 返回被重做的操作, 没有可以重做的操作时返回 `None`
+    ///
+    /// 撤销之后又执行了其他命令时不能再重做.
+    pub fn redo(&self) -> Result<Option<OperationEntry>, YsError> {
+        let _lock = self.lock_repository()?;
+        let operations = self.operations()?;
+        let mut undone = BTreeSet::new();
+        let mut redone = BTreeSet::new();
+        for entry in operations.iter() {
+            match entry.kind {
+                OperationKind::Undo(id) => undone.insert(id),
+                OperationKind::Redo(id) => redone.insert(id),
+                _ => continue,
+            };
+        }
+        let mut target = None;
+        for entry in operations.iter().rev() {
+            match entry.kind {
+                OperationKind::Undo(_) if !undone.contains(&entry.id) && !redone.contains(&entry.id) => {
+                    target = Some(entry);
+                    break;
+                }
+                OperationKind::Command | OperationKind::Restore(_) if !undone.contains(&entry.id) => break,
+                _ => continue,
+            }
+        }
+        let undo = match target {
+            Some(undo) => undo,
+            None => return Ok(None),
+        };
+        let redone = match undo.kind {
+            OperationKind::Undo(id) => self.operation(id)?,
+            _ => unreachable!(),
+        };
+        let before = self.repository_state()?;
+        let description = format!("redo: {}", redone.description);
+        self.restore_state(&undo.before, &description)?;
+        self.record(OperationKind::Redo(undo.id), description, before)?;
+        Ok(Some(redone))
+    }
+}
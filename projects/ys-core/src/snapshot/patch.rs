@@ -0,0 +1,338 @@
+use super::*;
+use crate::{
+    differences::{DifferenceStackType, FileMove, RenameOptions},
+    initialize::DotYuanShenClient,
+    looks_binary, DiffSide,
+};
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// 一行在差异中的状态
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LineChange {
+    /// 两边相同的上下文行
+    Equal,
+    /// 只在旧文件中存在的行
+    Delete,
+    /// 只在新文件中存在的行
+    Insert,
+}
+
+/// 统一格式补丁中的一段连续修改
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hunk {
+    /// 旧文件中的起始行号, 从 1 开始, 没有行时为前一行的行号
+    pub old_start: usize,
+    /// 旧文件中涉及的行数
+    pub old_lines: usize,
+    /// 新文件中的起始行号, 从 1 开始, 没有行时为前一行的行号
+    pub new_start: usize,
+    /// 新文件中涉及的行数
+    pub new_lines: usize,
+    /// 每一行及其状态, 行尾保留原有的换行符
+    pub lines: Vec<(LineChange, String)>,
+}
+
+/// 单个文件的补丁
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FilePatch {
+    /// 相对于工作目录的路径, 以 `/` 分隔
+    pub path: String,
+    /// 旧文件的 id, 新增的文件没有
+    pub old: Option<ObjectID>,
+    /// 新文件的 id, 删除的文件没有
+    pub new: Option<ObjectID>,
+    /// 文本文件的修改, 二进制文件为 `None`
+    pub hunks: Option<Vec<Hunk>>,
+    /// 文件由旧目录树中的另一个文件重命名或复制而来, 旧版本即为来源文件
+    pub source: Option<(DifferenceStackType, FileMove)>,
+}
+
+/// 用 Myers 算法计算两组行之间的最短编辑脚本
+///
+/// 使用线性空间的分治版本, 每次找到编辑路径中间的一段公共行后分别处理两侧, 内存占用与输入长度成正比.
+pub fn diff_lines<T: PartialEq>(old: &[T], new: &[T]) -> Vec<LineChange> {
+    let size = old.len() + new.len() + 1;
+    let mut forward = Frontier::new(size);
+    let mut backward = Frontier::new(size);
+    let mut changes = Vec::with_capacity(size);
+    conquer(old, new, &mut forward, &mut backward, &mut changes);
+    // 连续的修改中先列出删除再列出插入
+    for run in changes.split_mut(|change| *change == LineChange::Equal) {
+        run.sort_by_key(|change| *change == LineChange::Insert);
+    }
+    changes
+}
+
+/// 每条对角线上走得最远的位置, 对角线编号可以为负
+struct Frontier {
+    offset: isize,
+    x: Vec<usize>,
+}
+
+impl Frontier {
+    fn new(size: usize) -> Self {
+        Self { offset: size as isize, x: vec![0; 2 * size + 1] }
+    }
+    fn get(&self, k: isize) -> usize {
+        self.x[(k + self.offset) as usize]
+    }
+    fn set(&mut self, k: isize, x: usize) {
+        self.x[(k + self.offset) as usize] = x
+    }
+}
+
+fn conquer<T: PartialEq>(old: &[T], new: &[T], forward: &mut Frontier, backward: &mut Frontier, changes: &mut Vec<LineChange>) {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let (old, new) = (&old[prefix..], &new[prefix..]);
+    let suffix = old.iter().rev().zip(new.iter().rev()).take_while(|(a, b)| a == b).count();
+    let (old, new) = (&old[..old.len() - suffix], &new[..new.len() - suffix]);
+    changes.extend(std::iter::repeat_n(LineChange::Equal, prefix));
+    if old.is_empty() {
+        changes.extend(std::iter::repeat_n(LineChange::Insert, new.len()));
+    }
+    else if new.is_empty() {
+        changes.extend(std::iter::repeat_n(LineChange::Delete, old.len()));
+    }
+    else {
+        let (x, y) = middle_snake(old, new, forward, backward);
+        conquer(&old[..x], &new[..y], forward, backward, changes);
+        conquer(&old[x..], &new[y..], forward, backward, changes);
+    }
+    changes.extend(std::iter::repeat_n(LineChange::Equal, suffix));
+}
+
+/// 同时从两端搜索, 返回两条路径相遇处的分割点, 两侧都严格小于原问题
+fn middle_snake<T: PartialEq>(old: &[T], new: &[T], forward: &mut Frontier, backward: &mut Frontier) -> (usize, usize) {
+    let (n, m) = (old.len(), new.len());
+    let delta = n as isize - m as isize;
+    let odd = delta & 1 == 1;
+    forward.set(1, 0);
+    backward.set(1, 0);
+    for d in 0..=(n + m).div_ceil(2) as isize {
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && forward.get(k - 1) < forward.get(k + 1)) {
+                forward.get(k + 1)
+            }
+            else {
+                forward.get(k - 1) + 1
+            };
+            let y = (x as isize - k) as usize;
+            let (start_x, start_y) = (x, y);
+            if x < n && y < m {
+                x += old[x..].iter().zip(&new[y..]).take_while(|(a, b)| a == b).count();
+            }
+            forward.set(k, x);
+            if odd && (k - delta).abs() < d && forward.get(k) + backward.get(delta - k) >= n {
+                return (start_x, start_y);
+            }
+        }
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && backward.get(k - 1) < backward.get(k + 1)) {
+                backward.get(k + 1)
+            }
+            else {
+                backward.get(k - 1) + 1
+            };
+            let mut y = (x as isize - k) as usize;
+            if x < n && y < m {
+                let same = old[..n - x].iter().rev().zip(new[..m - y].iter().rev()).take_while(|(a, b)| a == b).count();
+                x += same;
+                y += same;
+            }
+            backward.set(k, x);
+            if !odd && (k - delta).abs() <= d && backward.get(k) + forward.get(delta - k) >= n {
+                return (n - x, m - y);
+            }
+        }
+    }
+    unreachable!("the forward and backward searches always meet")
+}
+
+/// 计算两段文本之间的统一格式补丁, 每段修改前后保留 `context` 行上下文
+pub fn unified_hunks(old: &str, new: &str, context: usize) -> Vec<Hunk> {
+    let old: Vec<&str> = old.split_inclusive('\n').collect();
+    let new: Vec<&str> = new.split_inclusive('\n').collect();
+    let changes = diff_lines(&old, &new);
+    // 每一步对应的旧行号和新行号
+    let mut positions = Vec::with_capacity(changes.len());
+    let (mut i, mut j) = (0, 0);
+    for change in changes.iter() {
+        positions.push((i, j));
+        match change {
+            LineChange::Equal => (i, j) = (i + 1, j + 1),
+            LineChange::Delete => i += 1,
+            LineChange::Insert => j += 1,
+        }
+    }
+    let mut hunks = vec![];
+    let mut step = 0;
+    while let Some(first) = (step..changes.len()).find(|index| changes[*index] != LineChange::Equal) {
+        let start = first.saturating_sub(context);
+        let mut end = first;
+        // 相邻两段修改之间的上下文不超过 2 * context 行时合并为一段
+        loop {
+            let next = (end + 1..changes.len()).find(|index| changes[*index] != LineChange::Equal);
+            match next {
+                Some(next) if next - end <= 2 * context + 1 => end = next,
+                _ => break,
+            }
+        }
+        let end = (end + context + 1).min(changes.len());
+        let mut hunk = Hunk { old_start: 0, old_lines: 0, new_start: 0, new_lines: 0, lines: vec![] };
+        for index in start..end {
+            let (i, j) = positions[index];
+            let line = match changes[index] {
+                LineChange::Equal => {
+                    hunk.old_lines += 1;
+                    hunk.new_lines += 1;
+                    old[i]
+                }
+                LineChange::Delete => {
+                    hunk.old_lines += 1;
+                    old[i]
+                }
+                LineChange::Insert => {
+                    hunk.new_lines += 1;
+                    new[j]
+                }
+            };
+            hunk.lines.push((changes[index], line.to_string()));
+        }
+        let (i, j) = positions[start];
+        hunk.old_start = if hunk.old_lines == 0 { i } else { i + 1 };
+        hunk.new_start = if hunk.new_lines == 0 { j } else { j + 1 };
+        hunks.push(hunk);
+        step = end;
+    }
+    hunks
+}
+
+impl FilePatch {
+    /// 比较文件的两个版本, 任意一边的内容看起来是二进制或者不是 UTF-8 时不生成文本补丁
+    pub fn new(
+        path: &str,
+        old: Option<(ObjectID, &[u8])>,
+        new: Option<(ObjectID, &[u8])>,
+        binary: bool,
+        context: usize,
+    ) -> Self {
+        let hunks = match (binary, text(old), text(new)) {
+            (false, Some(old), Some(new)) => Some(unified_hunks(old, new, context)),
+            _ => None,
+        };
+        Self { path: path.to_string(), old: old.map(|(id, _)| id), new: new.map(|(id, _)| id), hunks, source: None }
+    }
+    /// 以统一格式写出补丁, `color` 为真时使用 ANSI 颜色
+    pub fn write_unified<W: std::io::Write>(&self, w: &mut W, color: bool) -> std::io::Result<()> {
+        let paint = |style: &'static str| if color { style } else { "" };
+        let reset = paint(RESET);
+        let from = self.source.as_ref().map(|(_, entry)| entry.from.as_str()).unwrap_or(&self.path);
+        let old = self.old.map(|_| format!("a/{}", from)).unwrap_or_else(|| String::from("/dev/null"));
+        let new = self.new.map(|_| format!("b/{}", self.path)).unwrap_or_else(|| String::from("/dev/null"));
+        writeln!(w, "{}diff --ys a/{} b/{}{}", paint(BOLD), from, self.path, reset)?;
+        if let Some((kind, entry)) = &self.source {
+            let verb = match kind {
+                DifferenceStackType::Copied => "copy",
+                _ => "rename",
+            };
+            writeln!(w, "similarity index {}%", entry.similarity)?;
+            writeln!(w, "{} from {}", verb, entry.from)?;
+            writeln!(w, "{} to {}", verb, self.path)?;
+        }
+        let Some(hunks) = &self.hunks
+        else {
+            return writeln!(w, "Binary files {} and {} differ", old, new);
+        };
+        writeln!(w, "{}--- {}{}", paint(BOLD), old, reset)?;
+        writeln!(w, "{}+++ {}{}", paint(BOLD), new, reset)?;
+        for hunk in hunks {
+            writeln!(
+                w,
+                "{}@@ -{},{} +{},{} @@{}",
+                paint(CYAN),
+                hunk.old_start,
+                hunk.old_lines,
+                hunk.new_start,
+                hunk.new_lines,
+                reset
+            )?;
+            for (change, line) in hunk.lines.iter() {
+                let (sign, style) = match change {
+                    LineChange::Equal => (' ', ""),
+                    LineChange::Delete => ('-', paint(RED)),
+                    LineChange::Insert => ('+', paint(GREEN)),
+                };
+                let reset = if style.is_empty() { "" } else { reset };
+                match line.strip_suffix('\n') {
+                    Some(line) => writeln!(w, "{}{}{}{}", style, sign, line, reset)?,
+                    None => {
+                        writeln!(w, "{}{}{}{}", style, sign, line, reset)?;
+                        writeln!(w, "\\ No newline at end of file")?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 文件内容对应的文本, 不存在的文件视为空文本
+fn text(content: Option<(ObjectID, &[u8])>) -> Option<&str> {
+    match content {
+        Some((_, bytes)) if looks_binary(bytes) => None,
+        Some((_, bytes)) => std::str::from_utf8(bytes).ok(),
+        None => Some(""),
+    }
+}
+
+impl Display for FilePatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let mut buffer = vec![];
+        self.write_unified(&mut buffer, false).map_err(|_| core::fmt::Error)?;
+        f.write_str(&String::from_utf8_lossy(&buffer))
+    }
+}
+
+impl DotYuanShenClient {
+    /// 生成比较双方之间每个被修改文件的补丁, 按路径排序
+    ///
+    /// 给出 `renames` 时检测重命名和复制, 重命名的文件与其来源比较, 来源本身不再单独列出.
+    /// `paths` 非空时只比较位于其中的文件. 工作目录中的 `.ys.attributes` 将文件标记为二进制时不生成文本补丁.
+    pub async fn patches(
+        &self,
+        old: &DiffSide,
+        new: &DiffSide,
+        context: usize,
+        renames: Option<&RenameOptions>,
+        paths: &[String],
+    ) -> Result<Vec<FilePatch>, YsError> {
+        let (records, store) = self.compare(old, new, renames, paths).await?;
+        let mut patches = vec![];
+        for record in records {
+            let old_bytes = match record.old {
+                Some(id) => Some((id, store.get(id).await?)),
+                None => None,
+            };
+            let new_bytes = match record.new {
+                Some(id) => Some((id, store.get(id).await?)),
+                None => None,
+            };
+            let binary = self.attributes(Path::new(&record.path))?.is_binary();
+            let mut patch = FilePatch::new(
+                &record.path,
+                old_bytes.as_ref().map(|(id, bytes)| (*id, bytes.as_slice())),
+                new_bytes.as_ref().map(|(id, bytes)| (*id, bytes.as_slice())),
+                binary,
+                context,
+            );
+            patch.source = record.from.map(|entry| (record.kind, entry));
+            patches.push(patch);
+        }
+        Ok(patches)
+    }
+}
@@ -0,0 +1,76 @@
+use super::*;
+use crate::{
+    initialize::{DotYuanShenClient, YuanShenClient},
+    snapshot::transaction::RefLock,
+    utils::{append_json_line, json_lines},
+    YsErrorKind,
+};
+
+/// 引用历史中的记录在这段时间内作为垃圾回收的根, 单位为秒
+pub const REFLOG_EXPIRY: u64 = 90 * 24 * 60 * 60;
+
+/// 引用的一次移动, 每个引用的历史按时间顺序保存在 `.ys/logs/<引用名称>` 中, 每行一条
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReflogEntry {
+    /// 移动前指向的对象, `None` 表示引用由这次操作创建
+    pub old: Option<ObjectID>,
+    /// 移动后指向的对象
+    pub new: ObjectID,
+    /// 造成移动的操作, 例如 `commit: 修复换行`
+    pub operation: String,
+    /// 执行操作的作者
+    pub author: AuthorID,
+    /// 操作的时间, 自 UNIX 纪元起的秒数
+    pub datetime: u64,
+}
+
+/// 引用历史文件的位置
+pub(crate) fn reflog_path(dot_root: &Path, name: &str) -> PathBuf {
+    dot_root.join("logs").join(name)
+}
+
+/// 在引用历史的末尾追加一条记录
+///
+/// 追加和改写历史都持有同一个 `logs/<引用名称>.lock`, 过期清理不会丢掉同时追加的记录.
+pub(crate) fn append_reflog(dot_root: &Path, name: &str, entry: &ReflogEntry) -> Result<(), YsError> {
+    let path = reflog_path(dot_root, name);
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).map_err(|e| YsError::path_error(e, parent))?;
+    }
+    let _lock = RefLock::acquire(&path, name)?;
+    append_json_line(&path, entry)
+}
+
+impl DotYuanShenClient {
+    /// 引用在 `n` 次移动之前指向的对象, 即修订号中的 `name@{n}`
+    ///
+    /// `n` 为 0 时是引用当前的值. 历史记录不够长, 或者那时引用还不存在时返回 `None`.
+    pub fn reflog_value(&self, name: &str, n: usize) -> Result<Option<ObjectID>, YsError> {
+        if n == 0 {
+            return match name.split_once('/') {
+                Some(("branches", branch)) => Ok(Some(self.get_branch_id(branch)?)),
+                Some(("tags", tag)) => Ok(Some(self.get_tag_id(tag)?)),
+                _ => Err(YsErrorKind::MissingReference { name: name.to_string() })?,
+            };
+        }
+        let entries = self.reflog(name)?;
+        Ok(entries.len().checked_sub(n).and_then(|index| entries[index].old))
+    }
+    /// 删除早于 `before` 的历史记录, 返回删除的条数
+    pub fn expire_reflogs(&self, before: u64) -> Result<usize, YsError> {
+        let mut removed = 0;
+        for name in self.reflog_names()? {
+            // 读取和改写之间持有锁, 见 [append_reflog]
+            let mut lock = RefLock::acquire(&reflog_path(&self.dot_root, &name), &name)?;
+            let entries = self.reflog(&name)?;
+            let kept: Vec<_> = entries.iter().filter(|entry| entry.datetime >= before).cloned().collect();
+            if kept.len() == entries.len() {
+                continue;
+            }
+            removed += entries.len() - kept.len();
+            lock.write(&json_lines(&kept)?)?;
+            lock.commit()?;
+        }
+        Ok(removed)
+    }
+}
@@ -0,0 +1,296 @@
+use super::*;
+use crate::{
+    differences::{DiffStat, DifferenceRecord, RenameOptions},
+    initialize::{DotYuanShenClient, YuanShenClient},
+    snapshot::{
+        index::{is_within, StagingIndex},
+        scan::read_stored,
+        tag::peel_tag,
+    },
+    PathAttributes, YsErrorKind,
+};
+use std::{
+    collections::{btree_map::Entry, VecDeque},
+    io::ErrorKind,
+};
+
+mod parser;
+
+pub use self::parser::{Ancestry, Revision, RevisionSpec};
+
+/// 缩写的快照 id 至少需要的十六进制字符数
+const MINIMUM_PREFIX_LENGTH: usize = 4;
+
+/// 差异中被比较的一方
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DiffSide {
+    /// 某个快照的目录树
+    Snapshot(ObjectID),
+    /// 暂存区
+    Index,
+    /// 工作目录中已被追踪的文件, 未被追踪的文件不参与比较
+    WorkingTree,
+}
+
+impl DotYuanShenClient {
+    /// 将修订号解析为快照的 id, 语法见 [RevisionSpec], 不接受范围
+    pub async fn resolve_revision(&self, revision: &str) -> Result<ObjectID, YsError> {
+        self.resolve(&revision.parse()?).await
+    }
+    /// 将修订解析为快照的 id
+    ///
+    /// 名称依次作为世界线和标签解析, 都不存在时作为完整或至少 4 位的缩写快照 id 解析.
+    /// `@{n}` 从世界线或标签的引用历史中查找, 快照 id 没有引用历史.
+    /// 快照的前驱是按 id 排序的集合, 不记录合并时所在的世界线, 因此 `~n` 和 `^1` 沿 id 最小的前驱回退,
+    /// 对合并快照而言不一定是合并前所在的世界线.
+    pub async fn resolve(&self, revision: &Revision) -> Result<ObjectID, YsError> {
+        let unknown = || YsErrorKind::UnknownRevision { revision: revision.to_string() };
+        let store = self.store()?;
+        let mut id = match revision.name.as_deref() {
+            None => self.calculate_branch_id()?,
+            Some(name) if self.branch_exists(name)? => self.get_branch_id(name)?,
+            Some(name) if self.tag_exists(name)? => self.resolve_tag(name).await?,
+            Some(name) => self.find_snapshot(&store, name).await?.ok_or_else(unknown)?,
+        };
+        if let Some(n) = revision.entry {
+            let reference = match revision.name.as_deref() {
+                None => format!("branches/{}", self.get_branch_name()?),
+                Some(name) if self.branch_exists(name)? => format!("branches/{}", name),
+                Some(name) if self.tag_exists(name)? => format!("tags/{}", name),
+                Some(_) => Err(YsErrorKind::InvalidRevision {
+                    revision: revision.to_string(),
+                    reason: "快照 id 没有引用历史",
+                })?,
+            };
+            id = peel_tag(&store, self.reflog_value(&reference, n)?.ok_or_else(unknown)?).await?;
+        }
+        if let Some(date) = revision.date {
+            loop {
+                let snapshot: SnapShot = store.get_typed(id).await?;
+                // 没有记录时间的旧快照无法比较, 继续向前查找
+                if snapshot.data.datetime.is_some_and(|time| time <= date) {
+                    break;
+                }
+                id = *snapshot.previous.iter().next().ok_or_else(unknown)?;
+            }
+        }
+        for step in revision.steps.iter() {
+            let (first, count) = match *step {
+                Ancestry::Ancestor(count) => (0, count),
+                Ancestry::Parent(0) => continue,
+                Ancestry::Parent(n) => (n - 1, 1),
+            };
+            for _ in 0..count {
+                let snapshot: SnapShot = store.get_typed(id).await?;
+                id = *snapshot.previous.iter().nth(first).ok_or_else(unknown)?;
+            }
+        }
+        Ok(id)
+    }
+    /// 解析范围的两端, `symmetric` 时起点替换为两者最近的共同祖先
+    pub async fn resolve_range(
+        &self,
+        from: &Revision,
+        to: &Revision,
+        symmetric: bool,
+    ) -> Result<(ObjectID, ObjectID), YsError> {
+        let (old, new) = (self.resolve(from).await?, self.resolve(to).await?);
+        if !symmetric {
+            return Ok((old, new));
+        }
+        match self.merge_base(old, new).await? {
+            Some(base) => Ok((base, new)),
+            None => {
+                let range = RevisionSpec::Range { from: from.clone(), to: to.clone(), symmetric };
+                Err(YsErrorKind::UnknownRevision { revision: range.to_string() })?
+            }
+        }
+    }
+    /// 两个快照最近的共同祖先, 没有共同祖先时返回 `None`
+    ///
+    /// 先收集 `a` 的所有祖先, 再从 `b` 出发广度优先搜索, 返回遇到的第一个.
+    pub async fn merge_base(&self, a: ObjectID, b: ObjectID) -> Result<Option<ObjectID>, YsError> {
+        let store = self.store()?;
+        let mut ancestors = BTreeSet::new();
+        let mut pending = vec![a];
+        while let Some(id) = pending.pop() {
+            if ancestors.insert(id) {
+                let snapshot: SnapShot = store.get_typed(id).await?;
+                pending.extend(snapshot.previous);
+            }
+        }
+        let mut visited = BTreeSet::new();
+        let mut queue = VecDeque::from([b]);
+        while let Some(id) = queue.pop_front() {
+            if ancestors.contains(&id) {
+                return Ok(Some(id));
+            }
+            if visited.insert(id) {
+                let snapshot: SnapShot = store.get_typed(id).await?;
+                queue.extend(snapshot.previous);
+            }
+        }
+        Ok(None)
+    }
+    /// 检查 `ancestor` 是否出现在 `descendant` 的历史中, 快照本身也算作自己的祖先
+    pub async fn is_ancestor(&self, ancestor: ObjectID, descendant: ObjectID) -> Result<bool, YsError> {
+        let store = self.store()?;
+        let mut visited = BTreeSet::new();
+        let mut pending = vec![descendant];
+        while let Some(id) = pending.pop() {
+            if id == ancestor {
+                return Ok(true);
+            }
+            if visited.insert(id) {
+                let snapshot: SnapShot = store.get_typed(id).await?;
+                pending.extend(snapshot.previous);
+            }
+        }
+        Ok(false)
+    }
+    /// 按完整或缩写的 id 查找储存库中的快照, 找不到时返回 `None`, 对应多个快照时返回错误
+    async fn find_snapshot(&self, store: &LocalObjectStore, hex: &str) -> Result<Option<ObjectID>, YsError> {
+        if hex.len() < MINIMUM_PREFIX_LENGTH || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Ok(None);
+        }
+        let mut candidates = vec![];
+        for id in store.find_prefix(&hex.to_ascii_lowercase()).await? {
+            // 缩写也可能对应目录结构或文件, 无法解析为快照的对象被跳过, 读取失败时报错
+            match store.get_typed::<SnapShot>(id).await {
+                Ok(_) => candidates.push(id),
+                Err(e) if matches!(e.kind(), YsErrorKind::Serde { .. }) => {}
+                Err(e) => Err(e)?,
+            }
+        }
+        match candidates.len() {
+            0 | 1 => Ok(candidates.pop()),
+            _ => Err(YsErrorKind::AmbiguousRevision { revision: hex.to_string(), candidates })?,
+        }
+    }
+    /// 比较的一方对应的文件列表
+    ///
+    /// 工作目录一方只包含暂存区中仍然存在的文件, 元数据没有变化的文件直接使用缓存中的 id.
+    pub async fn side_entries(&self, side: &DiffSide) -> Result<StagingIndex, YsError> {
+        match side {
+            DiffSide::Snapshot(id) => {
+                let store = self.store()?;
+                let snapshot: SnapShot = store.get_typed(*id).await?;
+                Ok(StagingIndex::from_directory(&store.get_typed(snapshot.directory).await?))
+            }
+            DiffSide::Index => self.index().await,
+            DiffSide::WorkingTree => {
+                let mut cache = self.stat_cache();
+                let mut attributes: BTreeMap<&str, PathAttributes> = BTreeMap::new();
+                let mut working = StagingIndex::default();
+                let index = self.index().await?;
+                for path in index.entries.keys() {
+                    let full = self.working_directory().join(path);
+                    match full.metadata() {
+                        Ok(metadata) if metadata.is_file() => {}
+                        Ok(_) => continue,
+                        Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                        Err(e) => Err(YsError::path_error(e, &full))?,
+                    }
+                    let parent = path.rsplit_once('/').map(|(parent, _)| parent).unwrap_or("");
+                    let rules = match attributes.entry(parent) {
+                        Entry::Occupied(o) => o.into_mut(),
+                        Entry::Vacant(v) => v.insert(self.attributes_within(parent)?),
+                    };
+                    working.entries.insert(path.clone(), cache.hash_stored(path, &full, &rules.resolve(path))?);
+                }
+                self.save_stat_cache(&mut cache)?;
+                Ok(working)
+            }
+        }
+    }
+    /// 比较两方, 返回按路径排序的文件变化
+    ///
+    /// 给出 `renames` 时检测重命名和复制. `paths` 是相对于工作目录的路径, 非空时只保留位于其中的文件,
+    /// 重命名的来源位于其中的文件也会被保留.
+    pub async fn diff_records(
+        &self,
+        old: &DiffSide,
+        new: &DiffSide,
+        renames: Option<&RenameOptions>,
+        paths: &[String],
+    ) -> Result<Vec<DifferenceRecord>, YsError> {
+        Ok(self.compare(old, new, renames, paths).await?.0)
+    }
+    /// 比较两方, 返回每个文件的变化及其统计数据
+    pub async fn diff_stats(
+        &self,
+        old: &DiffSide,
+        new: &DiffSide,
+        renames: Option<&RenameOptions>,
+        paths: &[String],
+    ) -> Result<Vec<(DifferenceRecord, DiffStat)>, YsError> {
+        let (records, store) = self.compare(old, new, renames, paths).await?;
+        let mut stats = vec![];
+        for record in records {
+            let stat = DiffStat::collect([&record], &store).await?;
+            stats.push((record, stat));
+        }
+        Ok(stats)
+    }
+    /// 比较两方, 同时返回可以读取双方文件内容的储存库
+    pub(crate) async fn compare(
+        &self,
+        old: &DiffSide,
+        new: &DiffSide,
+        renames: Option<&RenameOptions>,
+        paths: &[String],
+    ) -> Result<(Vec<DifferenceRecord>, SideStore<'_>), YsError> {
+        let mut store = SideStore { client: self, store: self.store()?, working: BTreeMap::new() };
+        let mut directories = vec![];
+        for side in [old, new] {
+            let entries = self.side_entries(side).await?;
+            if let DiffSide::WorkingTree = side {
+                store.working.extend(entries.entries.iter().map(|(path, id)| (*id, path.clone())));
+            }
+            directories.push(entries.to_directory()?);
+        }
+        let (old, new) = (&directories[0], &directories[1]);
+        let records = match renames {
+            Some(options) => old.difference_with_renames(new, &store, options).await?.records(old, new),
+            None => old.changes(new),
+        };
+        let records = records
+            .filter(|record| {
+                paths.is_empty()
+                    || paths.iter().any(|path| {
+                        is_within(&record.path, path) || record.from.as_ref().is_some_and(|entry| is_within(&entry.from, path))
+                    })
+            })
+            .collect();
+        Ok((records, store))
+    }
+}
+
+/// 读取比较双方文件内容的储存库, 工作目录中的文件直接从磁盘读取, 不会写入储存库
+pub(crate) struct SideStore<'a> {
+    client: &'a DotYuanShenClient,
+    store: LocalObjectStore,
+    /// 工作目录中文件的 id 及其路径
+    working: BTreeMap<ObjectID, String>,
+}
+
+impl ObjectStore for SideStore<'_> {
+    async fn has(&self, id: ObjectID) -> Result<bool, YsError> {
+        Ok(self.working.contains_key(&id) || self.store.has(id).await?)
+    }
+    async fn get(&self, id: ObjectID) -> Result<Vec<u8>, YsError> {
+        match self.working.get(&id) {
+            Some(path) => {
+                let full = self.client.working_directory().join(path);
+                read_stored(&full, &self.client.attributes(&full)?)
+            }
+            None => self.store.get(id).await,
+        }
+    }
+    async fn find_prefix(&self, prefix: &str) -> Result<Vec<ObjectID>, YsError> {
+        self.store.find_prefix(prefix).await
+    }
+    async fn put(&mut self, object: &[u8]) -> Result<ObjectID, YsError> {
+        self.store.put(object).await
+    }
+}
@@ -0,0 +1,243 @@
+use crate::{SnapShotData, YsError, YsErrorKind};
+use std::{
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+const MINUTE: u64 = 60;
+const HOUR: u64 = 60 * MINUTE;
+const DAY: u64 = 24 * HOUR;
+
+/// 修订号表达式, 单个修订或者两个修订之间的范围
+///
+/// ```text
+/// spec     := revision | revision? ".." revision? | revision? "..." revision?
+/// revision := base ("@{" (date | n) "}")? ("~" n? | "^" n?)*
+/// base     := "HEAD" | "@" | 世界线名称 | 标签名称 | 完整或缩写的快照 id
+/// ```
+///
+/// 范围中省略的一端为 `HEAD`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RevisionSpec {
+    /// 单个修订
+    Single(Revision),
+    /// `from..to`, 或者 `from...to` 表示从两者的共同祖先到 `to`
+    Range {
+        /// 范围的起点
+        from: Revision,
+        /// 范围的终点
+        to: Revision,
+        /// 是否使用 `...`, 此时起点替换为两者的共同祖先
+        symmetric: bool,
+    },
+}
+
+/// 单个修订, 从某个起点出发, 依次沿前驱回退
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Revision {
+    /// 世界线名称, 标签名称或者十六进制的快照 id, `None` 表示 `HEAD`
+    pub name: Option<String>,
+    /// `@{date}` 给出的时间, 自 UNIX 纪元起的秒数, 取起点的第一前驱链上不晚于该时间的最新快照
+    pub date: Option<u64>,
+    /// `@{n}` 给出的序号, 取起点的引用在 `n` 次移动之前的值, 不会与 `date` 同时出现
+    pub entry: Option<usize>,
+    /// 依次应用的回溯步骤
+    pub steps: Vec<Ancestry>,
+}
+
+/// 修订号中的一个回溯步骤
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Ancestry {
+    /// `~n`, 沿第一个前驱回退 n 个快照, 前驱按 id 顺序排列, 不一定是合并前所在的世界线
+    Ancestor(usize),
+    /// `^n`, 第 n 个前驱, 按 id 顺序从 1 开始计数, `^0` 表示快照本身
+    Parent(usize),
+}
+
+impl Revision {
+    /// 当前世界线的最新快照
+    pub fn head() -> Self {
+        Self { name: None, date: None, entry: None, steps: vec![] }
+    }
+}
+
+fn invalid(revision: &str, reason: &'static str) -> YsError {
+    YsErrorKind::InvalidRevision { revision: revision.to_string(), reason }.into()
+}
+
+impl FromStr for RevisionSpec {
+    type Err = YsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let range = match s.find("...") {
+            Some(index) => Some((index, 3, true)),
+            None => s.find("..").map(|index| (index, 2, false)),
+        };
+        let (index, length, symmetric) = match range {
+            Some(range) => range,
+            None => return Ok(Self::Single(s.parse()?)),
+        };
+        let side = |text: &str| match text {
+            "" => Ok(Revision::head()),
+            _ => text.parse(),
+        };
+        let (from, to) = (&s[..index], &s[index + length..]);
+        if from.is_empty() && to.is_empty() {
+            Err(invalid(s, "范围的两端不能都省略"))?
+        }
+        Ok(Self::Range { from: side(from)?, to: side(to)?, symmetric })
+    }
+}
+
+impl FromStr for Revision {
+    type Err = YsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let end = s.find(['~', '^']).unwrap_or(s.len());
+        let (base, mut suffix) = s.split_at(end);
+        if base.is_empty() {
+            Err(invalid(s, "缺少世界线名称或快照 id"))?
+        }
+        let (name, date, entry) = match base.find("@{") {
+            Some(index) => {
+                let moment = base[index + 2..].strip_suffix('}').ok_or_else(|| invalid(s, "`@{` 缺少对应的 `}`"))?;
+                if !moment.is_empty() && moment.chars().all(|c| c.is_ascii_digit()) {
+                    (&base[..index], None, Some(moment.parse().map_err(|_| invalid(s, "引用历史的序号过大"))?))
+                }
+                else {
+                    let now = SnapShotData::now().unwrap_or_default();
+                    (&base[..index], Some(parse_date(moment, now).ok_or_else(|| invalid(s, "无法识别的时间"))?), None)
+                }
+            }
+            None => (base, None, None),
+        };
+        let name = match name {
+            "" | "@" | "HEAD" => None,
+            _ if name.contains(['{', '}']) => Err(invalid(s, "世界线名称中不能包含花括号"))?,
+            _ => Some(name.to_string()),
+        };
+        let mut steps = vec![];
+        while let Some(c) = suffix.chars().next() {
+            suffix = &suffix[c.len_utf8()..];
+            let digits = suffix.find(|c: char| !c.is_ascii_digit()).unwrap_or(suffix.len());
+            let count = match &suffix[..digits] {
+                "" => 1,
+                n => n.parse().map_err(|_| invalid(s, "回溯的次数过大"))?,
+            };
+            suffix = &suffix[digits..];
+            steps.push(match c {
+                '~' => Ancestry::Ancestor(count),
+                '^' => Ancestry::Parent(count),
+                _ => Err(invalid(s, "只能使用 `~n` 或 `^n` 后缀"))?,
+            });
+        }
+        Ok(Self { name, date, entry, steps })
+    }
+}
+
+impl Display for RevisionSpec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Single(revision) => Display::fmt(revision, f),
+            Self::Range { from, to, symmetric } => {
+                write!(f, "{}{}{}", from, if *symmetric { "..." } else { ".." }, to)
+            }
+        }
+    }
+}
+
+impl Display for Revision {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name.as_deref().unwrap_or("HEAD"))?;
+        if let Some(date) = self.date {
+            write!(f, "@{{{}}}", format_date(date))?;
+        }
+        if let Some(entry) = self.entry {
+            write!(f, "@{{{}}}", entry)?;
+        }
+        for step in &self.steps {
+            match step {
+                Ancestry::Ancestor(n) => write!(f, "~{}", n)?,
+                Ancestry::Parent(n) => write!(f, "^{}", n)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 解析 `@{...}` 中的时间, 返回自 UNIX 纪元起的秒数
+///
+/// 支持 `now`, `yesterday`, `3 days ago` 或 `3.days.ago` 这样的相对时间, 以及 UTC 的
+/// `2024-01-31`, `2024-01-31 12:00` 和 `2024-01-31T12:00:00`.
+fn parse_date(text: &str, now: u64) -> Option<u64> {
+    match text.trim() {
+        "now" => return Some(now),
+        "yesterday" => return now.checked_sub(DAY),
+        _ => {}
+    }
+    let words: Vec<&str> = text.split(['.', ' ']).filter(|word| !word.is_empty()).collect();
+    if let [count, unit, "ago"] = words.as_slice() {
+        let unit = match unit.strip_suffix('s').unwrap_or(unit) {
+            "second" => 1,
+            "minute" => MINUTE,
+            "hour" => HOUR,
+            "day" => DAY,
+            "week" => 7 * DAY,
+            "month" => 30 * DAY,
+            "year" => 365 * DAY,
+            _ => return None,
+        };
+        return now.checked_sub(count.parse::<u64>().ok()?.checked_mul(unit)?);
+    }
+    let (date, time) = text.trim().split_once([' ', 'T']).unwrap_or((text.trim(), "00:00"));
+    let date = date.split('-').map(|part| part.parse::<i64>().ok()).collect::<Option<Vec<_>>>()?;
+    let time = time.split(':').map(|part| part.parse::<u64>().ok()).collect::<Option<Vec<_>>>()?;
+    let (year, month, day) = match date.as_slice() {
+        [year, month, day] => (*year, *month, *day),
+        _ => return None,
+    };
+    let (hour, minute, second) = match time.as_slice() {
+        [hour, minute] => (*hour, *minute, 0),
+        [hour, minute, second] => (*hour, *minute, *second),
+        _ => return None,
+    };
+    if !(1..=12).contains(&month) || day < 1 || hour >= 24 || minute >= 60 || second >= 60 {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    // 拒绝 2 月 30 日这样不存在的日期
+    if civil_from_days(days) != (year, month, day) {
+        return None;
+    }
+    Some(u64::try_from(days).ok()? * DAY + hour * HOUR + minute * MINUTE + second)
+}
+
+/// 将秒数格式化为 UTC 的 `YYYY-MM-DD HH:MM:SS`
+fn format_date(seconds: u64) -> String {
+    let (year, month, day) = civil_from_days((seconds / DAY) as i64);
+    let rest = seconds % DAY;
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", year, month, day, rest / HOUR, rest % HOUR / MINUTE, rest % MINUTE)
+}
+
+/// 公历日期距离 1970-01-01 的天数
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// [days_from_civil] 的逆运算
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted + 2) / 5 + 1;
+    let month = if shifted < 10 { shifted + 3 } else { shifted - 9 };
+    (if month <= 2 { year_of_era + era * 400 + 1 } else { year_of_era + era * 400 }, month, day)
+}
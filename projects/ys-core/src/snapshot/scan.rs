@@ -0,0 +1,173 @@
+use super::*;
+use crate::{PathAttributes, ResolvedAttributes};
+use rayon::prelude::*;
+use std::{fs::Metadata, io::Read, num::NonZeroUsize, thread::available_parallelism};
+use tokio::sync::mpsc::channel;
+
+/// 超过此大小的文件会使用多线程计算哈希
+const PARALLEL_HASH_THRESHOLD: usize = 128 * 1024;
+
+/// 扫描得到的目录结构, 叶子上只有文件的位置和元数据
+enum ScanNode {
+    File(Box<ScanFile>),
+    Directory(BTreeMap<String, ScanNode>),
+}
+
+struct ScanFile {
+    relative: String,
+    path: PathBuf,
+    metadata: Metadata,
+    attributes: ResolvedAttributes,
+}
+
+impl SnapShotDirectory {
+    /// 扫描给定目录, 将其中的文件写入储存库, 并返回对应的目录树
+    ///
+    /// 目录遍历和哈希计算分布在线程池上并行执行, 大文件会使用多线程哈希.
+    /// 写入储存库的对象数量有上限, 读取完成但尚未写入的文件不会无限堆积在内存中.
+    /// 元数据没有变化且已经存在于储存库中的文件不会被重新读取.
+    /// 各个目录中的 `.ys.attributes` 会被逐层加入, 标记为文本的文件在写入前会统一换行符.
+    pub async fn new<Store: ObjectStore>(
+        dir: &Path,
+        ignores: &IgnoreRules,
+        store: &mut Store,
+        cache: &mut StatCache,
+    ) -> Result<Box<Self>, YsError> {
+        Self::scan(dir, "", ignores, &PathAttributes::default(), store, cache).await
+    }
+    /// 扫描工作目录中的一个子目录, `prefix` 是该目录相对于工作目录的路径, 用于查询元数据缓存
+    pub(crate) async fn scan<Store: ObjectStore>(
+        dir: &Path,
+        prefix: &str,
+        ignores: &IgnoreRules,
+        attributes: &PathAttributes,
+        store: &mut Store,
+        cache: &mut StatCache,
+    ) -> Result<Box<Self>, YsError> {
+        let tree = walk(dir, prefix, ignores, attributes)?;
+        let mut files = vec![];
+        flatten(&tree, &mut files);
+        let mut ids: Vec<Option<ObjectID>> = vec![None; files.len()];
+        let mut pending = vec![];
+        for (index, file) in files.iter().enumerate() {
+            match cache.lookup(&file.relative, &file.metadata) {
+                Some(id) if store.has(id).await? => ids[index] = Some(id),
+                _ => pending.push((index, file.path.clone(), file.attributes.clone())),
+            }
+        }
+        let bound = available_parallelism().map(NonZeroUsize::get).unwrap_or(4) * 2;
+        let (sender, mut receiver) = channel(bound);
+        rayon::spawn(move || {
+            pending.into_par_iter().for_each_with(sender, |sender, (index, path, attributes)| {
+                if !sender.is_closed() {
+                    let _ = sender.blocking_send((index, read_and_hash(&path, &attributes)));
+                }
+            })
+        });
+        while let Some((index, result)) = receiver.recv().await {
+            let (id, bytes) = result?;
+            if !store.has(id).await? {
+                store.put_prehashed(id, &bytes).await?;
+            }
+            ids[index] = Some(id);
+        }
+        let mut ids = files.iter().zip(ids).map(|(file, id)| {
+            let id = id.expect("every scanned file has been hashed");
+            cache.insert(&file.relative, &file.metadata, id);
+            id
+        });
+        Ok(build(&tree, &mut ids))
+    }
+}
+
+/// 并行遍历目录, 子目录会被分配到线程池中
+fn walk(
+    dir: &Path,
+    prefix: &str,
+    ignores: &IgnoreRules,
+    attributes: &PathAttributes,
+) -> Result<BTreeMap<String, ScanNode>, YsError> {
+    let entered = ignores.enter(dir, prefix)?;
+    let ignores: &IgnoreRules = &entered;
+    let entered = attributes.enter(dir, prefix)?;
+    let attributes: &PathAttributes = &entered;
+    let mut nodes = BTreeMap::new();
+    let mut directories = vec![];
+    for item in read_dir(dir).map_err(|e| YsError::path_error(e, dir))? {
+        let item = item?;
+        let Ok(name) = item.file_name().into_string()
+        else {
+            tracing::warn!("跳过无法识别的文件名: {:?}", item.path());
+            continue;
+        };
+        let relative = join_relative(prefix, &name);
+        let file_type = item.file_type()?;
+        if name.eq(DOT_YUAN_SHEN) || ignores.is_ignored(&relative, file_type.is_dir()) {
+            continue;
+        }
+        if file_type.is_dir() {
+            directories.push((name, item.path(), relative));
+        }
+        else if file_type.is_file() {
+            let metadata = item.metadata()?;
+            let resolved = attributes.resolve(&relative);
+            let file = ScanFile { relative, path: item.path(), metadata, attributes: resolved };
+            nodes.insert(name, ScanNode::File(Box::new(file)));
+        }
+        else {
+            tracing::warn!("暂不支持文件和目录以外的条目: {:?}", item.path());
+        }
+    }
+    let directories = directories
+        .into_par_iter()
+        .map(|(name, path, relative)| Ok((name, ScanNode::Directory(walk(&path, &relative, ignores, attributes)?))))
+        .collect::<Result<Vec<_>, YsError>>()?;
+    nodes.extend(directories);
+    Ok(nodes)
+}
+
+/// 按目录树的顺序列出所有文件, 与 [`build`] 的遍历顺序一致
+fn flatten<'a>(tree: &'a BTreeMap<String, ScanNode>, files: &mut Vec<&'a ScanFile>) {
+    for node in tree.values() {
+        match node {
+            ScanNode::File(file) => files.push(file),
+            ScanNode::Directory(dir) => flatten(dir, files),
+        }
+    }
+}
+
+/// 按 [`flatten`] 的顺序依次取出文件 id, 组装成目录树
+fn build(tree: &BTreeMap<String, ScanNode>, ids: &mut impl Iterator<Item = ObjectID>) -> Box<SnapShotDirectory> {
+    let mut root = BTreeMap::new();
+    for (name, node) in tree.iter() {
+        let entry = match node {
+            ScanNode::File(_) => DirectoryEntry::File(ids.next().expect("every scanned file has an id")),
+            ScanNode::Directory(dir) => DirectoryEntry::Directory(build(dir, ids)),
+        };
+        root.insert(name.clone(), entry);
+    }
+    Box::new(SnapShotDirectory { root })
+}
+
+/// 读取文件, 并按路径属性转换为写入储存库的内容
+pub(crate) fn read_stored(path: &Path, attributes: &ResolvedAttributes) -> Result<Vec<u8>, YsError> {
+    let mut bytes = Vec::new();
+    File::options()
+        .read(true)
+        .open(path)
+        .and_then(|mut f| f.read_to_end(&mut bytes))
+        .map_err(|e| YsError::path_error(e, path))?;
+    Ok(attributes.to_stored(bytes))
+}
+
+/// 读取文件并计算哈希, 大文件使用多线程哈希
+fn read_and_hash(path: &Path, attributes: &ResolvedAttributes) -> Result<(ObjectID, Vec<u8>), YsError> {
+    let bytes = read_stored(path, attributes)?;
+    let hash = if bytes.len() >= PARALLEL_HASH_THRESHOLD {
+        blake3::Hasher::new().update_rayon(&bytes).finalize()
+    }
+    else {
+        blake3::hash(&bytes)
+    };
+    Ok((ObjectID::from(hash), bytes))
+}
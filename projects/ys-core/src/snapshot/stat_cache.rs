@@ -0,0 +1,143 @@
+use super::*;
+use crate::{snapshot::scan::read_stored, ResolvedAttributes};
+use std::{
+    fs::{rename, Metadata},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// 文件元数据缓存, 储存在 `.ys/stat-cache` 中
+///
+/// 记录每个路径上次被哈希时的大小, 修改时间, inode 和 [`ObjectID`], 元数据没有变化的文件无需重新哈希.
+///
+/// 只有修改时间早于缓存文件自身修改时间的条目才会被信任: 如果文件在缓存写入的同一个时间粒度内被修改,
+/// 仅凭元数据无法分辨, 这类条目会被重新哈希.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StatCache {
+    /// 缓存文件的修改时间, 与被缓存的文件使用同一个文件系统的时间粒度
+    #[serde(skip)]
+    written: FileTime,
+    entries: BTreeMap<String, StatEntry>,
+    #[serde(skip)]
+    touched: BTreeSet<String>,
+}
+
+/// 单个文件的元数据记录
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatEntry {
+    /// 文件大小
+    pub size: u64,
+    /// 文件修改时间
+    pub modified: FileTime,
+    /// 文件的 inode 编号, 不支持的平台上为 0
+    pub inode: u64,
+    /// 文件内容的 id
+    pub id: ObjectID,
+}
+
+/// 纳秒精度的文件时间戳
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct FileTime {
+    /// 自 UNIX 纪元起的秒数
+    pub seconds: u64,
+    /// 秒以下的纳秒数
+    pub nanos: u32,
+}
+
+impl From<SystemTime> for FileTime {
+    fn from(time: SystemTime) -> Self {
+        let duration = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        Self { seconds: duration.as_secs(), nanos: duration.subsec_nanos() }
+    }
+}
+
+impl StatEntry {
+    fn new(metadata: &Metadata, id: ObjectID) -> Self {
+        Self {
+            size: metadata.len(),
+            modified: metadata.modified().map(FileTime::from).unwrap_or_default(),
+            inode: inode(metadata),
+            id,
+        }
+    }
+    fn matches(&self, metadata: &Metadata) -> bool {
+        let other = Self::new(metadata, self.id);
+        self.size == other.size && self.modified == other.modified && self.inode == other.inode
+    }
+}
+
+impl StatCache {
+    /// 读取缓存文件, 文件不存在或损坏时返回空缓存
+    pub fn load(path: &Path) -> Self {
+        match read_json::<StatCache>(path) {
+            Ok(mut o) => {
+                o.written = path.metadata().and_then(|m| m.modified()).map(FileTime::from).unwrap_or_default();
+                o
+            }
+            Err(e) => {
+                if path.exists() {
+                    tracing::warn!("忽略损坏的元数据缓存 {:?}: {}", path, e);
+                }
+                Self::default()
+            }
+        }
+    }
+    /// 将缓存写入磁盘, 先写入临时文件再重命名, 保证不会留下写了一半的缓存
+    ///
+    /// 与写入时间处于同一秒内的条目会被标记为失效, 下次读取时一定会重新哈希.
+    pub fn save(&mut self, path: &Path) -> Result<(), YsError> {
+        let now = FileTime::from(SystemTime::now());
+        for entry in self.entries.values_mut() {
+            if entry.modified.seconds >= now.seconds {
+                entry.size = u64::MAX;
+            }
+        }
+        let temp = path.with_extension("tmp");
+        std::fs::write(&temp, vec_json(self)?).map_err(|e| YsError::path_error(e, &temp))?;
+        rename(&temp, path).map_err(|e| YsError::path_error(e, path))?;
+        Ok(())
+    }
+    /// 查询元数据仍然有效的缓存记录, 可能被竞争修改的记录不会返回
+    pub fn lookup(&self, relative: &str, metadata: &Metadata) -> Option<ObjectID> {
+        let entry = self.entries.get(relative)?;
+        if entry.matches(metadata) && entry.modified < self.written {
+            Some(entry.id)
+        }
+        else {
+            None
+        }
+    }
+    /// 记录文件当前的元数据及其内容 id
+    pub fn insert(&mut self, relative: &str, metadata: &Metadata, id: ObjectID) {
+        self.touched.insert(relative.to_string());
+        self.entries.insert(relative.to_string(), StatEntry::new(metadata, id));
+    }
+    /// 计算文件的 id, 元数据没有变化时直接使用缓存
+    pub fn hash_file(&mut self, relative: &str, path: &Path) -> Result<ObjectID, YsError> {
+        self.hash_stored(relative, path, &ResolvedAttributes::default())
+    }
+    /// 按路径属性转换文件内容后计算 id, 与提交时写入储存库的 id 一致
+    pub fn hash_stored(&mut self, relative: &str, path: &Path, attributes: &ResolvedAttributes) -> Result<ObjectID, YsError> {
+        let metadata = path.metadata().map_err(|e| YsError::path_error(e, path))?;
+        let id = match self.lookup(relative, &metadata) {
+            Some(id) => id,
+            None => ObjectID::from(read_stored(path, attributes)?.as_slice()),
+        };
+        self.insert(relative, &metadata, id);
+        Ok(id)
+    }
+    /// 删除本次没有访问过的记录, 在完整扫描工作目录之后调用
+    pub fn prune(&mut self) {
+        let touched = &self.touched;
+        self.entries.retain(|k, _| touched.contains(k));
+    }
+}
+
+#[cfg(unix)]
+fn inode(metadata: &Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::ino(metadata)
+}
+
+#[cfg(not(unix))]
+fn inode(_: &Metadata) -> u64 {
+    0
+}
@@ -0,0 +1,159 @@
+use super::*;
+use crate::{initialize::DotYuanShenClient, snapshot::index::StagingIndex, PathAttributes};
+
+/// 工作目录相对于当前世界线的状态
+///
+/// 暂存区与最新快照之间的差异即下一次提交的内容, 工作目录与暂存区之间的差异需要先暂存才会被提交.
+/// 所有路径都是相对于工作目录的, 以 `/` 分隔, 并按字典序排列.
+#[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorkingStatus {
+    /// 快照中不存在, 下一次提交会加入的文件
+    pub added: BTreeSet<String>,
+    /// 暂存区中内容与快照不同的文件
+    pub modified: BTreeSet<String>,
+    /// 快照中存在, 但已从暂存区中移除的文件
+    pub deleted: BTreeSet<String>,
+    /// 已被暂存, 但工作目录中的内容与暂存区不同的文件
+    pub changed: BTreeSet<String>,
+    /// 已被暂存, 但已从工作目录中消失的文件
+    pub missing: BTreeSet<String>,
+    /// 工作目录中存在, 但没有加入暂存区的文件
+    pub untracked: BTreeSet<String>,
+    /// 被忽略规则排除的路径, 目录以 `/` 结尾
+    pub ignored: BTreeSet<String>,
+}
+
+impl WorkingStatus {
+    /// 比较快照中的目录树, 暂存区与磁盘上的工作目录
+    ///
+    /// 元数据没有变化的文件直接使用缓存中的 id, 不会重新哈希.
+    pub fn new(
+        head: &SnapShotDirectory,
+        index: &StagingIndex,
+        directory: &Path,
+        ignores: &IgnoreRules,
+        cache: &mut StatCache,
+    ) -> Result<Self, YsError> {
+        let mut status = WorkingStatus::default();
+        let head = StagingIndex::from_directory(head);
+        for (path, id) in index.entries.iter() {
+            match head.entries.get(path) {
+                None => status.added.insert(path.clone()),
+                Some(old) if old != id => status.modified.insert(path.clone()),
+                Some(_) => false,
+            };
+        }
+        status.deleted.extend(head.entries.keys().filter(|path| !index.entries.contains_key(*path)).cloned());
+        status.compare(&index.to_directory()?, directory, "", ignores, &PathAttributes::default(), cache)?;
+        Ok(status)
+    }
+    /// 工作目录中没有任何需要提交的修改, 被忽略的路径不计入其中
+    pub fn is_clean(&self) -> bool {
+        self.added.is_empty()
+            && self.modified.is_empty()
+            && self.deleted.is_empty()
+            && self.changed.is_empty()
+            && self.missing.is_empty()
+            && self.untracked.is_empty()
+    }
+    fn compare(
+        &mut self,
+        tracked: &SnapShotDirectory,
+        directory: &Path,
+        prefix: &str,
+        ignores: &IgnoreRules,
+        attributes: &PathAttributes,
+        cache: &mut StatCache,
+    ) -> Result<(), YsError> {
+        let entered = ignores.enter(directory, prefix)?;
+        let ignores: &IgnoreRules = &entered;
+        let entered = attributes.enter(directory, prefix)?;
+        let attributes: &PathAttributes = &entered;
+        let mut seen = BTreeSet::new();
+        for item in read_dir(directory).map_err(|e| YsError::path_error(e, directory))? {
+            let item = item?;
+            let Ok(name) = item.file_name().into_string()
+            else {
+                tracing::warn!("跳过无法识别的文件名: {:?}", item.path());
+                continue;
+            };
+            let path = item.path();
+            let relative = join_relative(prefix, &name);
+            let file_type = item.file_type()?;
+            if name.eq(DOT_YUAN_SHEN) || ignores.is_ignored(&relative, file_type.is_dir()) {
+                if file_type.is_dir() {
+                    self.ignored.insert(format!("{}/", relative));
+                }
+                else {
+                    self.ignored.insert(relative);
+                }
+                continue;
+            }
+            seen.insert(name.clone());
+            match (tracked.root.get(&name), file_type.is_dir()) {
+                (Some(DirectoryEntry::Directory(dir)), true) => {
+                    self.compare(dir, &path, &relative, ignores, attributes, cache)?
+                }
+                (Some(DirectoryEntry::File(id)), false) => {
+                    if cache.hash_stored(&relative, &path, &attributes.resolve(&relative))? != *id {
+                        self.changed.insert(relative);
+                    }
+                }
+                (Some(old), is_dir) => {
+                    collect_files(old, &relative, &mut self.missing);
+                    self.compare_new(&path, relative, is_dir, ignores, attributes, cache)?;
+                }
+                (None, is_dir) => self.compare_new(&path, relative, is_dir, ignores, attributes, cache)?,
+            }
+        }
+        for (name, entry) in tracked.root.iter() {
+            if !seen.contains(name) {
+                collect_files(entry, &join_relative(prefix, name), &mut self.missing);
+            }
+        }
+        Ok(())
+    }
+    fn compare_new(
+        &mut self,
+        path: &Path,
+        relative: String,
+        is_dir: bool,
+        ignores: &IgnoreRules,
+        attributes: &PathAttributes,
+        cache: &mut StatCache,
+    ) -> Result<(), YsError> {
+        if is_dir {
+            self.compare(&SnapShotDirectory::default(), path, &relative, ignores, attributes, cache)
+        }
+        else {
+            self.untracked.insert(relative);
+            Ok(())
+        }
+    }
+}
+
+impl DotYuanShenClient {
+    /// 比较工作目录, 暂存区与当前世界线的最新快照
+    pub async fn status(&self) -> Result<WorkingStatus, YsError> {
+        let head = self.head_directory().await?;
+        let index = self.index().await?;
+        let mut cache = self.stat_cache();
+        let status = WorkingStatus::new(&head, &index, self.working_directory(), &self.ignores()?, &mut cache)?;
+        cache.prune();
+        self.save_stat_cache(&mut cache)?;
+        Ok(status)
+    }
+}
+
+fn collect_files(entry: &DirectoryEntry, relative: &str, paths: &mut BTreeSet<String>) {
+    match entry {
+        DirectoryEntry::File(_) => {
+            paths.insert(relative.to_string());
+        }
+        DirectoryEntry::Directory(dir) => {
+            for (name, entry) in dir.root.iter() {
+                collect_files(entry, &join_relative(relative, name), paths);
+            }
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use super::*;
+use crate::{
+    initialize::{check_reference_name, DotYuanShenClient},
+    YsErrorKind,
+};
+
+/// 附注标签对象, 与快照一样通过 [ObjectStore::put_typed] 保存在储存库中
+///
+/// 轻量标签直接指向快照, 不会创建标签对象.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TagObject {
+    /// 标签指向的对象, 通常是快照, 也可以是另一个标签对象
+    pub target: ObjectID,
+    /// 创建时的标签名称, 校验时与引用的名称比较, 防止标签被改名冒充
+    pub name: String,
+    /// 创建标签的作者
+    pub tagger: AuthorID,
+    /// 标签的说明
+    pub message: String,
+    /// 创建标签的时间, 自 UNIX 纪元起的秒数
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub datetime: Option<u64>,
+    /// 使用共享密钥对其余字段计算的消息认证码 (blake3 keyed hash), 十六进制
+    ///
+    /// 这不是数字签名: 任何持有密钥的人都可以生成它, 只能向同样持有密钥的人证明标签没有被篡改,
+    /// 不能证明标签由谁创建.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+}
+
+impl TagObject {
+    /// 解析消息认证码使用的共享密钥, 可以是 32 字节的原始数据, 也可以是 64 个十六进制字符
+    pub fn parse_key(bytes: &[u8]) -> Result<[u8; 32], YsError> {
+        if let Ok(key) = <[u8; 32]>::try_from(bytes) {
+            return Ok(key);
+        }
+        match std::str::from_utf8(bytes).ok().and_then(|text| blake3::Hash::from_hex(text.trim()).ok()) {
+            Some(hash) => Ok(*hash.as_bytes()),
+            None => {
+                Err(YsErrorKind::InvalidObject { message: "密钥需要 32 字节或 64 个十六进制字符".to_string() })?
+            }
+        }
+    }
+    /// 消息认证码覆盖的内容, 即去掉认证码后的序列化结果
+    fn payload(&self) -> Result<Vec<u8>, YsError> {
+        Ok(serde_json::to_vec(&TagObject { mac: None, ..self.clone() })?)
+    }
+    /// 使用 32 字节的共享密钥计算消息认证码, 覆盖原有的认证码
+    pub fn authenticate(&mut self, key: &[u8; 32]) -> Result<(), YsError> {
+        self.mac = Some(blake3::keyed_hash(key, &self.payload()?).to_hex().to_string());
+        Ok(())
+    }
+    /// 使用共享密钥检查消息认证码, 没有认证码时返回 `false`
+    pub fn verify_mac(&self, key: &[u8; 32]) -> Result<bool, YsError> {
+        let expected = blake3::keyed_hash(key, &self.payload()?);
+        Ok(self.mac.as_deref().and_then(|hex| blake3::Hash::from_hex(hex).ok()).is_some_and(|hash| hash == expected))
+    }
+}
+
+/// 沿附注标签找到最终指向的对象, 不是标签对象的 id 原样返回
+pub async fn peel_tag<S: ObjectStore>(store: &S, mut id: ObjectID) -> Result<ObjectID, YsError> {
+    // 对象按内容寻址, 标签链不会成环
+    loop {
+        match store.get_typed::<TagObject>(id).await {
+            Ok(tag) => id = tag.target,
+            // 无法解析为标签对象, 说明已经到达快照或其他对象
+            Err(e) if matches!(e.kind(), YsErrorKind::Serde { .. }) => return Ok(id),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+impl DotYuanShenClient {
+    /// 保存附注标签对象并创建指向它的标签, 返回标签对象的 id
+    pub async fn create_annotated_tag(&self, tag: &TagObject, force: bool) -> Result<ObjectID, YsError> {
+        // 先校验名称, 以免为无法创建的标签写入对象
+        check_reference_name(&tag.name)?;
+        if !force && self.tag_exists(&tag.name)? {
+            Err(YsErrorKind::ReferenceExists { name: format!("tags/{}", tag.name) })?
+        }
+        let id = self.store()?.put_typed(tag).await?;
+        self.write_tag(&tag.name, id, force)?;
+        Ok(id)
+    }
+    /// 标签最终指向的对象, 附注标签会被展开
+    pub async fn resolve_tag(&self, name: &str) -> Result<ObjectID, YsError> {
+        peel_tag(&self.store()?, self.get_tag_id(name)?).await
+    }
+    /// 校验标签, 附注标签返回其标签对象, 轻量标签返回 `None`
+    ///
+    /// 检查对象内容与 id 一致, 标签对象记录的名称与标签相同, 以及最终指向的对象存在.
+    /// 给出 `key` 时还要求标签带有用该密钥计算的消息认证码.
+    pub async fn verify_tag(&self, name: &str, key: Option<&[u8; 32]>) -> Result<Option<TagObject>, YsError> {
+        let invalid = |message: String| YsErrorKind::InvalidObject { message };
+        let store = self.store()?;
+        let id = self.get_tag_id(name)?;
+        let bytes = store.get(id).await?;
+        if ObjectID::from(bytes.as_slice()) != id {
+            Err(invalid(format!("标签 {} 指向的对象 {} 内容已损坏", name, id)))?
+        }
+        let tag = match serde_json::from_slice::<TagObject>(&bytes) {
+            Ok(tag) => tag,
+            Err(_) if key.is_some() => Err(invalid(format!("轻量标签 {} 没有消息认证码", name)))?,
+            Err(_) => return Ok(None),
+        };
+        if tag.name != name {
+            Err(invalid(format!("标签 {} 的标签对象记录的名称是 {}", name, tag.name)))?
+        }
+        let target = peel_tag(&store, tag.target).await?;
+        if !store.has(target).await? {
+            Err(YsErrorKind::MissingObject { id: target })?
+        }
+        if let Some(key) = key {
+            if !tag.verify_mac(key)? {
+                Err(invalid(format!("标签 {} 的消息认证码无效", name)))?
+            }
+        }
+        Ok(Some(tag))
+    }
+}
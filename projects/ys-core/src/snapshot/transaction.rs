@@ -0,0 +1,228 @@
+use super::*;
+use crate::{
+    initialize::check_reference_name,
+    snapshot::reflog::{append_reflog, reflog_path, ReflogEntry},
+    YsErrorKind,
+};
+use std::io::ErrorKind;
+
+/// 引用事务对引用当前值的要求, 不满足时整个事务不会生效
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RefExpectation {
+    /// 不检查当前值
+    Any,
+    /// 引用必须不存在
+    Missing,
+    /// 引用必须指向给定的对象
+    Value(ObjectID),
+}
+
+impl RefExpectation {
+    fn expected(&self) -> Option<ObjectID> {
+        match self {
+            Self::Value(id) => Some(*id),
+            _ => None,
+        }
+    }
+}
+
+/// 事务中对单个引用的修改
+#[derive(Clone, Debug)]
+struct RefUpdate {
+    /// 相对于 `.ys` 的路径, 例如 `branches/main`
+    name: String,
+    expected: RefExpectation,
+    /// 新值, `None` 表示删除
+    new: Option<ObjectID>,
+    /// 继承该引用的历史, 用于重命名
+    reflog_from: Option<String>,
+}
+
+/// 同时修改多个引用的事务
+///
+/// 提交时按名称顺序为每个引用创建 `<name>.lock` 锁文件, 检查当前值是否符合预期, 把新值写入锁文件,
+/// 全部成功后才依次重命名到引用的位置. 这之前任何一步失败都会删除已经创建的锁文件, 不修改任何引用.
+/// 重命名本身不能整体回滚, 其间出现 IO 错误时, 已经重命名的引用会保留新值.
+/// 锁文件已经存在说明有其他进程正在修改同一个引用, 事务会直接失败而不是等待.
+///
+/// 每个被移动的引用都会在 `.ys/logs` 中留下一条 [ReflogEntry], 被删除的引用的历史也一并删除.
+#[derive(Clone, Debug)]
+pub struct RefTransaction {
+    dot_root: PathBuf,
+    updates: Vec<RefUpdate>,
+    operation: String,
+    author: AuthorID,
+}
+
+impl RefTransaction {
+    /// 创建修改 `dot_root` 中引用的事务
+    pub(crate) fn new(dot_root: &Path) -> Self {
+        Self {
+            dot_root: dot_root.to_path_buf(),
+            updates: vec![],
+            operation: "update".to_string(),
+            author: AuthorID::current_user(),
+        }
+    }
+    /// 记录在引用历史中的操作, 例如 `commit: 修复换行`, 默认为 `update`
+    pub fn operation(&mut self, operation: impl Into<String>) -> &mut Self {
+        self.operation = operation.into();
+        self
+    }
+    /// 记录在引用历史中的作者, 默认为当前系统用户
+    pub fn author(&mut self, author: AuthorID) -> &mut Self {
+        self.author = author;
+        self
+    }
+    /// 将引用指向 `new`, 引用不存在时创建
+    pub fn update(&mut self, name: impl Into<String>, new: ObjectID, expected: RefExpectation) -> &mut Self {
+        self.updates.push(RefUpdate { name: name.into(), expected, new: Some(new), reflog_from: None });
+        self
+    }
+    /// 让最近添加的修改继承另一个引用的历史, 通常与删除该引用配合实现重命名
+    pub(crate) fn inherit_reflog(&mut self, from: impl Into<String>) -> &mut Self {
+        if let Some(update) = self.updates.last_mut() {
+            update.reflog_from = Some(from.into());
+        }
+        self
+    }
+    /// 删除引用, 引用不存在时只要求满足 `expected`
+    pub fn delete(&mut self, name: impl Into<String>, expected: RefExpectation) -> &mut Self {
+        self.updates.push(RefUpdate { name: name.into(), expected, new: None, reflog_from: None });
+        self
+    }
+    /// 提交事务
+    ///
+    /// 加锁和检查失败时不修改任何引用; 之后逐个重命名锁文件, 中途失败时已经生效的修改不会撤销.
+    pub fn commit(mut self) -> Result<(), YsError> {
+        self.updates.sort_by(|a, b| a.name.cmp(&b.name));
+        if let Some(pair) = self.updates.windows(2).find(|pair| pair[0].name == pair[1].name) {
+            Err(YsErrorKind::InvalidReferenceName {
+                name: pair[0].name.clone(), reason: "同一个事务中不能多次修改同一个引用"
+            })?
+        }
+        let mut locks = vec![];
+        for update in self.updates.iter() {
+            match update.name.split_once('/') {
+                Some(("branches" | "tags", name)) => check_reference_name(name)?,
+                _ => Err(YsErrorKind::InvalidReferenceName {
+                    name: update.name.clone(), reason: "只能修改世界线和标签"
+                })?,
+            }
+            let path = self.dot_root.join(&update.name);
+            if let Some(parent) = path.parent() {
+                create_dir_all(parent)?;
+            }
+            let mut lock = RefLock::acquire(&path, &update.name)?;
+            let actual = match try_exists(&path)? {
+                // 不检查当前值时, 无法解析的引用会被直接覆盖
+                true if update.expected == RefExpectation::Any => ObjectID::read_reference(&path).ok(),
+                true => Some(ObjectID::read_reference(&path)?),
+                false => None,
+            };
+            if update.expected != RefExpectation::Any && actual != update.expected.expected() {
+                let expected = update.expected.expected();
+                Err(YsErrorKind::StaleReference { name: update.name.clone(), expected, actual })?
+            }
+            if let Some(new) = update.new {
+                lock.write(&new.reference_bytes()?)?;
+            }
+            locks.push((lock, actual));
+        }
+        let datetime = SnapShotData::now().unwrap_or_default();
+        let inherited: BTreeSet<&str> = self.updates.iter().filter_map(|update| update.reflog_from.as_deref()).collect();
+        for (update, (lock, old)) in self.updates.iter().zip(locks) {
+            let reflog = reflog_path(&self.dot_root, &update.name);
+            match update.new {
+                Some(new) => {
+                    let mut old = old;
+                    if let Some(from) = &update.reflog_from {
+                        let from = reflog_path(&self.dot_root, from);
+                        if try_exists(&from)? {
+                            if let Some(parent) = reflog.parent() {
+                                create_dir_all(parent)?;
+                            }
+                            std::fs::rename(&from, &reflog).map_err(|e| YsError::path_error(e, &from))?;
+                        }
+                        // 对继承来的历史而言, 重命名没有移动引用
+                        old = Some(new);
+                    }
+                    // 迁移格式这样没有移动引用的修改不记录, 重命名除外
+                    lock.commit()?;
+                    // 引用确实移动之后才记录, 失败的提交不会留下历史
+                    if old != Some(new) || update.reflog_from.is_some() {
+                        let operation = self.operation.clone();
+                        append_reflog(
+                            &self.dot_root,
+                            &update.name,
+                            &ReflogEntry { old, new, operation, author: self.author, datetime },
+                        )?;
+                    }
+                }
+                None => {
+                    remove_if_exists(&lock.target)?;
+                    if !inherited.contains(update.name.as_str()) {
+                        remove_if_exists(&reflog)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 删除文件, 文件不存在时什么也不做
+fn remove_if_exists(path: &Path) -> Result<(), YsError> {
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(YsError::path_error(e, path)),
+    }
+}
+
+/// 原子地替换 `.ys` 中不属于引用的小文件, 例如记录当前世界线的 `branch`
+pub(crate) fn replace_file(path: &Path, bytes: &[u8]) -> Result<(), YsError> {
+    let mut lock = RefLock::acquire(path, &path.to_string_lossy())?;
+    lock.write(bytes)?;
+    lock.commit()
+}
+
+/// 引用或其他文件的锁文件 `<path>.lock`, 没有提交就被丢弃时自动删除
+#[derive(Debug)]
+pub(crate) struct RefLock {
+    lock: PathBuf,
+    target: PathBuf,
+    file: File,
+    committed: bool,
+}
+
+impl RefLock {
+    pub(crate) fn acquire(target: &Path, name: &str) -> Result<Self, YsError> {
+        let mut lock = target.as_os_str().to_owned();
+        lock.push(".lock");
+        let lock = PathBuf::from(lock);
+        match File::options().write(true).create_new(true).open(&lock) {
+            Ok(file) => Ok(Self { lock, target: target.to_path_buf(), file, committed: false }),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                Err(YsErrorKind::ReferenceLocked { name: name.to_string(), lock })?
+            }
+            Err(e) => Err(YsError::path_error(e, lock)),
+        }
+    }
+    pub(crate) fn write(&mut self, bytes: &[u8]) -> Result<(), YsError> {
+        self.file.write_all(bytes).and_then(|_| self.file.sync_all()).map_err(|e| YsError::path_error(e, &self.lock))
+    }
+    pub(crate) fn commit(mut self) -> Result<(), YsError> {
+        std::fs::rename(&self.lock, &self.target).map_err(|e| YsError::path_error(e, &self.target))?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for RefLock {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = std::fs::remove_file(&self.lock);
+        }
+    }
+}
@@ -1,12 +1,12 @@
-use crate::{YsError, YsErrorKind};
+use crate::YsError;
 use blake3::Hash;
 use serde::{Deserialize, Serialize};
 use serde_json::{ser::PrettyFormatter, Serializer};
 use std::{
     fmt::Formatter,
-    fs::File,
-    io::Write,
-    path::{Path, PathBuf},
+    fs::{create_dir_all, File},
+    io::{BufRead, BufReader, ErrorKind, Write},
+    path::Path,
 };
 
 pub trait WriteHashID {
@@ -29,13 +29,49 @@ where
     Ok(serde_json::from_reader(File::options().read(true).open(path)?)?)
 }
 
-pub fn write_json<A>(thing: &A, path: &Path) -> Result<(), YsError>
+/// 读取每行一个 JSON 值的日志文件, 文件不存在时返回空列表
+pub fn read_json_lines<A>(path: &Path) -> Result<Vec<A>, YsError>
+where
+    A: for<'de> Deserialize<'de>,
+{
+    let file = match File::open(path) {
+        Ok(o) => o,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => Err(YsError::path_error(e, path))?,
+    };
+    let mut things = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| YsError::path_error(e, path))?;
+        // 写入中断时最后一行可能不完整, 跳过而不是让整个日志无法读取
+        if let Ok(thing) = serde_json::from_str(&line) {
+            things.push(thing);
+        }
+    }
+    Ok(things)
+}
+/// 每行一个 JSON 值的日志文件的内容
+pub fn json_lines<'a, A>(things: impl IntoIterator<Item = &'a A>) -> Result<Vec<u8>, YsError>
+where
+    A: Serialize + 'a,
+{
+    let mut bytes = vec![];
+    for thing in things {
+        serde_json::to_writer(&mut bytes, thing)?;
+        bytes.push(b'\n');
+    }
+    Ok(bytes)
+}
+/// 在日志文件的末尾追加一行, 文件和所在的目录不存在时创建
+pub fn append_json_line<A>(path: &Path, thing: &A) -> Result<(), YsError>
 where
     A: Serialize,
 {
-    let file = File::options().write(true).create(true).open(path)?;
-    let mut ser = Serializer::with_formatter(file, PrettyFormatter::with_indent(b"    "));
-    Ok(thing.serialize(&mut ser)?)
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).map_err(|e| YsError::path_error(e, parent))?;
+    }
+    let line = json_lines([thing])?;
+    let mut file = File::options().create(true).append(true).open(path).map_err(|e| YsError::path_error(e, path))?;
+    file.write_all(&line).map_err(|e| YsError::path_error(e, path))
 }
 pub fn vec_json<A>(thing: &A) -> Result<Vec<u8>, YsError>
 where
@@ -54,13 +90,3 @@ where
     let buffer = vec_json(thing)?;
     Ok(blake3::hash(&buffer))
 }
-
-pub fn truncate_write(path: PathBuf, bytes: &[u8]) -> Result<usize, YsError> {
-    let open = File::options().write(true).truncate(true).open(&path);
-    match open.and_then(|mut o| o.write(bytes)) {
-        Ok(o) => Ok(o),
-        Err(e) => {
-            return Err(YsErrorKind::IO { error: e, path: Some(path.to_path_buf()) })?;
-        }
-    }
-}
@@ -1,10 +1,20 @@
 use std::{
     collections::{BTreeMap, BTreeSet},
     env::current_dir,
+    fs::File,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
 };
+use tempfile::TempDir;
 use ys_core::{
-    differences::{DifferenceEntry, SnapShotDifference},
-    DirectoryEntry, IgnoreRules, LocalObjectStore, MemoryObjectStore, ObjectID, ObjectStore, SnapShotDirectory,
+    diff_lines,
+    set_config_value, unset_config_value, ConfigLayer, LayeredConfig, CONFIG_FILE,
+    differences::{DiffStat, DifferenceEntry, DifferenceRecord, DifferenceStackType, RenameOptions, SnapShotDifference},
+    initialize::{requested_work_tree, DotYuanShenClient, InitializeConfig, YuanShenClient},
+    Ancestry, AttributeValue, AuthorID, BranchFilter, BranchInfo, DiffSide, DirectoryEntry, FilePatch, Head, IgnoreRules,
+    LineChange, LocalObjectStore, MaterializeAction, MemoryObjectStore, ObjectID, ObjectStore, OperationKind, PathAttributes,
+    RefExpectation, RepositoryState, Revision, RevisionSpec, SnapShot, SnapShotData, SnapShotDirectory, StagingIndex,
+    StatCache, TagObject, WorkingStatus, YsErrorKind,
 };
 
 #[test]
@@ -14,6 +24,28 @@ fn ready() {
 
 const YUAN_SHEN: &[u8] = "源神, 启动!".as_bytes();
 
+/// 在临时目录中初始化初始世界线为 `main` 的仓库, 返回临时目录, 仓库, 对象储存库和初始快照
+async fn init_repo() -> (TempDir, DotYuanShenClient, LocalObjectStore, ObjectID) {
+    let temp = tempfile::tempdir().unwrap();
+    let config =
+        InitializeConfig { current: temp.path().to_path_buf(), initial_branch: "main".into(), ignores: IgnoreRules::default() };
+    let ys = config.generate().await.unwrap();
+    let store = ys.store().unwrap();
+    let root = ys.get_branch_id("main").unwrap();
+    (temp, ys, store, root)
+}
+
+/// 保存一个目录为空, 前驱为 `parent` 的快照
+async fn child_of(store: &mut LocalObjectStore, parent: ObjectID, message: &str) -> ObjectID {
+    let snapshot = SnapShot {
+        directory: store.put_typed(&SnapShotDirectory::default()).await.unwrap(),
+        previous: BTreeSet::from([parent]),
+        data: SnapShotData { kind: 0, message: message.to_string(), authors: Default::default(), datetime: None },
+        ignores: None,
+    };
+    store.put_typed(&snapshot).await.unwrap()
+}
+
 #[tokio::test]
 async fn test_memory_object_store() {
     let mut store = MemoryObjectStore::new();
@@ -30,35 +62,61 @@ async fn test_local_object_store() {
     store.put(YUAN_SHEN).await.unwrap();
     assert!(store.has(YUAN_SHEN.into()).await.unwrap());
     assert_eq!(store.get(YUAN_SHEN.into()).await.unwrap(), Vec::from(YUAN_SHEN));
+
+    // 压缩的对象与未压缩的对象可以混合保存, 读取时都还原为原始内容
+    let mut store = store.with_compression_level(9);
+    let text = "源神, 启动!\n".repeat(64);
+    let id = store.put(text.as_bytes()).await.unwrap();
+    let path = temp.path().join(&id.to_string()[..2]).join(&id.to_string()[2..]);
+    assert!(std::fs::metadata(&path).unwrap().len() < text.len() as u64);
+    assert_eq!(store.get(id).await.unwrap(), text.as_bytes());
+    assert_eq!(store.get(YUAN_SHEN.into()).await.unwrap(), Vec::from(YUAN_SHEN));
+    assert_eq!(store.find_prefix(&id.to_string()[..6]).await.unwrap(), [id]);
 }
 
 #[test]
-#[ignore]
 fn test_diff_display() {
-    let diff_empty: SnapShotDifference =
-        SnapShotDifference { deleted: BTreeSet::new(), added: BTreeMap::new(), modified: BTreeMap::new() };
+    let diff_empty: SnapShotDifference = SnapShotDifference {
+        deleted: BTreeSet::new(),
+        added: BTreeMap::new(),
+        modified: BTreeMap::new(),
+        ..Default::default()
+    };
     assert_eq!(diff_empty.to_string(), "");
 
     let deleted_foo = BTreeSet::from([String::from("foo")]);
     let added_bar: BTreeMap<String, DirectoryEntry> =
         vec![(String::from("bar"), DirectoryEntry::File(ObjectID::from(&vec![])))].into_iter().collect();
 
-    let diff_1: SnapShotDifference =
-        SnapShotDifference { deleted: BTreeSet::new(), added: added_bar.clone(), modified: BTreeMap::new() };
+    let diff_1: SnapShotDifference = SnapShotDifference {
+        deleted: BTreeSet::new(),
+        added: added_bar.clone(),
+        modified: BTreeMap::new(),
+        ..Default::default()
+    };
     assert_eq!(diff_1.to_string(), "A bar\n");
 
-    let diff_2: SnapShotDifference =
-        SnapShotDifference { deleted: deleted_foo.clone(), added: BTreeMap::new(), modified: BTreeMap::new() };
+    let diff_2: SnapShotDifference = SnapShotDifference {
+        deleted: deleted_foo.clone(),
+        added: BTreeMap::new(),
+        modified: BTreeMap::new(),
+        ..Default::default()
+    };
     assert_eq!(diff_2.to_string(), "D foo\n");
 
-    let diff_3: SnapShotDifference =
-        SnapShotDifference { deleted: deleted_foo.clone(), added: added_bar.clone(), modified: BTreeMap::new() };
+    let diff_3: SnapShotDifference = SnapShotDifference {
+        deleted: deleted_foo.clone(),
+        added: added_bar.clone(),
+        modified: BTreeMap::new(),
+        ..Default::default()
+    };
     assert_eq!(diff_3.to_string(), ["A bar", "D foo", ""].join("\n"));
 
     let diff_4: SnapShotDifference = SnapShotDifference {
         deleted: deleted_foo.clone(),
         added: added_bar.clone(),
         modified: vec![(String::from("baz"), DifferenceEntry::File(ObjectID::from(&vec![])))].into_iter().collect(),
+        ..Default::default()
     };
     assert_eq!(diff_4.to_string(), ["A bar", "M baz", "D foo", ""].join("\n"));
 
@@ -71,10 +129,735 @@ fn test_diff_display() {
         ]
         .into_iter()
         .collect(),
+        ..Default::default()
     };
     assert_eq!(diff_5.to_string(), ["D a/foo", "A bar", "A baz/bar", "M baz/baz", "D baz/foo", "D foo", ""].join("\n"));
 }
 
+#[test]
+fn test_unified_patch() {
+    use LineChange::*;
+    assert_eq!(diff_lines(&["a", "b", "c"], &["a", "c", "d"]), [Equal, Delete, Equal, Insert]);
+    assert_eq!(diff_lines::<&str>(&[], &[]), []);
+
+    let old = (1..=10).map(|i| format!("{i}\n")).collect::<String>();
+    let new = old.replace("2\n", "two\n").replace("9\n", "").replace("10\n", "10");
+    let patch = FilePatch::new(
+        "a/b.txt",
+        Some((ObjectID::from(old.as_bytes()), old.as_bytes())),
+        Some((ObjectID::from(new.as_bytes()), new.as_bytes())),
+        false,
+        1,
+    );
+    let expected = [
+        "diff --ys a/a/b.txt b/a/b.txt",
+        "--- a/a/b.txt",
+        "+++ b/a/b.txt",
+        "@@ -1,3 +1,3 @@",
+        " 1",
+        "-2",
+        "+two",
+        " 3",
+        "@@ -8,3 +8,2 @@",
+        " 8",
+        "-9",
+        "-10",
+        "+10",
+        "\\ No newline at end of file",
+        "",
+    ];
+    assert_eq!(patch.to_string(), expected.join("\n"));
+
+    let added = FilePatch::new("new", None, Some((ObjectID::from(b"x\n".as_slice()), b"x\n")), false, 3);
+    assert_eq!(added.to_string(), "diff --ys a/new b/new\n--- /dev/null\n+++ b/new\n@@ -0,0 +1,1 @@\n+x\n");
+
+    let binary = FilePatch::new("bin", None, Some((ObjectID::from(b"\0".as_slice()), b"\0")), false, 3);
+    assert_eq!(binary.to_string(), "diff --ys a/bin b/bin\nBinary files /dev/null and b/bin differ\n");
+    let marked = FilePatch::new("txt", None, Some((ObjectID::from(b"x".as_slice()), b"x")), true, 3);
+    assert!(marked.hunks.is_none());
+}
+
+#[tokio::test]
+async fn test_rename_detection() {
+    let mut store = MemoryObjectStore::new();
+    let text = (1..=20).map(|i| format!("line {i}\n")).collect::<String>();
+    let same = store.put(b"moved\n").await.unwrap();
+    let original = store.put(text.as_bytes()).await.unwrap();
+    let edited = store.put(text.replace("line 7\n", "seven\n").as_bytes()).await.unwrap();
+    let unrelated = store.put(b"something else\n").await.unwrap();
+    let old = SnapShotDirectory {
+        root: BTreeMap::from([
+            (String::from("a.txt"), DirectoryEntry::File(same)),
+            (String::from("long.txt"), DirectoryEntry::File(original)),
+            (String::from("gone"), DirectoryEntry::File(unrelated)),
+        ]),
+    };
+    let nested = SnapShotDirectory { root: BTreeMap::from([(String::from("a.txt"), DirectoryEntry::File(same))]) };
+    let new = SnapShotDirectory {
+        root: BTreeMap::from([
+            (String::from("dir"), DirectoryEntry::Directory(Box::new(nested))),
+            (String::from("renamed.txt"), DirectoryEntry::File(edited)),
+            (String::from("new"), DirectoryEntry::File(store.put(b"fresh\n").await.unwrap())),
+        ]),
+    };
+    let diff = old.difference_with_renames(&new, &store, &RenameOptions::default()).await.unwrap();
+    assert_eq!(diff.renamed["dir/a.txt"].from, "a.txt");
+    assert_eq!(diff.renamed["dir/a.txt"].similarity, 100);
+    assert_eq!(diff.renamed["renamed.txt"].from, "long.txt");
+    assert_eq!(diff.renamed["renamed.txt"].similarity, 95);
+    assert_eq!(diff.renamed.len(), 2);
+    assert_eq!(diff.renamed_to("long.txt"), Some("renamed.txt"));
+    assert_eq!(diff.renamed_to("gone"), None);
+    assert_eq!(diff.to_string(), ["R100 a.txt -> dir/a.txt", "D gone", "A new", "R095 long.txt -> renamed.txt", ""].join("\n"));
+
+    let strict = RenameOptions { threshold: 100, ..Default::default() };
+    let diff = old.difference_with_renames(&new, &store, &strict).await.unwrap();
+    assert_eq!(diff.renamed.keys().collect::<Vec<_>>(), ["dir/a.txt"]);
+
+    let kept = SnapShotDirectory {
+        root: BTreeMap::from([
+            (String::from("a.txt"), DirectoryEntry::File(same)),
+            (String::from("b.txt"), DirectoryEntry::File(same)),
+        ]),
+    };
+    let diff = old.difference_with_renames(&kept, &store, &RenameOptions::default()).await.unwrap();
+    assert!(diff.renamed.is_empty() && diff.copied.is_empty());
+    let copies = RenameOptions { copies: true, ..Default::default() };
+    let diff = old.difference_with_renames(&kept, &store, &copies).await.unwrap();
+    assert_eq!(diff.copied["b.txt"].from, "a.txt");
+    assert!(diff.to_string().contains("C100 a.txt -> b.txt\n"));
+}
+
+#[tokio::test]
+async fn test_merge_follows_renames() {
+    let mut store = MemoryObjectStore::new();
+    let long = (0..20).map(|i| format!("line {}\n", i)).collect::<String>();
+    let original = store.put(long.as_bytes()).await.unwrap();
+    let edited = store.put(long.replace("line 3\n", "line three\n").as_bytes()).await.unwrap();
+    let other = store.put(b"other\n").await.unwrap();
+    let changed = store.put(b"changed\n").await.unwrap();
+    let tree = |entries: &[(&str, ObjectID)]| {
+        let entries = entries.iter().map(|(path, id)| (path.to_string(), *id)).collect();
+        StagingIndex { entries }.to_directory().unwrap()
+    };
+    let base = tree(&[("long.txt", original), ("other", other)]);
+    let ours = tree(&[("docs/long.txt", original), ("other", other)]);
+    let theirs = tree(&[("long.txt", edited), ("other", other), ("new", other)]);
+    let merge = base.merge(&ours, &theirs, &store, &RenameOptions::default()).await.unwrap();
+    assert!(merge.is_clean(), "{:?}", merge);
+    // 修改跟随重命名后的文件
+    let expected = [("docs/long.txt", edited), ("new", other), ("other", other)];
+    assert_eq!(merge.index.entries, expected.iter().map(|(path, id)| (path.to_string(), *id)).collect());
+    let merge = base.merge(&theirs, &ours, &store, &RenameOptions::default()).await.unwrap();
+    assert_eq!(merge.index.entries, expected.iter().map(|(path, id)| (path.to_string(), *id)).collect());
+
+    let theirs = tree(&[("long.txt", original), ("other", changed)]);
+    let ours = tree(&[("long.txt", original)]);
+    let merge = base.merge(&ours, &theirs, &store, &RenameOptions::default()).await.unwrap();
+    assert_eq!(merge.conflicts.iter().collect::<Vec<_>>(), ["other"]);
+}
+
+#[tokio::test]
+async fn test_difference_records() {
+    let mut store = MemoryObjectStore::new();
+    let a = store.put(b"one\ntwo\n").await.unwrap();
+    let b = store.put(b"one\nthree\nfour\n").await.unwrap();
+    let nested = |entries: Vec<(&str, DirectoryEntry)>| {
+        DirectoryEntry::Directory(Box::new(SnapShotDirectory {
+            root: entries.into_iter().map(|(name, entry)| (name.to_string(), entry)).collect(),
+        }))
+    };
+    let old = SnapShotDirectory {
+        root: BTreeMap::from([
+            (String::from("a"), DirectoryEntry::File(a)),
+            (String::from("a.txt"), DirectoryEntry::File(a)),
+            (String::from("dir"), nested(vec![("x", DirectoryEntry::File(a)), ("y", DirectoryEntry::File(a))])),
+        ]),
+    };
+    let new = SnapShotDirectory {
+        root: BTreeMap::from([
+            (String::from("a"), nested(vec![("inner", DirectoryEntry::File(a))])),
+            (String::from("a.txt"), DirectoryEntry::File(b)),
+            (String::from("dir"), nested(vec![("y", DirectoryEntry::File(a))])),
+            (String::from("moved"), DirectoryEntry::File(a)),
+        ]),
+    };
+    let records: Vec<DifferenceRecord> = old.changes(&new).collect();
+    let summary: Vec<(&str, DifferenceStackType, Option<ObjectID>, Option<ObjectID>)> =
+        records.iter().map(|record| (record.path.as_str(), record.kind, record.old, record.new)).collect();
+    assert_eq!(
+        summary,
+        [
+            ("a", DifferenceStackType::Deleted, Some(a), None),
+            ("a.txt", DifferenceStackType::Modified, Some(a), Some(b)),
+            ("a/inner", DifferenceStackType::Added, None, Some(a)),
+            ("dir/x", DifferenceStackType::Deleted, Some(a), None),
+            ("moved", DifferenceStackType::Added, None, Some(a)),
+        ]
+    );
+
+    let difference = old.difference_with_renames(&new, &store, &RenameOptions::default()).await.unwrap();
+    let lines: Vec<String> = difference.records(&old, &new).map(|record| record.to_string()).collect();
+    assert_eq!(lines, ["M a.txt", "R100 a -> a/inner", "R100 dir/x -> moved"]);
+
+    let stat = DiffStat::collect(&records, &store).await.unwrap();
+    let expected =
+        DiffStat { files_changed: 5, lines_added: 6, lines_removed: 5, bytes_added: 27, bytes_removed: 20, binary_files: 0 };
+    assert_eq!(stat, expected);
+    let binary = DiffStat::between(Some(b"\0\0"), Some(b"\0"));
+    assert_eq!(binary.binary_files, 1);
+    assert_eq!((binary.lines_added, binary.bytes_removed, binary.bytes_added), (0, 2, 1));
+
+    let temp = tempfile::tempdir().unwrap();
+    old.checkout(&SnapShotDirectory::default(), &store, temp.path()).await.unwrap();
+    SnapShotDirectory::default().checkout(&old, &store, temp.path()).await.unwrap();
+    std::fs::write(temp.path().join("dir").join("untracked"), b"keep").unwrap();
+    old.checkout(&new, &store, temp.path()).await.unwrap();
+    assert_eq!(std::fs::read(temp.path().join("a").join("inner")).unwrap(), b"one\ntwo\n");
+    assert!(!temp.path().join("dir").join("x").exists());
+    assert!(temp.path().join("dir").join("untracked").exists());
+    new.checkout(&SnapShotDirectory::default(), &store, temp.path()).await.unwrap();
+    assert!(!temp.path().join("a").exists());
+    assert!(temp.path().join("dir").join("untracked").exists());
+}
+
+#[tokio::test]
+async fn test_diff_between_sides() {
+    let (temp, ys, mut store, first) = init_repo().await;
+    let dot = temp.path().join(".ys");
+    // 初始化写入的快照可以从打开的储存库中读取
+    let initial: SnapShot = store.get_typed(first).await.unwrap();
+    assert_eq!(initial.data.message, "Project initialized!");
+    let file = store.put(b"one\n").await.unwrap();
+    let directory = SnapShotDirectory { root: BTreeMap::from([(String::from("a.txt"), DirectoryEntry::File(file))]) };
+    let snapshot = SnapShot {
+        directory: store.put_typed(&directory).await.unwrap(),
+        previous: BTreeSet::from([first]),
+        data: SnapShotData { kind: 0, message: String::new(), authors: Default::default(), datetime: None },
+        ignores: None,
+    };
+    let second = store.put_typed(&snapshot).await.unwrap();
+    second.write_branch(&dot, "main").unwrap();
+    std::fs::write(temp.path().join("a.txt"), "one\ntwo\n").unwrap();
+
+    assert_eq!(ys.resolve_revision("HEAD").await.unwrap(), second);
+    assert_eq!(ys.resolve_revision("main").await.unwrap(), second);
+    assert_eq!(ys.resolve_revision("HEAD~1").await.unwrap(), first);
+    assert_eq!(ys.resolve_revision("main^").await.unwrap(), first);
+    assert_eq!(ys.resolve_revision(&second.to_string()[..8]).await.unwrap(), second);
+    assert!(ys.resolve_revision("HEAD~2").await.is_err());
+    assert!(ys.resolve_revision("missing").await.is_err());
+
+    let records = ys.diff_records(&DiffSide::Index, &DiffSide::WorkingTree, None, &[]).await.unwrap();
+    assert_eq!(records.iter().map(|record| record.to_string()).collect::<Vec<_>>(), ["M a.txt"]);
+    assert_eq!(records[0].new, Some(ObjectID::from(b"one\ntwo\n".as_slice())));
+    assert!(ys.diff_records(&DiffSide::Snapshot(second), &DiffSide::Index, None, &[]).await.unwrap().is_empty());
+
+    let patches = ys.patches(&DiffSide::Snapshot(first), &DiffSide::WorkingTree, 3, None, &[]).await.unwrap();
+    assert_eq!(patches.len(), 1);
+    assert_eq!(patches[0].to_string(), "diff --ys a/a.txt b/a.txt\n--- /dev/null\n+++ b/a.txt\n@@ -0,0 +1,2 @@\n+one\n+two\n");
+    let filtered = ys.patches(&DiffSide::Snapshot(first), &DiffSide::WorkingTree, 3, None, &[String::from("b")]).await;
+    assert!(filtered.unwrap().is_empty());
+    let stats = ys.diff_stats(&DiffSide::Snapshot(second), &DiffSide::WorkingTree, None, &[]).await.unwrap();
+    assert_eq!((stats[0].1.lines_added, stats[0].1.lines_removed), (1, 0));
+}
+
+#[tokio::test]
+async fn test_revision_spec() {
+    let spec: RevisionSpec = "main@{2024-02-29 12:30}~2^2..HEAD".parse().unwrap();
+    let RevisionSpec::Range { from, to, symmetric: false } = &spec
+    else {
+        panic!("{spec:?}")
+    };
+    assert_eq!(from.name.as_deref(), Some("main"));
+    assert_eq!(from.date, Some(1709209800));
+    assert_eq!(from.steps, [Ancestry::Ancestor(2), Ancestry::Parent(2)]);
+    assert_eq!(to, &Revision::head());
+    assert_eq!(spec.to_string(), "main@{2024-02-29 12:30:00}~2^2..HEAD");
+    assert_eq!("...@^".parse::<RevisionSpec>().unwrap().to_string(), "HEAD...HEAD^1");
+    for invalid in ["", "..", "~1", "main@{2023-02-29}", "main@{soon}", "main~1x", "main@{now"] {
+        assert!(invalid.parse::<RevisionSpec>().is_err(), "{invalid}");
+    }
+
+    let (temp, ys, mut store, root) = init_repo().await;
+    let dot = temp.path().join(".ys");
+    let snapshot = |previous: Vec<ObjectID>, datetime: u64| SnapShot {
+        directory: root,
+        previous: previous.into_iter().collect(),
+        data: SnapShotData { kind: 0, message: datetime.to_string(), authors: Default::default(), datetime: Some(datetime) },
+        ignores: None,
+    };
+    // root <- left <- merge, root <- right <- merge
+    let left = store.put_typed(&snapshot(vec![root], 100)).await.unwrap();
+    let right = store.put_typed(&snapshot(vec![root], 200)).await.unwrap();
+    let merge = store.put_typed(&snapshot(vec![left, right], 300)).await.unwrap();
+    merge.write_branch(&dot, "main").unwrap();
+    right.write_branch(&dot, "side").unwrap();
+    let (first, second) = if left < right { (left, right) } else { (right, left) };
+
+    assert_eq!(ys.resolve_revision("main^1").await.unwrap(), first);
+    assert_eq!(ys.resolve_revision("main^2").await.unwrap(), second);
+    assert_eq!(ys.resolve_revision("HEAD^2~1").await.unwrap(), root);
+    assert_eq!(ys.resolve_revision("@^0").await.unwrap(), merge);
+    // 沿第一个前驱查找, 两个前驱的时间都早于给出的时间
+    assert_eq!(ys.resolve_revision("main@{1970-01-01 00:04:10}").await.unwrap(), first);
+    assert!(ys.resolve_revision("main^3").await.is_err());
+    assert!(ys.resolve_revision("side@{1970-01-01}").await.is_err());
+    assert_eq!(ys.resolve_range(&"side".parse().unwrap(), &Revision::head(), false).await.unwrap(), (right, merge));
+    assert_eq!(ys.merge_base(left, right).await.unwrap(), Some(root));
+    let RevisionSpec::Range { from, to, symmetric } = "side...main~1".parse().unwrap()
+    else {
+        unreachable!()
+    };
+    assert_eq!(ys.resolve_range(&from, &to, symmetric).await.unwrap().0, if first == left { root } else { right });
+
+    let mut memory = MemoryObjectStore::new();
+    let id = memory.put(b"object").await.unwrap();
+    assert_eq!(memory.find_prefix(&id.to_string()[..6]).await.unwrap(), [id]);
+    assert_eq!(store.find_prefix(&merge.to_string()[..10]).await.unwrap(), [merge]);
+
+    // 构造两个缩写相同的快照
+    let mut seen = BTreeMap::new();
+    let mut datetime = 1000;
+    let (a, b) = loop {
+        let id = store.put_typed(&snapshot(vec![root], datetime)).await.unwrap();
+        if let Some(other) = seen.insert(id.to_string()[..4].to_string(), id) {
+            break (other, id);
+        }
+        datetime += 1;
+    };
+    let error = ys.resolve_revision(&a.to_string()[..4]).await.unwrap_err().to_string();
+    assert!(error.contains(&a.to_string()) && error.contains(&b.to_string()), "{error}");
+    assert_eq!(ys.resolve_revision(&b.to_string()[..12]).await.unwrap(), b);
+    // 缩写对应的不是快照时视为找不到
+    let blob = store.put(b"not a snapshot").await.unwrap();
+    let error = ys.resolve_revision(&blob.to_string()[..12]).await.unwrap_err();
+    assert!(matches!(error.kind(), YsErrorKind::UnknownRevision { .. }), "{error}");
+}
+
+#[tokio::test]
+async fn test_tags() {
+    let (temp, ys, mut store, first) = init_repo().await;
+    let dot = temp.path().join(".ys");
+    let file = store.put(b"release\n").await.unwrap();
+    let directory = SnapShotDirectory { root: BTreeMap::from([(String::from("a.txt"), DirectoryEntry::File(file))]) };
+    let snapshot = SnapShot {
+        directory: store.put_typed(&directory).await.unwrap(),
+        previous: BTreeSet::from([first]),
+        data: SnapShotData { kind: 0, message: String::new(), authors: Default::default(), datetime: None },
+        ignores: None,
+    };
+    let second = store.put_typed(&snapshot).await.unwrap();
+    second.write_branch(&dot, "main").unwrap();
+
+    // 轻量标签直接指向快照, 不能被意外移动
+    ys.write_tag("v0.1", first, false).unwrap();
+    assert!(ys.write_tag("v0.1", second, false).is_err());
+    assert_eq!(ys.resolve_revision("v0.1").await.unwrap(), first);
+    assert_eq!(ys.verify_tag("v0.1", None).await.unwrap(), None);
+    for invalid in ["", "HEAD", "a/b", "a..b", "v1~1", ".hidden", "v1.lock"] {
+        assert!(ys.write_tag(invalid, first, false).is_err(), "{invalid}");
+    }
+
+    let key = TagObject::parse_key(b"0123456789abcdef0123456789abcdef").unwrap();
+    let mut tag = TagObject {
+        target: second,
+        name: String::from("v1.0"),
+        tagger: AuthorID::from("someone <someone@example.com>"),
+        message: String::from("first release"),
+        datetime: Some(1),
+        mac: None,
+    };
+    // 名称不合法时不会写入标签对象
+    let invalid = TagObject { name: String::from("a..b"), ..tag.clone() };
+    assert!(ys.create_annotated_tag(&invalid, false).await.is_err());
+    assert!(!store.has(ObjectID::from(serde_json::to_vec(&invalid).unwrap().as_slice())).await.unwrap());
+    tag.authenticate(&key).unwrap();
+    let tag_id = ys.create_annotated_tag(&tag, false).await.unwrap();
+    assert!(ys.create_annotated_tag(&tag, false).await.is_err());
+    assert_eq!(store.get_typed::<TagObject>(tag_id).await.unwrap(), tag);
+    assert_eq!(ys.get_tag_id("v1.0").unwrap(), tag_id);
+    assert_eq!(ys.resolve_revision("v1.0~1").await.unwrap(), first);
+    // 其他进程留下的锁文件不是标签
+    std::fs::write(dot.join("tags").join("v9.lock"), b"").unwrap();
+    assert_eq!(ys.tag_names().unwrap(), ["v0.1", "v1.0"]);
+    assert_eq!(ys.verify_tag("v1.0", Some(&key)).await.unwrap(), Some(tag.clone()));
+    assert!(ys.verify_tag("v1.0", Some(&[0; 32])).await.is_err());
+    assert!(ys.verify_tag("v0.1", Some(&key)).await.is_err());
+    // 把标签对象挂到另一个名称下会被发现
+    ys.write_tag("v2.0", tag_id, false).unwrap();
+    assert!(ys.verify_tag("v2.0", None).await.is_err());
+
+    // 标签是垃圾回收的根, 即使世界线离开了被标记的快照, 清空引用历史以免它也保留这些快照
+    first.write_branch(&dot, "main").unwrap();
+    assert!(ys.expire_reflogs(u64::MAX).unwrap() > 0);
+    assert_eq!(ys.gc_roots().unwrap(), BTreeSet::from([first, tag_id]));
+    let reachable = ys.reachable_objects().await.unwrap();
+    assert!([tag_id, second, snapshot.directory, file].iter().all(|id| reachable.contains(id)));
+    assert_eq!(ys.delete_tag("v1.0").unwrap(), tag_id);
+    ys.delete_tag("v2.0").unwrap();
+    assert!(!ys.reachable_objects().await.unwrap().contains(&second));
+    assert!(ys.delete_tag("v1.0").is_err());
+}
+
+#[tokio::test]
+async fn test_branch_management() {
+    let (_temp, ys, mut store, root) = init_repo().await;
+    let feature = child_of(&mut store, root, "feature").await;
+
+    ys.create_branch_at("Feature-X", feature, false).unwrap();
+    ys.create_branch_at("release", root, false).unwrap();
+    assert!(ys.create_branch_at("release", feature, false).is_err());
+    assert!(ys.create_branch_at("bad name", root, false).is_err());
+    ys.create_branch_at("release", feature, true).unwrap();
+    // 当前所在的世界线不能被强制移动
+    let error = ys.create_branch_at("main", feature, true).unwrap_err();
+    assert!(matches!(error.kind(), YsErrorKind::CheckedOutBranch { .. }), "{error}");
+    ys.create_branch_at("release", root, true).unwrap();
+
+    let names = |branches: Vec<BranchInfo>| branches.into_iter().map(|branch| branch.name).collect::<Vec<_>>();
+    let all = ys.list_branches(&BranchFilter::default()).await.unwrap();
+    assert_eq!(
+        all.iter().map(|branch| (branch.name.as_str(), branch.current)).collect::<Vec<_>>(),
+        [("Feature-X", false), ("main", true), ("release", false)]
+    );
+    assert_eq!(all[0].tip, feature);
+    let filter = BranchFilter { pattern: Some(String::from("feature")), ..Default::default() };
+    assert!(ys.list_branches(&filter).await.unwrap().is_empty());
+    let filter = BranchFilter { ignore_case: true, ..filter };
+    assert_eq!(names(ys.list_branches(&filter).await.unwrap()), ["Feature-X"]);
+    let filter = BranchFilter { contains: Some(feature), ..Default::default() };
+    assert_eq!(names(ys.list_branches(&filter).await.unwrap()), ["Feature-X"]);
+    let filter = BranchFilter { without: Some(feature), ..Default::default() };
+    assert_eq!(names(ys.list_branches(&filter).await.unwrap()), ["main", "release"]);
+
+    // 未并入当前世界线的快照需要强制删除, 当前世界线不能删除
+    assert!(ys.delete_branch("Feature-X", false).await.is_err());
+    assert!(ys.delete_branch("main", true).await.is_err());
+    assert_eq!(ys.delete_branch("release", false).await.unwrap(), root);
+    assert!(ys.delete_branch("release", false).await.is_err());
+
+    ys.rename_branch("main", "trunk", false).unwrap();
+    assert_eq!(ys.get_branch_name().unwrap(), "trunk");
+    assert!(ys.rename_branch("trunk", "Feature-X", false).is_err());
+    ys.rename_branch("Feature-X", "feature", false).unwrap();
+    assert_eq!(ys.branch_names().unwrap(), ["feature", "trunk"]);
+    assert_eq!(ys.delete_branch("feature", true).await.unwrap(), feature);
+}
+
+#[tokio::test]
+async fn test_ref_transactions() {
+    let (temp, ys, ..) = init_repo().await;
+    let dot = temp.path().join(".ys");
+    let ids: Vec<ObjectID> = (0..4u8).map(|i| ObjectID::from([i].as_slice())).collect();
+    ids[0].write_branch(&dot, "main").unwrap();
+    ys.create_branch_at("other", ids[0], false).unwrap();
+    let lock_files = || {
+        std::fs::read_dir(dot.join("branches"))
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().ends_with(".lock"))
+    };
+
+    // 旧值不符时整个事务都不生效
+    let mut transaction = ys.transaction();
+    transaction.update("branches/main", ids[1], RefExpectation::Value(ids[0]));
+    transaction.update("branches/other", ids[1], RefExpectation::Value(ids[2]));
+    transaction.update("branches/new", ids[1], RefExpectation::Missing);
+    assert!(transaction.commit().is_err());
+    assert_eq!(ys.get_branch_id("main").unwrap(), ids[0]);
+    assert!(!ys.branch_exists("new").unwrap());
+    assert_eq!(lock_files().count(), 0);
+
+    let mut transaction = ys.transaction();
+    transaction.update("branches/main", ids[1], RefExpectation::Value(ids[0]));
+    transaction.delete("branches/other", RefExpectation::Value(ids[0]));
+    transaction.update("tags/v1", ids[2], RefExpectation::Missing);
+    transaction.commit().unwrap();
+    assert_eq!(ys.get_branch_id("main").unwrap(), ids[1]);
+    assert_eq!(ys.branch_names().unwrap(), ["main"]);
+    assert_eq!(ys.get_tag_id("v1").unwrap(), ids[2]);
+
+    let mut transaction = ys.transaction();
+    transaction.update("branches/main", ids[2], RefExpectation::Any).update("branches/main", ids[3], RefExpectation::Any);
+    assert!(transaction.commit().is_err());
+    let mut transaction = ys.transaction();
+    transaction.update("index", ids[2], RefExpectation::Any);
+    assert!(transaction.commit().is_err());
+
+    // 其他进程持有锁时直接失败, 也不会删除别人的锁文件
+    std::fs::write(dot.join("branches").join("main.lock"), "").unwrap();
+    assert!(ys.set_branch_snapshot_id("main", ids[3]).is_err());
+    assert_eq!(lock_files().count(), 1);
+    std::fs::remove_file(dot.join("branches").join("main.lock")).unwrap();
+
+    // 并发的比较并交换只有一个能成功
+    let old = ids[1];
+    let winners: Vec<bool> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..8u8)
+            .map(|i| {
+                let ys = &ys;
+                scope.spawn(move || {
+                    let mut transaction = ys.transaction();
+                    transaction.update("branches/main", ObjectID::from([100 + i].as_slice()), RefExpectation::Value(old));
+                    transaction.commit().is_ok()
+                })
+            })
+            .collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    });
+    let winner = winners.iter().position(|won| *won).unwrap();
+    assert_eq!(winners.iter().filter(|won| **won).count(), 1);
+    assert_eq!(ys.get_branch_id("main").unwrap(), ObjectID::from([100 + winner as u8].as_slice()));
+    assert_eq!(lock_files().count(), 0);
+
+    // 较短的新内容不会留下旧内容的尾巴
+    ys.set_branch("a-rather-long-branch-name").unwrap();
+    ys.set_branch("main").unwrap();
+    assert_eq!(ys.get_branch_name().unwrap(), "main");
+}
+
+#[tokio::test]
+async fn test_branch_file_format() {
+    let (temp, ys, mut store, root) = init_repo().await;
+    let dot = temp.path().join(".ys");
+    let read =
+        |path: &str| serde_json::from_str::<serde_json::Value>(&std::fs::read_to_string(dot.join(path)).unwrap()).unwrap();
+    let versioned = |id: ObjectID| serde_json::json!({ "version": 1, "target": id.to_string() });
+
+    // 初始化, 提交, 切换和创建世界线都写入同一种格式
+    assert_eq!(read("branches/main"), versioned(root));
+    let second = child_of(&mut store, root, "second").await;
+    let mut transaction = ys.transaction();
+    transaction.update("branches/main", second, RefExpectation::Value(root));
+    transaction.commit().unwrap();
+    assert_eq!(read("branches/main"), versioned(second));
+    assert_eq!(ys.calculate_branch_id().unwrap(), second);
+    assert_eq!(ys.create_branch("feature").unwrap(), second);
+    ys.set_branch("feature").unwrap();
+    assert_eq!(ys.calculate_branch_id().unwrap(), second);
+    assert_eq!(read("branches/feature"), versioned(second));
+    ys.create_branch_at("release", root, false).unwrap();
+    assert_eq!(ys.resolve_revision("release").await.unwrap(), root);
+    assert_eq!(read("branches/release"), versioned(root));
+
+    // 早期版本写入的三种格式都能读取, 读取本身不会修改引用
+    let legacy_branch = format!("{{\n    \"tree-id\": \"{}\"\n}}", root);
+    std::fs::write(dot.join("branches").join("tree-id"), &legacy_branch).unwrap();
+    std::fs::write(dot.join("branches").join("string"), format!("\"{}\"", root)).unwrap();
+    std::fs::write(dot.join("branches").join("raw"), format!("{}\n", root)).unwrap();
+    std::fs::create_dir_all(dot.join("tags")).unwrap();
+    std::fs::write(dot.join("tags").join("v0.1"), root.to_string()).unwrap();
+    for name in ["tree-id", "string", "raw"] {
+        assert_eq!(ys.get_branch_id(name).unwrap(), root);
+    }
+    assert_eq!(ys.get_tag_id("v0.1").unwrap(), root);
+    assert_eq!(std::fs::read_to_string(dot.join("branches").join("tree-id")).unwrap(), legacy_branch);
+    // 旧格式也能参与比较并交换
+    let mut transaction = ys.transaction();
+    transaction.update("branches/raw", second, RefExpectation::Value(root));
+    transaction.commit().unwrap();
+    assert_eq!(ys.get_branch_id("raw").unwrap(), second);
+    std::fs::write(dot.join("branches").join("raw"), format!("{}\n", root)).unwrap();
+
+    // 重新初始化时一次性改写为当前格式, 不留下引用历史
+    let reflog = ys.reflog("branches/raw").unwrap();
+    let config =
+        InitializeConfig { current: temp.path().to_path_buf(), initial_branch: "main".into(), ignores: IgnoreRules::default() };
+    let ys = config.generate().await.unwrap();
+    assert_eq!(ys.migrate_references().unwrap(), Vec::<String>::new());
+    for name in ["tree-id", "string", "raw"] {
+        assert_eq!(read(&format!("branches/{}", name)), versioned(root));
+    }
+    assert_eq!(read("tags/v0.1"), versioned(root));
+    assert_eq!(ys.reflog("branches/raw").unwrap(), reflog);
+    // 迁移后的世界线可以正常提交和切换
+    ys.set_branch("tree-id").unwrap();
+    assert_eq!(ys.calculate_branch_id().unwrap(), root);
+    let mut transaction = ys.transaction();
+    transaction.update("branches/tree-id", second, RefExpectation::Value(root));
+    transaction.commit().unwrap();
+    assert_eq!(read("branches/tree-id"), versioned(second));
+    ys.switch_working_tree(second, root).await.unwrap();
+    ys.set_branch("string").unwrap();
+    assert_eq!(ys.calculate_branch_id().unwrap(), root);
+    ys.set_branch("main").unwrap();
+
+    // 更新版本写入的引用和损坏的引用不会被当作其他快照
+    std::fs::write(dot.join("branches").join("future"), format!("{{\"version\": 2, \"target\": \"{}\"}}", root)).unwrap();
+    std::fs::write(dot.join("branches").join("broken"), "{\"target\": 42}").unwrap();
+    assert!(ys.get_branch_id("future").unwrap_err().to_string().contains("版本"));
+    assert!(ys.get_branch_id("broken").is_err());
+}
+
+#[tokio::test]
+async fn test_reflog() {
+    let (temp, ys, mut store, root) = init_repo().await;
+    let dot = temp.path().join(".ys");
+    let log = ys.reflog("branches/main").unwrap();
+    assert_eq!(log.len(), 1);
+    assert_eq!((log[0].old, log[0].new, log[0].operation.as_str()), (None, root, "init"));
+
+    let second = child_of(&mut store, root, "second").await;
+    let mut transaction = ys.transaction();
+    transaction.update("branches/main", second, RefExpectation::Value(root));
+    transaction.operation("commit: second").author(AuthorID::from("ys <ys@example.com>"));
+    transaction.commit().unwrap();
+    let log = ys.reflog("branches/main").unwrap();
+    assert_eq!(log[1].old, Some(root));
+    assert_eq!(log[1].author, AuthorID::from("ys <ys@example.com>"));
+
+    // `@{n}` 是引用在 n 次移动之前的值, 与前驱无关
+    let parsed: Revision = "main@{1}~0".parse().unwrap();
+    assert_eq!((parsed.entry, parsed.date), (Some(1), None));
+    assert_eq!(parsed.to_string(), "main@{1}~0");
+    root.write_branch(&dot, "main").unwrap();
+    assert_eq!(ys.resolve_revision("main@{0}").await.unwrap(), root);
+    assert_eq!(ys.resolve_revision("main@{1}").await.unwrap(), second);
+    assert_eq!(ys.resolve_revision("HEAD@{2}").await.unwrap(), root);
+    assert_eq!(ys.resolve_revision("@{1}^").await.unwrap(), root);
+    assert!(ys.resolve_revision("main@{3}").await.is_err());
+    assert!(ys.resolve_revision(&format!("{}@{{1}}", second)).await.is_err());
+
+    // 没有移动引用的修改不记录, 重命名会带走历史, 删除会清除历史
+    std::fs::write(dot.join("branches").join("main"), root.to_string()).unwrap();
+    assert_eq!(ys.get_branch_id("main").unwrap(), root);
+    assert_eq!(ys.reflog("branches/main").unwrap().len(), 3);
+    ys.create_branch_at("feature", second, false).unwrap();
+    ys.rename_branch("feature", "topic", false).unwrap();
+    let log = ys.reflog("branches/topic").unwrap();
+    assert_eq!(
+        log.iter().map(|entry| entry.operation.as_str()).collect::<Vec<_>>(),
+        ["branch: created", "branch: renamed feature to topic"]
+    );
+    assert!(ys.reflog("branches/feature").unwrap().is_empty());
+    assert_eq!(ys.resolve_revision("topic@{1}").await.unwrap(), second);
+    ys.write_tag("v1", second, false).unwrap();
+    assert_eq!(ys.reflog_names().unwrap(), ["branches/main", "branches/topic", "tags/v1"]);
+    ys.delete_branch("topic", true).await.unwrap();
+    ys.delete_tag("v1").unwrap();
+    assert_eq!(ys.reflog_names().unwrap(), ["branches/main"]);
+
+    // 被覆盖的快照在历史过期之前不会被回收
+    assert!(ys.gc_roots().unwrap().contains(&second));
+    // 追加和清理共用历史文件的锁, 锁被占用时清理失败且不改动历史
+    let lock = dot.join("logs").join("branches").join("main.lock");
+    assert!(!lock.exists());
+    std::fs::write(&lock, b"").unwrap();
+    let error = ys.expire_reflogs(u64::MAX).unwrap_err();
+    assert!(matches!(error.kind(), YsErrorKind::ReferenceLocked { .. }));
+    assert_eq!(ys.reflog("branches/main").unwrap().len(), 3);
+    std::fs::remove_file(&lock).unwrap();
+    assert_eq!(ys.expire_reflogs(u64::MAX).unwrap(), 3);
+    assert!(!ys.gc_roots().unwrap().contains(&second));
+}
+
+#[tokio::test]
+async fn test_operation_log() {
+    let (temp, ys, mut store, root) = init_repo().await;
+    let second = child_of(&mut store, root, "second").await;
+    let initial = ys.repository_state().unwrap();
+    assert_eq!(initial.head, Head::Branch("main".to_string()));
+    assert!(ys.undo().unwrap().is_none());
+
+    // 没有改变状态的命令不记录
+    let pending = ys.begin_operation().unwrap();
+    assert_eq!(pending.before(), &initial);
+    // 命令执行期间持有仓库锁, 撤销和其他命令都要等待它完成
+    let error = ys.begin_operation().unwrap_err();
+    assert!(matches!(error.kind(), YsErrorKind::ReferenceLocked { .. }));
+    assert!(ys.undo().is_err());
+    assert!(pending.record("status").unwrap().is_none());
+    let pending = ys.begin_operation().unwrap();
+    ys.set_branch_snapshot_id("main", second).unwrap();
+    let commit = pending.record("commit: second").unwrap().unwrap();
+    assert_eq!((commit.id, commit.kind), (0, OperationKind::Command));
+    // 没有记录就放弃的命令只释放锁
+    drop(ys.begin_operation().unwrap());
+    let pending = ys.begin_operation().unwrap();
+    ys.create_branch_at("side", root, false).unwrap();
+    ys.write_tag("v1", second, false).unwrap();
+    ys.set_branch("side").unwrap();
+    pending.record("experiment").unwrap();
+    let after = ys.repository_state().unwrap();
+    assert_eq!(ys.operation(1).unwrap().before.changes(&after).len(), 2);
+
+    // 连续撤销依次回到更早的状态, 重做按相反的顺序恢复
+    assert_eq!(ys.undo().unwrap().unwrap().description, "experiment");
+    assert_eq!(ys.repository_state().unwrap(), ys.operation(0).unwrap().after);
+    assert!(!ys.branch_exists("side").unwrap() && !ys.tag_exists("v1").unwrap());
+    assert_eq!(ys.undo().unwrap().unwrap().description, "commit: second");
+    assert_eq!(ys.repository_state().unwrap(), initial);
+    assert!(ys.undo().unwrap().is_none());
+    assert_eq!(ys.redo().unwrap().unwrap().id, 0);
+    assert_eq!(ys.redo().unwrap().unwrap().id, 1);
+    assert_eq!(ys.repository_state().unwrap(), after);
+    assert!(ys.redo().unwrap().is_none());
+    assert_eq!(ys.reflog("branches/main").unwrap().last().unwrap().operation, "redo: commit: second");
+
+    // 撤销之后执行了其他命令就不能再重做
+    ys.undo().unwrap();
+    let pending = ys.begin_operation().unwrap();
+    ys.create_branch_at("other", root, false).unwrap();
+    pending.record("branch: create other").unwrap();
+    assert!(ys.redo().unwrap().is_none());
+
+    // 恢复到任意操作之后的状态, 恢复本身也可以撤销
+    ys.restore_operation(1).unwrap();
+    assert_eq!(ys.repository_state().unwrap(), after);
+    assert!(matches!(ys.operations().unwrap().last().unwrap().kind, OperationKind::Restore(1)));
+    assert!(!ys.branch_exists("other").unwrap());
+    ys.undo().unwrap();
+    assert!(ys.branch_exists("other").unwrap() && !ys.branch_exists("side").unwrap());
+    assert!(ys.restore_operation(100).is_err());
+
+    // 截断日志之后序号仍然从最后一条记录继续, 不会重复
+    let last = ys.operations().unwrap().pop().unwrap();
+    std::fs::write(temp.path().join(".ys").join("operations"), format!("{}\n", serde_json::to_string(&last).unwrap())).unwrap();
+    let pending = ys.begin_operation().unwrap();
+    ys.create_branch_at("after-truncate", root, false).unwrap();
+    assert_eq!(pending.record("branch: create after-truncate").unwrap().unwrap().id, last.id + 1);
+}
+
+#[tokio::test]
+async fn test_detached_head() {
+    let (_temp, ys, mut store, root) = init_repo().await;
+    let detached = child_of(&mut store, root, "detached").await;
+
+    // 分离时没有当前世界线, 当前快照就是 `HEAD` 指向的快照
+    let pending = ys.begin_operation().unwrap();
+    ys.set_head(&Head::Detached { detached }).unwrap();
+    assert_eq!(ys.head().unwrap(), Head::Detached { detached });
+    assert_eq!(ys.calculate_branch_id().unwrap(), detached);
+    assert!(ys.get_branch_name().is_err());
+    assert_eq!(ys.resolve_revision("HEAD~1").await.unwrap(), root);
+    assert!(ys.resolve_revision("@{1}").await.is_err());
+    assert!(ys.list_branches(&BranchFilter::default()).await.unwrap().iter().all(|branch| !branch.current));
+    assert_eq!(ys.get_branch_id("main").unwrap(), root);
+
+    // 分离的快照不属于任何世界线, 但仍然是垃圾回收的根
+    assert!(ys.is_referenced(root).await.unwrap());
+    assert!(!ys.is_referenced(detached).await.unwrap());
+    assert!(ys.gc_roots().unwrap().contains(&detached));
+    ys.write_tag("keep", detached, false).unwrap();
+    assert!(ys.is_referenced(detached).await.unwrap());
+    ys.delete_tag("keep").unwrap();
+
+    // 操作日志记录分离的 `HEAD`, 撤销后回到原来的世界线
+    pending.record("checkout: detached").unwrap();
+    let entry = ys.operations().unwrap().pop().unwrap();
+    assert_eq!(entry.after.head.detached(), Some(detached));
+    ys.set_head(&Head::Branch("main".to_string())).unwrap();
+    assert!(ys.gc_roots().unwrap().contains(&detached));
+    ys.restore_operation(entry.id).unwrap();
+    assert_eq!(ys.head().unwrap(), Head::Detached { detached });
+    ys.undo().unwrap();
+    assert_eq!(ys.head().unwrap(), Head::Branch("main".to_string()));
+
+    // 旧版本的操作日志中 `HEAD` 是世界线的名称
+    let state: RepositoryState = serde_json::from_str(r#"{"head": "main", "branches": {}, "tags": {}}"#).unwrap();
+    assert_eq!(state.head.branch(), Some("main"));
+}
+
 #[tokio::test]
 #[ignore]
 async fn test_directory() {
@@ -82,10 +865,522 @@ async fn test_directory() {
     let mut store = MemoryObjectStore::new();
     let codebase = SnapShotDirectory::new(
         dir.as_path(),
-        &IgnoreRules { glob: vec![String::from(".git"), String::from(".ys"), String::from("target")].into_iter().collect() },
+        &IgnoreRules::parse(".git/\n.ys/\ntarget/\n"),
         &mut store,
+        &mut StatCache::default(),
     )
+    .await
     .unwrap();
     let readme_path = String::from("README.md");
     assert!(codebase.root.get(&readme_path).is_some());
 }
+
+#[tokio::test]
+async fn test_checkout_directory() {
+    let temp = tempfile::tempdir().unwrap();
+    let mut store = MemoryObjectStore::new();
+    let a = store.put(b"a").await.unwrap();
+    let b = store.put(b"b").await.unwrap();
+    let nested = SnapShotDirectory { root: BTreeMap::from([(String::from("inner"), DirectoryEntry::File(a))]) };
+    let this = SnapShotDirectory {
+        root: BTreeMap::from([
+            (String::from("keep"), DirectoryEntry::File(a)),
+            (String::from("change"), DirectoryEntry::File(a)),
+            (String::from("remove"), DirectoryEntry::Directory(Box::new(nested.clone()))),
+        ]),
+    };
+    let that = SnapShotDirectory {
+        root: BTreeMap::from([
+            (String::from("keep"), DirectoryEntry::File(a)),
+            (String::from("change"), DirectoryEntry::File(b)),
+            (String::from("create"), DirectoryEntry::Directory(Box::new(nested))),
+            (String::from("empty"), DirectoryEntry::Directory(Box::default())),
+        ]),
+    };
+    SnapShotDirectory::default().checkout(&this, &store, temp.path()).await.unwrap();
+    assert_eq!(std::fs::read(temp.path().join("remove").join("inner")).unwrap(), b"a");
+
+    this.checkout(&that, &store, temp.path()).await.unwrap();
+    assert_eq!(std::fs::read(temp.path().join("keep")).unwrap(), b"a");
+    assert_eq!(std::fs::read(temp.path().join("change")).unwrap(), b"b");
+    assert_eq!(std::fs::read(temp.path().join("create").join("inner")).unwrap(), b"a");
+    assert!(!temp.path().join("remove").exists());
+    assert!(temp.path().join("empty").is_dir());
+}
+
+#[tokio::test]
+async fn test_materialize_directory() {
+    let temp = tempfile::tempdir().unwrap();
+    let mut store = MemoryObjectStore::new();
+    let short = store.put(b"short").await.unwrap();
+    std::fs::write(temp.path().join("file"), b"a much longer file").unwrap();
+    std::fs::write(temp.path().join("stale"), b"stale").unwrap();
+    std::fs::create_dir(temp.path().join("replaced")).unwrap();
+    std::fs::write(temp.path().join("replaced").join("inner"), b"inner").unwrap();
+    std::fs::create_dir(temp.path().join(".ys")).unwrap();
+    let directory = SnapShotDirectory {
+        root: BTreeMap::from([
+            (String::from("file"), DirectoryEntry::File(short)),
+            (String::from("replaced"), DirectoryEntry::File(short)),
+            (
+                String::from("nested"),
+                DirectoryEntry::Directory(Box::new(SnapShotDirectory {
+                    root: BTreeMap::from([(String::from("inner"), DirectoryEntry::File(short))]),
+                })),
+            ),
+        ]),
+    };
+    let report = directory.materialize(&store, temp.path(), &IgnoreRules::default()).await.unwrap();
+    assert_eq!(std::fs::read(temp.path().join("file")).unwrap(), b"short");
+    assert_eq!(std::fs::read(temp.path().join("replaced")).unwrap(), b"short");
+    assert_eq!(std::fs::read(temp.path().join("nested").join("inner")).unwrap(), b"short");
+    assert!(!temp.path().join("stale").exists());
+    assert!(temp.path().join(".ys").exists());
+    assert!(report.actions.contains(&(temp.path().join("stale"), MaterializeAction::RemoveFile)));
+    assert!(report.actions.contains(&(temp.path().join("replaced"), MaterializeAction::RemoveDirectory)));
+    assert!(report.actions.contains(&(temp.path().join("nested"), MaterializeAction::CreateDirectory)));
+    assert!(report.actions.contains(&(temp.path().join("file"), MaterializeAction::WriteFile)));
+
+    let report = directory.materialize(&store, temp.path(), &IgnoreRules::default()).await.unwrap();
+    assert!(report.is_empty());
+}
+
+#[tokio::test]
+async fn test_reject_unsafe_entry_names() {
+    for name in ["", ".", "..", "../escape", "a/b", "a\\b", "/absolute", ".ys", ".YS"] {
+        assert!(SnapShotDirectory::check_name(name).is_err(), "{:?} should be rejected", name);
+    }
+    assert!(SnapShotDirectory::check_name(".ys.ignore").is_ok());
+
+    let id = ObjectID::from(&vec![]);
+    let json = format!(r#"{{"safe": {{"Directory": {{"..": {{"File": "{}"}}}}}}}}"#, id);
+    assert!(serde_json::from_str::<SnapShotDirectory>(&json).is_err());
+    let json = format!(r#"{{"safe": {{"Directory": {{"file": {{"File": "{}"}}}}}}}}"#, id);
+    assert!(serde_json::from_str::<SnapShotDirectory>(&json).is_ok());
+
+    let temp = tempfile::tempdir().unwrap();
+    let inside = temp.path().join("inside");
+    let mut store = MemoryObjectStore::new();
+    let id = store.put(b"escape").await.unwrap();
+    let evil = SnapShotDirectory { root: BTreeMap::from([(String::from("../escape"), DirectoryEntry::File(id))]) };
+    assert!(evil.materialize(&store, &inside, &IgnoreRules::default()).await.is_err());
+    assert!(SnapShotDirectory::default().checkout(&evil, &store, &inside).await.is_err());
+    assert!(!temp.path().join("escape").exists());
+}
+
+#[tokio::test]
+async fn test_working_status() {
+    let temp = tempfile::tempdir().unwrap();
+    let root = temp.path();
+    std::fs::create_dir_all(root.join("dir")).unwrap();
+    std::fs::write(root.join("keep"), b"keep").unwrap();
+    std::fs::write(root.join("change"), b"before").unwrap();
+    std::fs::write(root.join("dir").join("remove"), b"remove").unwrap();
+    let mut store = MemoryObjectStore::new();
+    let ignores = IgnoreRules::default();
+    let mut cache = StatCache::default();
+    let tracked = SnapShotDirectory::new(root, &ignores, &mut store, &mut cache).await.unwrap();
+    let mut index = StagingIndex::from_directory(&tracked);
+    assert!(WorkingStatus::new(&tracked, &index, root, &ignores, &mut cache).unwrap().is_clean());
+
+    std::fs::write(root.join("change"), b"after").unwrap();
+    std::fs::remove_file(root.join("dir").join("remove")).unwrap();
+    std::fs::create_dir_all(root.join("new")).unwrap();
+    std::fs::write(root.join("new").join("file"), b"new").unwrap();
+    std::fs::create_dir_all(root.join(".ys")).unwrap();
+    let status = WorkingStatus::new(&tracked, &index, root, &ignores, &mut cache).unwrap();
+    assert!(!status.is_clean());
+    assert!(status.added.is_empty() && status.modified.is_empty() && status.deleted.is_empty());
+    assert_eq!(status.untracked, BTreeSet::from([String::from("new/file")]));
+    assert_eq!(status.changed, BTreeSet::from([String::from("change")]));
+    assert_eq!(status.missing, BTreeSet::from([String::from("dir/remove")]));
+    assert_eq!(status.ignored, BTreeSet::from([String::from(".ys/")]));
+
+    // 暂存之后, 差异从工作目录转移到暂存区
+    index.stage("new/file", ObjectID::from(b"new".as_slice()));
+    index.stage("change", ObjectID::from(b"after".as_slice()));
+    index.unstage("dir/remove");
+    let status = WorkingStatus::new(&tracked, &index, root, &ignores, &mut cache).unwrap();
+    assert_eq!(status.added, BTreeSet::from([String::from("new/file")]));
+    assert_eq!(status.modified, BTreeSet::from([String::from("change")]));
+    assert_eq!(status.deleted, BTreeSet::from([String::from("dir/remove")]));
+    assert!(status.untracked.is_empty() && status.changed.is_empty() && status.missing.is_empty());
+}
+
+#[test]
+fn test_staging_index() {
+    let head = StagingIndex {
+        entries: BTreeMap::from([
+            (String::from("a/b"), ObjectID::from(b"b".as_slice())),
+            (String::from("a/c"), ObjectID::from(b"c".as_slice())),
+            (String::from("ab"), ObjectID::from(b"ab".as_slice())),
+        ]),
+    };
+    let directory = head.to_directory().unwrap();
+    assert_eq!(StagingIndex::from_directory(&directory), head);
+
+    // 暂存的文件会替换与之冲突的目录, 反之亦然
+    let mut index = head.clone();
+    index.stage("a", ObjectID::from(b"a".as_slice()));
+    assert_eq!(index.entries.keys().collect::<Vec<_>>(), ["a", "ab"]);
+    index.stage("a/d", ObjectID::from(b"d".as_slice()));
+    assert_eq!(index.entries.keys().collect::<Vec<_>>(), ["a/d", "ab"]);
+
+    // 移除目录不会影响同名前缀的文件
+    assert_eq!(index.unstage("a"), vec![String::from("a/d")]);
+    assert_eq!(index.entries.keys().collect::<Vec<_>>(), ["ab"]);
+
+    index.reset("a", &head);
+    assert_eq!(index, head);
+    index.reset("", &StagingIndex::default());
+    assert!(index.entries.is_empty());
+
+    let temp = tempfile::tempdir().unwrap();
+    let path = temp.path().join("index");
+    assert_eq!(StagingIndex::load(&path).unwrap(), None);
+    head.save(&path).unwrap();
+    assert_eq!(StagingIndex::load(&path).unwrap(), Some(head));
+}
+
+#[test]
+fn test_ignore_rules() {
+    let rules = IgnoreRules::parse(
+        "# comment\n\
+         \n\
+         *.log\n\
+         !keep.log\n\
+         build/\n\
+         /root-only\n\
+         doc/*.txt\n\
+         **/cache/**\n\
+         a/**/z\n\
+         file[0-9]?\n\
+         \\#hash\n",
+    );
+    assert_eq!(rules.rules().len(), 9);
+    // 文件名中包含规则文本的字符不再被忽略
+    assert!(!rules.is_ignored("a", false));
+    assert!(!rules.is_ignored("comment", false));
+    assert!(rules.is_ignored("debug.log", false));
+    assert!(rules.is_ignored("deep/nested/debug.log", false));
+    assert!(!rules.is_ignored("keep.log", false));
+    assert_eq!(rules.matched("keep.log", false).map(|rule| rule.line), Some(4));
+    assert!(rules.is_ignored("build", true));
+    assert!(rules.is_ignored("src/build", true));
+    assert!(!rules.is_ignored("build", false));
+    assert!(rules.is_ignored("root-only", false));
+    assert!(!rules.is_ignored("sub/root-only", false));
+    assert!(rules.is_ignored("doc/readme.txt", false));
+    assert!(!rules.is_ignored("doc/nested/readme.txt", false));
+    assert!(!rules.is_ignored("other/doc/readme.txt", false));
+    assert!(rules.is_ignored("cache/item", false));
+    assert!(rules.is_ignored("x/y/cache/item", false));
+    assert!(!rules.is_ignored("cache", true));
+    assert!(rules.is_ignored("a/z", false));
+    assert!(rules.is_ignored("a/b/c/z", false));
+    assert!(rules.is_ignored("file1x", false));
+    assert!(!rules.is_ignored("filex1", false));
+    assert!(rules.is_ignored("#hash", false));
+
+    // 连续的 `*` 不会让匹配的耗时随长度指数增长
+    let rules = IgnoreRules::parse("*a*a*a*a*a*a*a*a*a*a*a*a*b\n");
+    assert!(!rules.is_ignored(&"a".repeat(64), false));
+    assert!(rules.is_ignored(&format!("{}b", "a".repeat(64)), false));
+
+    // 多个 `**` 在很深的路径上同样不会指数增长
+    let rules = IgnoreRules::parse("a/**/b/**/c/**/**/d/**/e/**/f\n");
+    let deep = vec!["x"; 64].join("/");
+    assert!(!rules.is_ignored(&format!("a/{}/b/{}/c/{}", deep, deep, deep), false));
+    assert!(rules.is_ignored(&format!("a/{}/b/c/{}/d/e/{}/f", deep, deep, deep), false));
+    assert!(rules.is_ignored("a/b/c/d/e/f", false));
+    assert!(!rules.is_ignored("a/b/c/d/e", true));
+}
+
+#[test]
+fn test_default_ignore_rules() {
+    let rules = IgnoreRules::default();
+    assert_eq!(rules.source(), include_str!("../src/objects/ignore_rules/.ys.ignore"));
+    assert_eq!(rules.rules().iter().map(|rule| rule.source.as_str()).collect::<Vec<_>>(), [".idea/", ".git/", ".ys/"]);
+    assert!(rules.rules().iter().all(|rule| rule.directory_only && !rule.negated && !rule.anchored));
+    for directory in [".idea", ".git", ".ys", "nested/.git"] {
+        assert!(rules.is_ignored(directory, true), "{:?} should be ignored", directory);
+        assert!(!rules.is_ignored(directory, false), "file {:?} should not be ignored", directory);
+    }
+    assert!(!rules.is_ignored("src", true));
+}
+
+#[tokio::test]
+async fn test_ignore_rules_serde() {
+    let mut rules = IgnoreRules::default();
+    rules.extend("*.log\n!keep.log\n", "sub", Some(PathBuf::from("sub/.ys.ignore")));
+    let json = serde_json::to_string(&rules).unwrap();
+    let loaded: IgnoreRules = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded, rules);
+    assert!(loaded.is_ignored("sub/a.log", false));
+    assert!(!loaded.is_ignored("a.log", false));
+
+    let mut store = MemoryObjectStore::new();
+    let id = store.put_typed(&rules).await.unwrap();
+    assert_eq!(store.get_typed::<IgnoreRules>(id).await.unwrap(), rules);
+    assert!(serde_json::from_str::<IgnoreRules>(r##"{"source":"","rules":[{"source":"# comment","line":1}]}"##).is_err());
+}
+
+#[tokio::test]
+async fn test_snapshot_ignores() {
+    let (_temp, ys, mut store, _) = init_repo().await;
+    let rules = IgnoreRules::parse("*.log\n");
+    let snapshot = SnapShot {
+        directory: store.put_typed(&SnapShotDirectory::default()).await.unwrap(),
+        previous: Default::default(),
+        data: SnapShotData { kind: 0, message: String::new(), authors: Default::default(), datetime: None },
+        ignores: Some(store.put_typed(&rules).await.unwrap()),
+    };
+    let id = store.put_typed(&snapshot).await.unwrap();
+    let loaded: SnapShot = store.get_typed(id).await.unwrap();
+    assert_eq!(ys.snapshot_ignores(&loaded).await.unwrap(), rules);
+
+    // 旧版本的快照没有记录忽略规则
+    let legacy: SnapShot = serde_json::from_str(
+        r#"{"directory":"af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262","previous":[],"data":{"kind":0,"message":"","authors":[]}}"#,
+    )
+    .unwrap();
+    assert_eq!(legacy.ignores, None);
+    assert!(!serde_json::to_string(&legacy).unwrap().contains("ignores"));
+    assert_eq!(ys.snapshot_ignores(&legacy).await.unwrap(), IgnoreRules::default());
+
+    // 项目中已有的 `.ys.ignore` 在初始化时保留, 初始快照记录其中的规则
+    let temp = tempfile::tempdir().unwrap();
+    let text = "*.log\nbuild/\nnode_modules/\nsecret.env\n";
+    std::fs::write(temp.path().join(".ys.ignore"), text).unwrap();
+    let config =
+        InitializeConfig { current: temp.path().to_path_buf(), initial_branch: "main".into(), ignores: IgnoreRules::default() };
+    let ys = config.generate().await.unwrap();
+    assert_eq!(std::fs::read_to_string(temp.path().join(".ys.ignore")).unwrap(), text);
+    let initial: SnapShot = ys.store().unwrap().get_typed(ys.get_branch_id("main").unwrap()).await.unwrap();
+    assert_eq!(ys.snapshot_ignores(&initial).await.unwrap(), IgnoreRules::parse(text));
+}
+
+#[tokio::test]
+async fn test_nested_ignore_files() {
+    let (temp, ys, ..) = init_repo().await;
+    let root = temp.path();
+    std::fs::write(root.join(".ys.ignore"), "*.log\nbuild/\n").unwrap();
+    std::fs::write(root.join(".ys").join("exclude"), "*.tmp\n").unwrap();
+    std::fs::create_dir_all(root.join("sub").join("build")).unwrap();
+    std::fs::write(root.join("sub").join(".ys.ignore"), "!keep.log\n").unwrap();
+    for file in ["a.log", "a.tmp", "sub/keep.log", "sub/drop.log", "sub/build/out"] {
+        std::fs::write(root.join(file), file).unwrap();
+    }
+
+    let directory =
+        SnapShotDirectory::new(root, &ys.ignores().unwrap(), &mut MemoryObjectStore::new(), &mut StatCache::default())
+            .await
+            .unwrap();
+    let files = StagingIndex::from_directory(&directory);
+    assert_eq!(files.entries.keys().collect::<Vec<_>>(), [".ys.ignore", "sub/.ys.ignore", "sub/keep.log"]);
+
+    let rule = ys.check_ignore(&root.join("sub").join("drop.log")).unwrap().unwrap();
+    assert_eq!((rule.origin, rule.line, rule.source.as_str()), (Some(root.join(".ys.ignore")), 1, "*.log"));
+    let rule = ys.check_ignore(&root.join("sub").join("keep.log")).unwrap().unwrap();
+    assert!(rule.negated);
+    assert_eq!(rule.origin, Some(root.join("sub").join(".ys.ignore")));
+    let rule = ys.check_ignore(&root.join("sub").join("build").join("out")).unwrap().unwrap();
+    assert_eq!(rule.source, "build/");
+    let rule = ys.check_ignore(&root.join("a.tmp")).unwrap().unwrap();
+    assert_eq!(rule.origin, Some(root.join(".ys").join("exclude")));
+    assert_eq!(ys.check_ignore(&root.join("sub")).unwrap(), None);
+}
+
+#[test]
+fn test_path_attributes() {
+    let attributes = PathAttributes::parse(
+        "# comment\n\
+         * text=auto\n\
+         *.png binary lockable\n\
+         *.psd -text merge=ours chunk=fastcdc\n\
+         docs/** eol=crlf\n\
+         docs/raw.txt !eol -diff\n",
+    );
+    assert_eq!(attributes.rules().len(), 5);
+    let png = attributes.resolve("assets/logo.png");
+    assert!(png.is_binary());
+    assert_eq!(png.get("text"), Some(&AttributeValue::Unset));
+    assert_eq!(png.get("merge"), Some(&AttributeValue::Unset));
+    let psd = attributes.resolve("art.psd");
+    assert!(!psd.is_binary());
+    assert_eq!(psd.get("chunk"), Some(&AttributeValue::Value("fastcdc".into())));
+    let guide = attributes.resolve("docs/guide.md");
+    assert_eq!(guide.end_of_line(), Some("crlf"));
+    assert_eq!(guide.to_working(b"a\nb\r\n".to_vec()), b"a\r\nb\r\n");
+    let raw = attributes.resolve("docs/raw.txt");
+    assert_eq!(raw.end_of_line(), None);
+    assert!(raw.is_binary());
+    assert_eq!(attributes.resolve("src/main.rs").get("text"), Some(&AttributeValue::Value("auto".into())));
+    assert_eq!(attributes.resolve("src/main.rs").to_stored(b"a\r\nb\r\n".to_vec()), b"a\nb\n");
+    assert_eq!(attributes.resolve("data.bin").to_stored(b"a\0\r\n".to_vec()), b"a\0\r\n");
+    assert_eq!(png.to_stored(b"a\r\n".to_vec()), b"a\r\n");
+    assert_eq!(png.to_working(b"a\n".to_vec()), b"a\n");
+}
+
+#[tokio::test]
+async fn test_attributes_in_tree_building() {
+    let temp = tempfile::tempdir().unwrap();
+    let root = temp.path();
+    std::fs::create_dir_all(root.join("raw")).unwrap();
+    std::fs::write(root.join(".ys.attributes"), "*.txt text\n").unwrap();
+    std::fs::write(root.join("raw").join(".ys.attributes"), "*.txt -text\n").unwrap();
+    std::fs::write(root.join("a.txt"), b"line\r\n").unwrap();
+    std::fs::write(root.join("raw").join("a.txt"), b"line\r\n").unwrap();
+    let mut store = MemoryObjectStore::new();
+    let ignores = IgnoreRules::default();
+    let mut cache = StatCache::default();
+    let directory = SnapShotDirectory::new(root, &ignores, &mut store, &mut cache).await.unwrap();
+    let index = StagingIndex::from_directory(&directory);
+    assert_eq!(index.entries["a.txt"], ObjectID::from(b"line\n".as_slice()));
+    assert_eq!(index.entries["raw/a.txt"], ObjectID::from(b"line\r\n".as_slice()));
+    // 工作目录的状态使用同样的转换规则, 刚提交的文件不会被视为修改
+    let status = WorkingStatus::new(&directory, &index, root, &ignores, &mut StatCache::default()).unwrap();
+    assert!(status.is_clean(), "{:?}", status);
+}
+
+#[tokio::test]
+async fn test_attributes_in_materialize() {
+    let source = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(source.path().join("docs")).unwrap();
+    std::fs::write(source.path().join(".ys.attributes"), "*.txt eol=crlf\n").unwrap();
+    std::fs::write(source.path().join("docs").join("a.txt"), b"one\ntwo\n").unwrap();
+    std::fs::write(source.path().join("b.md"), b"one\n").unwrap();
+    let mut store = MemoryObjectStore::new();
+    let ignores = IgnoreRules::default();
+    let directory =
+        SnapShotDirectory::new(source.path(), &ignores, &mut store, &mut StatCache::default()).await.unwrap();
+
+    // 写出时按属性转换为 CRLF, 重新扫描得到同样的目录树
+    let target = tempfile::tempdir().unwrap();
+    directory.materialize(&store, target.path(), &ignores).await.unwrap();
+    assert_eq!(std::fs::read(target.path().join("docs").join("a.txt")).unwrap(), b"one\r\ntwo\r\n");
+    assert_eq!(std::fs::read(target.path().join("b.md")).unwrap(), b"one\n");
+    let rescanned =
+        SnapShotDirectory::new(target.path(), &ignores, &mut store, &mut StatCache::default()).await.unwrap();
+    assert_eq!(rescanned, directory);
+    let report = directory.materialize(&store, target.path(), &ignores).await.unwrap();
+    assert!(report.is_empty(), "{:?}", report);
+
+    // 切换时同样转换新写入的文件
+    std::fs::write(source.path().join("docs").join("c.txt"), b"three\n").unwrap();
+    let next = SnapShotDirectory::new(source.path(), &ignores, &mut store, &mut StatCache::default()).await.unwrap();
+    directory.checkout(&next, &store, target.path()).await.unwrap();
+    assert_eq!(std::fs::read(target.path().join("docs").join("c.txt")).unwrap(), b"three\r\n");
+}
+
+#[test]
+fn test_stat_cache() {
+    let temp = tempfile::tempdir().unwrap();
+    let file = temp.path().join("file");
+    let cache_path = temp.path().join("stat-cache");
+    let past = SystemTime::now() - Duration::from_secs(60);
+    std::fs::write(&file, b"before").unwrap();
+    File::options().write(true).open(&file).unwrap().set_modified(past).unwrap();
+
+    let mut cache = StatCache::default();
+    let before = cache.hash_file("file", &file).unwrap();
+    assert_eq!(before, ObjectID::from(b"before".as_slice()));
+    cache.save(&cache_path).unwrap();
+
+    // 大小和修改时间都没有变化时, 直接使用缓存中的 id
+    std::fs::write(&file, b"after!").unwrap();
+    File::options().write(true).open(&file).unwrap().set_modified(past).unwrap();
+    let mut cache = StatCache::load(&cache_path);
+    assert_eq!(cache.hash_file("file", &file).unwrap(), before);
+
+    // 元数据变化后重新哈希
+    std::fs::write(&file, b"changed").unwrap();
+    assert_eq!(cache.hash_file("file", &file).unwrap(), ObjectID::from(b"changed".as_slice()));
+
+    // 与缓存写入处于同一时间粒度内的条目不会被信任
+    cache.save(&cache_path).unwrap();
+    let cache = StatCache::load(&cache_path);
+    assert_eq!(cache.lookup("file", &file.metadata().unwrap()), None);
+}
+
+#[test]
+fn test_discover_repository() {
+    let temp = tempfile::tempdir().unwrap();
+    let root = temp.path().join("project");
+    let nested = root.join("src").join("deep");
+    std::fs::create_dir_all(root.join(".ys")).unwrap();
+    std::fs::create_dir_all(&nested).unwrap();
+
+    let ys = DotYuanShenClient::discover(&nested, false).unwrap();
+    assert_eq!(ys.working_directory(), root);
+    assert_eq!(ys.relative_path(&nested.join("..").join("main.rs")).unwrap(), "src/main.rs");
+    assert!(DotYuanShenClient::discover(temp.path(), false).is_err());
+
+    // 明确给出的仓库不再向上查找, 可以是工作目录或其中的 `.ys` 文件夹
+    let ys = DotYuanShenClient::from_env(temp.path(), Some(Path::new("project/.ys"))).unwrap();
+    assert_eq!(ys.working_directory(), root.canonicalize().unwrap());
+    assert!(DotYuanShenClient::from_env(&root, Some(&nested)).is_err());
+    assert_eq!(requested_work_tree(temp.path(), Some(Path::new("new/.ys"))), Some(temp.path().join("new")));
+}
+
+#[test]
+fn test_layered_config() {
+    let temp = tempfile::tempdir().unwrap();
+    let user = temp.path().join("user.toml");
+    let repository = temp.path().join("repository").join(CONFIG_FILE);
+    let text = "[user]\nname = \"someone\"\nemail = \"someone@example.com\"\n[init]\ndefault-branch = \"main\"\n";
+    std::fs::write(&user, text).unwrap();
+    set_config_value(&repository, "user.name", "other").unwrap();
+    set_config_value(&repository, "core.compression-level", "3").unwrap();
+    set_config_value(&repository, "core.ignore-files", "extra.ignore").unwrap();
+    assert!(set_config_value(&repository, "core.compression-level", "10").is_err());
+    assert!(set_config_value(&repository, "user.nmae", "typo").is_err());
+
+    let mut config = LayeredConfig::default();
+    config.add_file(ConfigLayer::User, &user).unwrap();
+    config.add_file(ConfigLayer::Repository, &repository).unwrap();
+    config.add_environment(|name| (name == "YS_USER_EMAIL").then(|| "env@example.com".to_string())).unwrap();
+    let resolved = config.resolve().unwrap();
+    assert_eq!(resolved.default_branch(), "main");
+    assert_eq!(resolved.user.name.as_deref(), Some("other"));
+    assert_eq!(resolved.user.email.as_deref(), Some("env@example.com"));
+    assert_eq!(resolved.core.compression_level, Some(3));
+    assert_eq!(config.get("core.compression-level").unwrap().1.as_integer(), Some(3));
+    assert_eq!(resolved.core.ignore_files, vec![PathBuf::from("extra.ignore")]);
+    assert_eq!(resolved.user.author_id(), Some(AuthorID::from("other <env@example.com>")));
+    assert_eq!(config.get("user.name").map(|(layer, _)| layer), Some(ConfigLayer::Repository));
+    assert_eq!(config.entries().len(), 5);
+    assert_eq!(config.clone().only(ConfigLayer::User).get("user.name").unwrap().1.as_str(), Some("someone"));
+
+    assert!(unset_config_value(&repository, "user.name").unwrap());
+    assert!(!unset_config_value(&repository, "user.name").unwrap());
+    let mut config = LayeredConfig::default();
+    config.add_file(ConfigLayer::User, &user).unwrap();
+    config.add_file(ConfigLayer::Repository, &repository).unwrap();
+    assert_eq!(config.resolve().unwrap().user.name.as_deref(), Some("someone"));
+
+    // 环境变量覆盖配置文件中的压缩级别, 同样检查取值范围
+    config.add_environment(|name| (name == "YS_COMPRESSION_LEVEL").then(|| "7".to_string())).unwrap();
+    assert_eq!(config.resolve().unwrap().core.compression_level, Some(7));
+    let mut invalid = LayeredConfig::default();
+    assert!(invalid.add_environment(|name| (name == "YS_COMPRESSION_LEVEL").then(|| "12".to_string())).is_err());
+
+    // 个人身份默认不写入随项目提交的仓库配置
+    assert_eq!(ConfigLayer::default_for("user.email"), ConfigLayer::User);
+    assert_eq!(ConfigLayer::default_for("init.default-branch"), ConfigLayer::Repository);
+}
+
+#[tokio::test]
+async fn test_remove_keeps_unstaged_edits() {
+    let (temp, ys, ..) = init_repo().await;
+    let file = temp.path().join("a.txt");
+    std::fs::write(&file, "staged\n").unwrap();
+    ys.add(&[file.clone()]).await.unwrap();
+    std::fs::write(&file, "edited\n").unwrap();
+
+    assert!(ys.remove(&[file.clone()], false, false).await.is_err());
+    assert!(file.exists());
+    assert!(ys.read_index().unwrap().unwrap().entries.contains_key("a.txt"));
+    assert_eq!(ys.remove(&[file.clone()], false, true).await.unwrap(), ["a.txt"]);
+    assert!(!file.exists());
+}